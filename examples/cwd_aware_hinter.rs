@@ -21,6 +21,7 @@ fn create_item(cwd: &str, cmd: &str, exit_status: i64) -> reedline::HistoryItem
         cwd: Some(cwd.to_string()),
         duration: Some(Duration::from_millis(1000)),
         exit_status: Some(exit_status),
+        tags: vec![],
         more_info: None,
     }
 }
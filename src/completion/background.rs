@@ -0,0 +1,319 @@
+use crate::{Completer, Result, Span, Suggestion};
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    time::Duration,
+};
+
+/// Default time the typing has to pause before the wrapped completer runs
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// The line and cursor position of a completion
+type Query = (String, usize);
+
+/// A [`Completer`] running a slow completer on a worker thread, so the prompt stays responsive
+///
+/// The wrapped completer only runs once the typing pauses for the debounce time, see
+/// [`BackgroundCompleter::with_debounce()`], and only for the most recent line: queries
+/// typed over in the meantime are dropped, and the results of a query that is outdated when
+/// it finishes are ignored. A completion that already runs isn't interrupted.
+///
+/// Until the results of the current line arrive, the last results are served, with their
+/// spans ending at the cursor, as long as the line before their spans didn't change. The
+/// engine picks up the results through [`Completer::check_pending()`] and updates the open
+/// menu. If the wrapped completer panics, nothing is pending anymore and the last results stay.
+///
+/// The wrapped completer is called with [`Completer::complete()`], as the
+/// [`CompletionContext`](crate::CompletionContext) can't be sent to the worker.
+///
+/// ```rust
+/// use reedline::{BackgroundCompleter, DefaultCompleter, Reedline};
+/// use std::time::Duration;
+///
+/// let slow = DefaultCompleter::new(vec!["cargo".into(), "cat".into()]);
+/// let completer = BackgroundCompleter::new(Box::new(slow))
+///     .unwrap()
+///     .with_debounce(Duration::from_millis(100));
+/// let line_editor = Reedline::create().with_completer(Box::new(completer));
+/// ```
+pub struct BackgroundCompleter {
+    queries: Sender<Query>,
+    results: Receiver<(Query, Vec<Suggestion>)>,
+    debounce: Sender<Duration>,
+    // the most recent query, `None` once its results arrived
+    pending: Option<Query>,
+    // the query of the last results, with them
+    last: Option<(Query, Vec<Suggestion>)>,
+}
+
+impl BackgroundCompleter {
+    /// Moves the completer to a new worker thread
+    pub fn new(completer: Box<dyn Completer + Send>) -> Result<Self> {
+        let (queries, query_receiver) = mpsc::channel();
+        let (result_sender, results) = mpsc::channel();
+        let (debounce, debounce_receiver) = mpsc::channel();
+        // the worker stops when the completer is dropped
+        std::thread::Builder::new()
+            .name("reedline-completer".to_string())
+            .spawn(move || {
+                complete_queries(completer, query_receiver, result_sender, debounce_receiver);
+            })?;
+        Ok(BackgroundCompleter {
+            queries,
+            results,
+            debounce,
+            pending: None,
+            last: None,
+        })
+    }
+
+    /// A builder that sets the time the typing has to pause before the wrapped completer runs,
+    /// 50 ms by default
+    #[must_use]
+    pub fn with_debounce(self, debounce: Duration) -> Self {
+        // the worker only hangs up if the wrapped completer panicked
+        let _ = self.debounce.send(debounce);
+        self
+    }
+}
+
+/// The loop of the worker thread, until the [`BackgroundCompleter`] is dropped
+fn complete_queries(
+    mut completer: Box<dyn Completer + Send>,
+    queries: Receiver<Query>,
+    results: Sender<(Query, Vec<Suggestion>)>,
+    debounce: Receiver<Duration>,
+) {
+    let mut wait = DEFAULT_DEBOUNCE;
+    while let Ok(mut query) = queries.recv() {
+        if let Some(latest) = debounce.try_iter().last() {
+            wait = latest;
+        }
+        // only the query after the last keystroke runs
+        loop {
+            match queries.recv_timeout(wait) {
+                Ok(newer) => query = newer,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        let suggestions = completer.complete(&query.0, query.1);
+        if results.send((query, suggestions)).is_err() {
+            return;
+        }
+    }
+}
+
+impl Completer for BackgroundCompleter {
+    /// Returns the results of the line if they arrived, otherwise queues it and returns the
+    /// last results
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.check_pending();
+        match &self.last {
+            Some(((last_line, last_pos), suggestions)) if last_line == line && *last_pos == pos => {
+                return suggestions.clone();
+            }
+            _ => {}
+        }
+        let query = (line.to_string(), pos);
+        if self.pending.as_ref() != Some(&query) {
+            // the worker only hangs up if the wrapped completer panicked, then nothing is pending
+            self.pending = self.queries.send(query.clone()).ok().map(|()| query);
+        }
+        let Some(((last_line, _), suggestions)) = &self.last else {
+            return vec![];
+        };
+        suggestions
+            .iter()
+            // the text before the span has to be the same for it to still apply
+            .filter(|suggestion| {
+                let start = suggestion.span.start;
+                start <= pos
+                    && line
+                        .get(..start)
+                        .map_or(false, |before| last_line.get(..start) == Some(before))
+            })
+            .map(|suggestion| Suggestion {
+                span: Span::new(suggestion.span.start, pos),
+                ..suggestion.clone()
+            })
+            .collect()
+    }
+
+    fn has_pending(&mut self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Stores the results of the most recent line, ignoring those of outdated lines
+    fn check_pending(&mut self) -> bool {
+        let mut updated = false;
+        loop {
+            match self.results.try_recv() {
+                Ok((query, suggestions)) => {
+                    if self.pending.as_ref() == Some(&query) {
+                        self.pending = None;
+                        self.last = Some((query, suggestions));
+                        updated = true;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                // the wrapped completer panicked, no results will arrive
+                Err(TryRecvError::Disconnected) => {
+                    self.pending = None;
+                    break;
+                }
+            }
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    /// Suggests the line with a `!`, recording the lines it completed,
+    /// and waits while the test holds the `gate`
+    #[derive(Clone, Default)]
+    struct RecordingCompleter {
+        lines: Arc<Mutex<Vec<String>>>,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Completer for RecordingCompleter {
+        fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+            self.lines.lock().unwrap().push(line.to_string());
+            drop(self.gate.lock().unwrap());
+            vec![Suggestion {
+                value: format!("{line}!"),
+                span: Span::new(0, pos),
+                ..Default::default()
+            }]
+        }
+    }
+
+    fn values(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|s| s.value).collect()
+    }
+
+    fn wait_for_results(completer: &mut BackgroundCompleter) {
+        let start = Instant::now();
+        while !completer.check_pending() {
+            assert!(start.elapsed() < Duration::from_secs(10), "no results");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn serves_the_last_results_until_the_new_ones_arrive() -> Result<()> {
+        let mut completer = BackgroundCompleter::new(Box::<RecordingCompleter>::default())?
+            .with_debounce(Duration::ZERO);
+        assert!(completer.complete("ca", 2).is_empty());
+        assert!(completer.has_pending());
+        wait_for_results(&mut completer);
+        assert!(!completer.has_pending());
+        assert_eq!(values(completer.complete("ca", 2)), vec!["ca!"]);
+
+        let stale = completer.complete("car", 3);
+        assert_eq!(values(stale.clone()), vec!["ca!"]);
+        assert_eq!(stale[0].span, Span::new(0, 3));
+        assert!(completer.has_pending());
+        wait_for_results(&mut completer);
+        assert_eq!(values(completer.complete("car", 3)), vec!["car!"]);
+        Ok(())
+    }
+
+    #[test]
+    fn only_completes_the_line_after_the_last_keystroke() -> Result<()> {
+        let inner = RecordingCompleter::default();
+        let mut completer = BackgroundCompleter::new(Box::new(inner.clone()))?
+            .with_debounce(Duration::from_millis(200));
+        for line in ["c", "ca", "car"] {
+            completer.complete(line, line.len());
+        }
+        wait_for_results(&mut completer);
+        assert_eq!(values(completer.complete("car", 3)), vec!["car!"]);
+        assert_eq!(*inner.lines.lock().unwrap(), vec!["car"]);
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_the_results_of_outdated_lines() -> Result<()> {
+        let inner = RecordingCompleter::default();
+        let mut completer =
+            BackgroundCompleter::new(Box::new(inner.clone()))?.with_debounce(Duration::ZERO);
+        let gate = inner.gate.lock().unwrap();
+        completer.complete("ca", 2);
+        let start = Instant::now();
+        while inner.lines.lock().unwrap().is_empty() {
+            assert!(start.elapsed() < Duration::from_secs(10), "not started");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // typed on while "ca" is completed
+        assert!(completer.complete("cat", 3).is_empty());
+        drop(gate);
+
+        wait_for_results(&mut completer);
+        assert_eq!(values(completer.complete("cat", 3)), vec!["cat!"]);
+        assert_eq!(*inner.lines.lock().unwrap(), vec!["ca", "cat"]);
+        Ok(())
+    }
+
+    /// Suggests the last word with a `!`
+    struct WordCompleter;
+
+    impl Completer for WordCompleter {
+        fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+            let start = line[..pos].rfind(' ').map_or(0, |space| space + 1);
+            vec![Suggestion {
+                value: format!("{}!", &line[start..pos]),
+                span: Span::new(start, pos),
+                ..Default::default()
+            }]
+        }
+    }
+
+    #[test]
+    fn only_serves_the_last_results_for_the_same_text_before_their_span() -> Result<()> {
+        let mut completer =
+            BackgroundCompleter::new(Box::new(WordCompleter))?.with_debounce(Duration::ZERO);
+        completer.complete("git ch", 6);
+        wait_for_results(&mut completer);
+
+        let stale = completer.complete("git che", 7);
+        assert_eq!(values(stale.clone()), vec!["ch!"]);
+        assert_eq!(stale[0].span, Span::new(4, 7));
+        assert!(completer.complete("cargo ch", 8).is_empty());
+        Ok(())
+    }
+
+    /// Panics on every completion
+    struct PanickingCompleter;
+
+    impl Completer for PanickingCompleter {
+        fn complete(&mut self, _line: &str, _pos: usize) -> Vec<Suggestion> {
+            panic!("completion failed");
+        }
+    }
+
+    #[test]
+    fn nothing_is_pending_after_the_wrapped_completer_panicked() -> Result<()> {
+        let mut completer =
+            BackgroundCompleter::new(Box::new(PanickingCompleter))?.with_debounce(Duration::ZERO);
+        assert!(completer.complete("ca", 2).is_empty());
+        let start = Instant::now();
+        while completer.has_pending() {
+            assert!(start.elapsed() < Duration::from_secs(10), "still pending");
+            assert!(!completer.check_pending());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert!(completer.complete("cat", 3).is_empty());
+        assert!(!completer.has_pending());
+        Ok(())
+    }
+}
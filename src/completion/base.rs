@@ -1,3 +1,4 @@
+use super::CompletionContext;
 use nu_ansi_term::Style;
 use std::ops::Range;
 
@@ -34,6 +35,20 @@ pub trait Completer {
     /// span to replace and the contents of that replacement
     fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion>;
 
+    /// same as [`Completer::complete`], with what the engine knows about the line: the working
+    /// directory, the edit mode, the menu asking and the history
+    ///
+    /// The engine's menus get their completions through this. The default implementation
+    /// ignores the context and calls [`Completer::complete`].
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        _context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.complete(line, pos)
+    }
+
     /// same as [`Completer::complete`] but it will return a vector of ranges of the strings
     /// the suggestions are based on
     fn complete_with_base_ranges(
@@ -41,13 +56,7 @@ pub trait Completer {
         line: &str,
         pos: usize,
     ) -> (Vec<Suggestion>, Vec<Range<usize>>) {
-        let mut ranges = vec![];
-        let suggestions = self.complete(line, pos);
-        for suggestion in &suggestions {
-            ranges.push(suggestion.span.start..suggestion.span.end);
-        }
-        ranges.dedup();
-        (suggestions, ranges)
+        base_ranges(self.complete(line, pos))
     }
 
     /// action that will return a partial section of available completions
@@ -72,6 +81,52 @@ pub trait Completer {
         self.complete(line, pos).len()
     }
 
+    /// same as [`Completer::complete_with_base_ranges`], with the context of
+    /// [`Completer::complete_with_context`]
+    ///
+    /// The engine calls this for the menus showing base ranges. The default implementation
+    /// builds on [`Completer::complete_with_context`].
+    fn complete_with_base_ranges_and_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> (Vec<Suggestion>, Vec<Range<usize>>) {
+        base_ranges(self.complete_with_context(line, pos, context))
+    }
+
+    /// same as [`Completer::partial_complete`], with the context of
+    /// [`Completer::complete_with_context`]
+    ///
+    /// The default implementation builds on [`Completer::complete_with_context`].
+    fn partial_complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        start: usize,
+        offset: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.complete_with_context(line, pos, context)
+            .into_iter()
+            .skip(start)
+            .take(offset)
+            .collect()
+    }
+
+    /// same as [`Completer::total_completions`], with the context of
+    /// [`Completer::complete_with_context`]
+    ///
+    /// The default implementation builds on [`Completer::complete_with_context`].
+    fn total_completions_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> usize {
+        self.complete_with_context(line, pos, context).len()
+    }
+
     /// Returns `true` while completions are being computed in the background.
     ///
     /// When this returns `true` the engine switches to polling mode so it can
@@ -94,6 +149,17 @@ pub trait Completer {
     }
 }
 
+/// The suggestions with the ranges of the strings they are based on, as returned by
+/// [`Completer::complete_with_base_ranges`]
+fn base_ranges(suggestions: Vec<Suggestion>) -> (Vec<Suggestion>, Vec<Range<usize>>) {
+    let mut ranges = vec![];
+    for suggestion in &suggestions {
+        ranges.push(suggestion.span.start..suggestion.span.end);
+    }
+    ranges.dedup();
+    (suggestions, ranges)
+}
+
 /// Suggestion returned by the Completer
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Suggestion {
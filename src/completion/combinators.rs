@@ -0,0 +1,521 @@
+//! Completers combining the suggestions of other completers
+//!
+//! All of them pass the [`CompletionContext`] on to the inner completers, also for the paging
+//! and the base ranges of the menus, and forward [`Completer::has_pending()`] and
+//! [`Completer::check_pending()`] to every inner completer, so background completions keep
+//! updating the menu.
+
+use crate::{Completer, CompletionContext, Suggestion};
+use std::{collections::HashSet, ops::Range};
+
+/// Whether any of the completers computes completions in the background
+fn any_pending<'a>(completers: impl Iterator<Item = &'a mut Box<dyn Completer + Send>>) -> bool {
+    // no short circuit, so the state of every completer is queried
+    completers.fold(false, |pending, completer| {
+        completer.has_pending() | pending
+    })
+}
+
+/// Whether any of the completers finished a background completion
+fn any_finished<'a>(completers: impl Iterator<Item = &'a mut Box<dyn Completer + Send>>) -> bool {
+    // no short circuit, so every finished completion is stored
+    completers.fold(false, |finished, completer| {
+        completer.check_pending() | finished
+    })
+}
+
+/// Lists the suggestions of all completers in order, leaving out values already suggested
+///
+/// ```rust
+/// use reedline::{DefaultCompleter, FilePathCompleter, MergedCompleter, Reedline};
+///
+/// let commands = DefaultCompleter::new(vec!["cargo".into(), "cat".into()]);
+/// let completer = MergedCompleter::new(vec![
+///     Box::new(commands),
+///     Box::new(FilePathCompleter::default()),
+/// ]);
+/// let line_editor = Reedline::create().with_completer(Box::new(completer));
+/// ```
+pub struct MergedCompleter {
+    completers: Vec<Box<dyn Completer + Send>>,
+}
+
+impl MergedCompleter {
+    /// Merges the suggestions of the completers, those of earlier completers first
+    pub fn new(completers: Vec<Box<dyn Completer + Send>>) -> Self {
+        MergedCompleter { completers }
+    }
+}
+
+impl MergedCompleter {
+    fn merge(
+        &mut self,
+        mut complete: impl FnMut(&mut dyn Completer) -> Vec<Suggestion>,
+    ) -> Vec<Suggestion> {
+        let mut values = HashSet::new();
+        self.completers
+            .iter_mut()
+            .flat_map(|completer| complete(completer.as_mut()))
+            .filter(|suggestion| values.insert(suggestion.value.clone()))
+            .collect()
+    }
+}
+
+impl Completer for MergedCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.merge(|completer| completer.complete(line, pos))
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.merge(|completer| completer.complete_with_context(line, pos, context))
+    }
+
+    fn has_pending(&mut self) -> bool {
+        any_pending(self.completers.iter_mut())
+    }
+
+    fn check_pending(&mut self) -> bool {
+        any_finished(self.completers.iter_mut())
+    }
+}
+
+/// Suggests what the first completer with any suggestions suggests
+///
+/// ```rust
+/// use reedline::{DefaultCompleter, FallbackCompleter, FilePathCompleter};
+///
+/// // paths only if no command matches
+/// let completer = FallbackCompleter::new(vec![
+///     Box::new(DefaultCompleter::new(vec!["cargo".into(), "cat".into()])),
+///     Box::new(FilePathCompleter::default()),
+/// ]);
+/// ```
+pub struct FallbackCompleter {
+    completers: Vec<Box<dyn Completer + Send>>,
+}
+
+impl FallbackCompleter {
+    /// Tries the completers in order
+    pub fn new(completers: Vec<Box<dyn Completer + Send>>) -> Self {
+        FallbackCompleter { completers }
+    }
+}
+
+impl FallbackCompleter {
+    fn first_non_empty(
+        &mut self,
+        mut complete: impl FnMut(&mut dyn Completer) -> Vec<Suggestion>,
+    ) -> Vec<Suggestion> {
+        self.completers
+            .iter_mut()
+            .map(|completer| complete(completer.as_mut()))
+            .find(|suggestions| !suggestions.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+impl Completer for FallbackCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.first_non_empty(|completer| completer.complete(line, pos))
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.first_non_empty(|completer| completer.complete_with_context(line, pos, context))
+    }
+
+    fn has_pending(&mut self) -> bool {
+        any_pending(self.completers.iter_mut())
+    }
+
+    fn check_pending(&mut self) -> bool {
+        any_finished(self.completers.iter_mut())
+    }
+}
+
+type Route = (
+    Box<dyn Fn(&str, usize) -> bool + Send>,
+    Box<dyn Completer + Send>,
+);
+
+/// Passes the line to the completer of the first route whose predicate accepts the line and
+/// cursor position, or to the default completer
+///
+/// ```rust
+/// use reedline::{DefaultCompleter, FilePathCompleter, RoutedCompleter};
+///
+/// let commands = DefaultCompleter::new(vec!["cargo".into(), "cat".into()]);
+/// // the first word is a command, the others are paths
+/// let completer = RoutedCompleter::new(Box::new(FilePathCompleter::default()))
+///     .with_route(|line, pos| !line[..pos].contains(' '), Box::new(commands));
+/// ```
+pub struct RoutedCompleter {
+    routes: Vec<Route>,
+    default: Box<dyn Completer + Send>,
+}
+
+impl RoutedCompleter {
+    /// Routes to `default` until routes are added
+    pub fn new(default: Box<dyn Completer + Send>) -> Self {
+        RoutedCompleter {
+            routes: vec![],
+            default,
+        }
+    }
+
+    /// A builder that adds a route, checked after the routes added before
+    #[must_use]
+    pub fn with_route(
+        mut self,
+        predicate: impl Fn(&str, usize) -> bool + Send + 'static,
+        completer: Box<dyn Completer + Send>,
+    ) -> Self {
+        self.routes.push((Box::new(predicate), completer));
+        self
+    }
+
+    fn route(&mut self, line: &str, pos: usize) -> &mut dyn Completer {
+        match self
+            .routes
+            .iter_mut()
+            .find(|(predicate, _)| predicate(line, pos))
+        {
+            Some((_, completer)) => completer.as_mut(),
+            None => self.default.as_mut(),
+        }
+    }
+
+    fn all_completers(&mut self) -> impl Iterator<Item = &mut Box<dyn Completer + Send>> {
+        self.routes
+            .iter_mut()
+            .map(|(_, completer)| completer)
+            .chain(std::iter::once(&mut self.default))
+    }
+}
+
+impl Completer for RoutedCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.route(line, pos).complete(line, pos)
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.route(line, pos)
+            .complete_with_context(line, pos, context)
+    }
+
+    fn complete_with_base_ranges_and_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> (Vec<Suggestion>, Vec<Range<usize>>) {
+        self.route(line, pos)
+            .complete_with_base_ranges_and_context(line, pos, context)
+    }
+
+    fn partial_complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        start: usize,
+        offset: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.route(line, pos)
+            .partial_complete_with_context(line, pos, start, offset, context)
+    }
+
+    fn total_completions_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> usize {
+        self.route(line, pos)
+            .total_completions_with_context(line, pos, context)
+    }
+
+    fn has_pending(&mut self) -> bool {
+        any_pending(self.all_completers())
+    }
+
+    fn check_pending(&mut self) -> bool {
+        any_finished(self.all_completers())
+    }
+}
+
+/// Changes or drops the suggestions of another completer
+///
+/// ```rust
+/// use reedline::{DefaultCompleter, MappedCompleter, Suggestion};
+///
+/// let commands = DefaultCompleter::new(vec!["cargo".into(), "cat".into()]);
+/// let completer = MappedCompleter::new(Box::new(commands), |suggestion| {
+///     Some(Suggestion {
+///         description: Some("command".into()),
+///         append_whitespace: true,
+///         ..suggestion
+///     })
+/// });
+/// ```
+pub struct MappedCompleter {
+    completer: Box<dyn Completer + Send>,
+    map: Box<dyn FnMut(Suggestion) -> Option<Suggestion> + Send>,
+}
+
+impl MappedCompleter {
+    /// Replaces every suggestion by the result of `map`, dropping those it returns `None` for
+    pub fn new(
+        completer: Box<dyn Completer + Send>,
+        map: impl FnMut(Suggestion) -> Option<Suggestion> + Send + 'static,
+    ) -> Self {
+        MappedCompleter {
+            completer,
+            map: Box::new(map),
+        }
+    }
+
+    /// Keeps the suggestions `predicate` returns `true` for
+    pub fn filter(
+        completer: Box<dyn Completer + Send>,
+        predicate: impl Fn(&Suggestion) -> bool + Send + 'static,
+    ) -> Self {
+        MappedCompleter::new(completer, move |suggestion| {
+            predicate(&suggestion).then_some(suggestion)
+        })
+    }
+}
+
+impl Completer for MappedCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.completer
+            .complete(line, pos)
+            .into_iter()
+            .filter_map(&mut self.map)
+            .collect()
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.completer
+            .complete_with_context(line, pos, context)
+            .into_iter()
+            .filter_map(&mut self.map)
+            .collect()
+    }
+
+    fn has_pending(&mut self) -> bool {
+        self.completer.has_pending()
+    }
+
+    fn check_pending(&mut self) -> bool {
+        self.completer.check_pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Span;
+    use pretty_assertions::assert_eq;
+
+    /// Suggests its values, after a background completion if `pending`
+    struct FixedCompleter {
+        values: Vec<&'static str>,
+        pending: bool,
+    }
+
+    impl FixedCompleter {
+        fn boxed(values: Vec<&'static str>) -> Box<dyn Completer + Send> {
+            Box::new(FixedCompleter {
+                values,
+                pending: false,
+            })
+        }
+
+        fn pending(values: Vec<&'static str>) -> Box<dyn Completer + Send> {
+            Box::new(FixedCompleter {
+                values,
+                pending: true,
+            })
+        }
+    }
+
+    impl Completer for FixedCompleter {
+        fn complete(&mut self, _line: &str, pos: usize) -> Vec<Suggestion> {
+            if self.pending {
+                return vec![];
+            }
+            self.values
+                .iter()
+                .map(|value| Suggestion {
+                    value: value.to_string(),
+                    span: Span::new(0, pos),
+                    ..Default::default()
+                })
+                .collect()
+        }
+
+        fn has_pending(&mut self) -> bool {
+            self.pending
+        }
+
+        fn check_pending(&mut self) -> bool {
+            std::mem::take(&mut self.pending)
+        }
+    }
+
+    fn values(completer: &mut dyn Completer, line: &str) -> Vec<String> {
+        completer
+            .complete(line, line.len())
+            .into_iter()
+            .map(|suggestion| suggestion.value)
+            .collect()
+    }
+
+    #[test]
+    fn merges_without_duplicates() {
+        let mut completer = MergedCompleter::new(vec![
+            FixedCompleter::boxed(vec!["cargo", "cat"]),
+            FixedCompleter::boxed(vec!["cat", "cd"]),
+        ]);
+        assert_eq!(values(&mut completer, "c"), vec!["cargo", "cat", "cd"]);
+    }
+
+    #[test]
+    fn falls_back_to_the_next_completer() {
+        let mut completer = FallbackCompleter::new(vec![
+            FixedCompleter::boxed(vec![]),
+            FixedCompleter::boxed(vec!["cd"]),
+            FixedCompleter::boxed(vec!["cat"]),
+        ]);
+        assert_eq!(values(&mut completer, "c"), vec!["cd"]);
+    }
+
+    #[test]
+    fn routes_by_the_line() {
+        let mut completer = RoutedCompleter::new(FixedCompleter::boxed(vec!["Cargo.toml"]))
+            .with_route(
+                |line, pos| !line[..pos].contains(' '),
+                FixedCompleter::boxed(vec!["cargo"]),
+            )
+            .with_route(|_, _| true, FixedCompleter::boxed(vec!["unreachable"]));
+        assert_eq!(values(&mut completer, "car"), vec!["cargo"]);
+        assert_eq!(values(&mut completer, "cat C"), vec!["unreachable"]);
+    }
+
+    #[test]
+    fn maps_and_filters_suggestions() {
+        let mut completer = MappedCompleter::new(
+            FixedCompleter::boxed(vec!["cargo", "cat", "cd"]),
+            |suggestion| {
+                (suggestion.value != "cat").then_some(Suggestion {
+                    append_whitespace: true,
+                    ..suggestion
+                })
+            },
+        );
+        let suggestions = completer.complete("c", 1);
+        assert_eq!(suggestions.len(), 2);
+        assert!(suggestions.iter().all(|s| s.append_whitespace));
+
+        let mut completer =
+            MappedCompleter::filter(FixedCompleter::boxed(vec!["cargo", "cat"]), |suggestion| {
+                suggestion.value.len() > 3
+            });
+        assert_eq!(values(&mut completer, "c"), vec!["cargo"]);
+    }
+
+    #[test]
+    fn passes_the_context_on() {
+        /// Suggests the name of the menu
+        struct MenuNameCompleter;
+
+        impl Completer for MenuNameCompleter {
+            fn complete(&mut self, _line: &str, _pos: usize) -> Vec<Suggestion> {
+                vec![]
+            }
+
+            fn complete_with_context(
+                &mut self,
+                _line: &str,
+                pos: usize,
+                context: &CompletionContext,
+            ) -> Vec<Suggestion> {
+                vec![Suggestion {
+                    value: context.menu_name.unwrap_or_default().to_string(),
+                    span: Span::new(0, pos),
+                    ..Default::default()
+                }]
+            }
+        }
+
+        let history = crate::FileBackedHistory::default();
+        let context = CompletionContext {
+            cwd: None,
+            edit_mode: crate::PromptEditMode::Default,
+            menu_name: Some("completion_menu"),
+            history: &history,
+        };
+        let mut completer = MappedCompleter::filter(
+            Box::new(FallbackCompleter::new(vec![
+                FixedCompleter::boxed(vec![]),
+                Box::new(MergedCompleter::new(vec![Box::new(RoutedCompleter::new(
+                    Box::new(MenuNameCompleter),
+                ))])),
+            ])),
+            |_| true,
+        );
+        assert!(completer.complete("c", 1).is_empty());
+        let suggestions = completer.complete_with_context("c", 1, &context);
+        assert_eq!(suggestions[0].value, "completion_menu");
+    }
+
+    #[test]
+    fn forwards_background_completions() {
+        let mut completer = FallbackCompleter::new(vec![
+            FixedCompleter::pending(vec!["cargo"]),
+            FixedCompleter::pending(vec!["cat"]),
+        ]);
+        assert!(completer.has_pending());
+        assert!(values(&mut completer, "c").is_empty());
+        // both finished completions are stored
+        assert!(completer.check_pending());
+        assert!(!completer.has_pending());
+        assert_eq!(values(&mut completer, "c"), vec!["cargo"]);
+
+        let mut completer = MappedCompleter::new(
+            Box::new(
+                RoutedCompleter::new(FixedCompleter::boxed(vec![])).with_route(
+                    |_, _| true,
+                    Box::new(MergedCompleter::new(vec![FixedCompleter::pending(vec![
+                        "cd",
+                    ])])),
+                ),
+            ),
+            Some,
+        );
+        assert!(completer.has_pending());
+        assert!(completer.check_pending());
+        assert!(!completer.check_pending());
+        assert_eq!(values(&mut completer, "c"), vec!["cd"]);
+    }
+}
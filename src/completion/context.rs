@@ -0,0 +1,188 @@
+use crate::{Completer, History, PromptEditMode, Suggestion};
+use std::ops::Range;
+
+/// What the engine knows about the line being completed,
+/// passed to [`Completer::complete_with_context()`]
+#[derive(Clone)]
+pub struct CompletionContext<'a> {
+    /// The working directory set with [`Reedline::with_cwd()`](crate::Reedline::with_cwd),
+    /// `None` if the process' working directory applies
+    pub cwd: Option<&'a str>,
+    /// The current edit mode, e.g. to complete differently in vi normal mode
+    pub edit_mode: PromptEditMode,
+    /// The name of the menu asking for the completions
+    pub menu_name: Option<&'a str>,
+    /// The history of the engine, with the [`HistoryFilter`](crate::HistoryFilter) applied
+    pub history: &'a dyn History,
+}
+
+impl<'a> CompletionContext<'a> {
+    /// The context of the engine, before a menu is known
+    pub(crate) fn new(
+        history: &'a dyn History,
+        cwd: Option<&'a str>,
+        edit_mode: PromptEditMode,
+    ) -> Self {
+        CompletionContext {
+            cwd,
+            edit_mode,
+            menu_name: None,
+            history,
+        }
+    }
+}
+
+/// Passes the context to the completer of a menu, for the menus that only know [`Completer`]
+///
+/// Every method is forwarded to the variant of the wrapped completer taking the context,
+/// which calls the completer's own method unless the completer reads the context there.
+pub(crate) struct ContextCompleter<'a> {
+    completer: &'a mut dyn Completer,
+    context: CompletionContext<'a>,
+}
+
+impl<'a> ContextCompleter<'a> {
+    pub(crate) fn new(completer: &'a mut dyn Completer, context: CompletionContext<'a>) -> Self {
+        ContextCompleter { completer, context }
+    }
+}
+
+impl Completer for ContextCompleter<'_> {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.completer
+            .complete_with_context(line, pos, &self.context)
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        _context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        // the context of the wrapper knows the menu
+        self.complete(line, pos)
+    }
+
+    fn complete_with_base_ranges(
+        &mut self,
+        line: &str,
+        pos: usize,
+    ) -> (Vec<Suggestion>, Vec<Range<usize>>) {
+        self.completer
+            .complete_with_base_ranges_and_context(line, pos, &self.context)
+    }
+
+    fn partial_complete(
+        &mut self,
+        line: &str,
+        pos: usize,
+        start: usize,
+        offset: usize,
+    ) -> Vec<Suggestion> {
+        self.completer
+            .partial_complete_with_context(line, pos, start, offset, &self.context)
+    }
+
+    fn total_completions(&mut self, line: &str, pos: usize) -> usize {
+        self.completer
+            .total_completions_with_context(line, pos, &self.context)
+    }
+
+    fn has_pending(&mut self) -> bool {
+        self.completer.has_pending()
+    }
+
+    fn check_pending(&mut self) -> bool {
+        self.completer.check_pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileBackedHistory, Span};
+    use pretty_assertions::assert_eq;
+
+    /// Suggests the menu name, but pages and counts on its own
+    struct PagingCompleter;
+
+    impl Completer for PagingCompleter {
+        fn complete(&mut self, _line: &str, _pos: usize) -> Vec<Suggestion> {
+            vec![]
+        }
+
+        fn complete_with_context(
+            &mut self,
+            _line: &str,
+            pos: usize,
+            context: &CompletionContext,
+        ) -> Vec<Suggestion> {
+            vec![Suggestion {
+                value: context.menu_name.unwrap_or_default().to_string(),
+                span: Span::new(0, pos),
+                ..Default::default()
+            }]
+        }
+
+        fn complete_with_base_ranges_and_context(
+            &mut self,
+            _line: &str,
+            _pos: usize,
+            _context: &CompletionContext,
+        ) -> (Vec<Suggestion>, Vec<Range<usize>>) {
+            (vec![], vec![0..1, 1..2])
+        }
+
+        fn partial_complete_with_context(
+            &mut self,
+            _line: &str,
+            pos: usize,
+            start: usize,
+            offset: usize,
+            context: &CompletionContext,
+        ) -> Vec<Suggestion> {
+            vec![Suggestion {
+                value: format!(
+                    "{} page {start}+{offset}",
+                    context.menu_name.unwrap_or_default()
+                ),
+                span: Span::new(0, pos),
+                ..Default::default()
+            }]
+        }
+
+        fn total_completions_with_context(
+            &mut self,
+            _line: &str,
+            _pos: usize,
+            _context: &CompletionContext,
+        ) -> usize {
+            42
+        }
+    }
+
+    #[test]
+    fn keeps_the_methods_of_the_wrapped_completer() {
+        let history = FileBackedHistory::default();
+        let context = CompletionContext {
+            menu_name: Some("list_menu"),
+            ..CompletionContext::new(&history, None, PromptEditMode::Default)
+        };
+        let mut inner = PagingCompleter;
+        let mut completer = ContextCompleter::new(&mut inner, context);
+
+        let values = |suggestions: Vec<Suggestion>| -> Vec<String> {
+            suggestions.into_iter().map(|s| s.value).collect()
+        };
+        assert_eq!(values(completer.complete("ca", 2)), vec!["list_menu"]);
+        assert_eq!(
+            values(completer.partial_complete("ca", 2, 10, 5)),
+            vec!["list_menu page 10+5"]
+        );
+        assert_eq!(completer.total_completions("ca", 2), 42);
+        assert_eq!(
+            completer.complete_with_base_ranges("ca", 2).1,
+            vec![0..1, 1..2]
+        );
+    }
+}
@@ -1,9 +1,71 @@
-use crate::{Completer, Span, Suggestion};
+use crate::{
+    utils::fuzzy::{fuzzy_match, is_case_sensitive, FuzzyMatch},
+    Completer, Span, Suggestion,
+};
 use std::{
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashSet},
     str::Chars,
     sync::Arc,
 };
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How the [`DefaultCompleter`] matches the typed text against its words
+///
+/// All strategies but [`MatchingStrategy::Prefix`] report the matched graphemes in
+/// [`Suggestion::match_indices`], so that the menus highlight them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchingStrategy {
+    /// Words starting with the typed text
+    #[default]
+    Prefix,
+    /// Words starting with the typed text, ignoring the case
+    CaseInsensitive,
+    /// Words starting with the typed text, ignoring the case unless the typed text contains
+    /// an uppercase character
+    SmartCase,
+    /// Words containing the typed text, those containing it earlier first.
+    /// Uses smart case like [`MatchingStrategy::SmartCase`].
+    Substring,
+    /// Words containing the typed characters in order, best matches first: matches at the start
+    /// of words and consecutive matches rank higher.
+    /// Uses smart case like [`MatchingStrategy::SmartCase`].
+    Fuzzy,
+}
+
+impl MatchingStrategy {
+    /// Score and matched graphemes of the word, `None` if it doesn't match
+    fn match_word(self, typed: &str, word: &str) -> Option<FuzzyMatch> {
+        let (case_sensitive, anchored) = match self {
+            MatchingStrategy::Prefix => (true, true),
+            MatchingStrategy::CaseInsensitive => (false, true),
+            MatchingStrategy::SmartCase => (is_case_sensitive(typed), true),
+            MatchingStrategy::Substring => (is_case_sensitive(typed), false),
+            MatchingStrategy::Fuzzy => return fuzzy_match(typed, word),
+        };
+        let graphemes = |text: &str| -> Vec<String> {
+            text.graphemes(true)
+                .map(|g| {
+                    if case_sensitive {
+                        g.to_string()
+                    } else {
+                        g.to_lowercase()
+                    }
+                })
+                .collect()
+        };
+        let (typed, word) = (graphemes(typed), graphemes(word));
+        let start = if anchored {
+            word.starts_with(&typed).then_some(0)?
+        } else {
+            (0..=word.len().checked_sub(typed.len())?)
+                .find(|&start| word[start..].starts_with(&typed))?
+        };
+        Some(FuzzyMatch {
+            score: -(start as i64),
+            indices: (start..start + typed.len()).collect(),
+        })
+    }
+}
 
 /// A default completer that can detect keywords
 ///
@@ -26,6 +88,7 @@ use std::{
 pub struct DefaultCompleter {
     root: CompletionNode,
     min_word_len: usize,
+    matching: MatchingStrategy,
 }
 
 impl Default for DefaultCompleter {
@@ -34,6 +97,7 @@ impl Default for DefaultCompleter {
         Self {
             root: CompletionNode::new(inclusions),
             min_word_len: 2,
+            matching: MatchingStrategy::default(),
         }
     }
 }
@@ -92,35 +156,42 @@ impl Completer for DefaultCompleter {
                     } else {
                         span_line = format!("{s} {span_line}");
                     }
-                    if let Some(mut extensions) = self.root.complete(span_line.chars()) {
-                        extensions.sort();
-                        completions.extend(
-                            extensions
-                                .iter()
-                                .map(|ext| {
-                                    let span = Span::new(
-                                        pos - span_line.len() - span_line_whitespaces,
-                                        pos,
-                                    );
-
-                                    Suggestion {
-                                        value: format!("{span_line}{ext}"),
-                                        description: None,
-                                        style: None,
-                                        extra: None,
-                                        span,
-                                        append_whitespace: false,
-                                        ..Default::default()
-                                    }
-                                })
-                                .filter(|t| t.value.len() > (t.span.end - t.span.start))
-                                .collect::<Vec<Suggestion>>(),
-                        );
-                    }
+                    completions.extend(
+                        self.matching_words(&span_line)
+                            .into_iter()
+                            .map(|(value, match_indices)| {
+                                let span =
+                                    Span::new(pos - span_line.len() - span_line_whitespaces, pos);
+
+                                Suggestion {
+                                    value,
+                                    description: None,
+                                    style: None,
+                                    extra: None,
+                                    span,
+                                    append_whitespace: false,
+                                    match_indices,
+                                    ..Default::default()
+                                }
+                            })
+                            .filter(|t| t.value != span_line)
+                            .collect::<Vec<Suggestion>>(),
+                    );
                 }
             }
         }
         completions.dedup();
+        if self.matching != MatchingStrategy::Prefix {
+            // a word matching several of the trailing words replaces as many of them as it can,
+            // the longer spans come later
+            let mut seen = HashSet::new();
+            completions = completions
+                .into_iter()
+                .rev()
+                .filter(|suggestion| seen.insert(suggestion.value.clone()))
+                .collect();
+            completions.reverse();
+        }
         completions
     }
 }
@@ -277,6 +348,52 @@ impl DefaultCompleter {
         self.min_word_len = len;
         self
     }
+
+    /// A builder that sets how the typed text is matched against the words
+    ///
+    /// # Example
+    /// ```
+    /// use reedline::{Completer, DefaultCompleter, MatchingStrategy};
+    ///
+    /// let mut completions =
+    ///     DefaultCompleter::default().with_matching_strategy(MatchingStrategy::Fuzzy);
+    /// completions.insert(vec!["git push".into(), "gpg".into(), "grep".into()]);
+    /// let suggestions = completions.complete("gp", 2);
+    /// assert_eq!(suggestions[0].value, "gpg");
+    /// assert_eq!(suggestions[1].value, "git push");
+    /// assert_eq!(suggestions[1].match_indices, Some(vec![0, 4]));
+    /// ```
+    #[must_use]
+    pub fn with_matching_strategy(mut self, matching: MatchingStrategy) -> Self {
+        self.matching = matching;
+        self
+    }
+
+    /// The words matching the typed text in the order to suggest them, with the matched graphemes
+    fn matching_words(&self, typed: &str) -> Vec<(String, Option<Vec<usize>>)> {
+        if self.matching == MatchingStrategy::Prefix {
+            let mut extensions = self.root.complete(typed.chars()).unwrap_or_default();
+            extensions.sort();
+            return extensions
+                .into_iter()
+                .map(|ext| (format!("{typed}{ext}"), None))
+                .collect();
+        }
+        let mut matches: Vec<_> = self
+            .root
+            .collect("")
+            .into_iter()
+            .filter_map(|word| {
+                let found = self.matching.match_word(typed, &word)?;
+                Some((found.score, word, found.indices))
+            })
+            .collect();
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        matches
+            .into_iter()
+            .map(|(_, word, indices)| (word, Some(indices)))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -365,6 +482,7 @@ impl CompletionNode {
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
+    use rstest::rstest;
     #[test]
     fn default_completer_with_non_ansi() {
         let mut completions = DefaultCompleter::default();
@@ -461,4 +579,53 @@ mod tests {
             [&buffer[ranges[0].clone()], &buffer[ranges[1].clone()]]
         );
     }
+
+    #[rstest]
+    #[case(MatchingStrategy::Prefix, "bat", vec![("batcave", None), ("batman", None)])]
+    #[case(MatchingStrategy::CaseInsensitive, "bat", vec![("Batmobile", Some(vec![0, 1, 2])), ("batcave", Some(vec![0, 1, 2])), ("batman", Some(vec![0, 1, 2]))])]
+    #[case(MatchingStrategy::SmartCase, "Bat", vec![("Batmobile", Some(vec![0, 1, 2]))])]
+    #[case(MatchingStrategy::SmartCase, "bat", vec![("Batmobile", Some(vec![0, 1, 2])), ("batcave", Some(vec![0, 1, 2])), ("batman", Some(vec![0, 1, 2]))])]
+    #[case(MatchingStrategy::Substring, "ob", vec![("robber", Some(vec![1, 2])), ("Batmobile", Some(vec![4, 5]))])]
+    #[case(MatchingStrategy::Fuzzy, "bmn", vec![("batman", Some(vec![0, 3, 5]))])]
+    fn matching_strategies(
+        #[case] matching: MatchingStrategy,
+        #[case] typed: &str,
+        #[case] expected: Vec<(&str, Option<Vec<usize>>)>,
+    ) {
+        let mut completer = DefaultCompleter::default().with_matching_strategy(matching);
+        completer.insert(
+            ["batman", "robber", "Batmobile", "batcave"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        );
+        let line = format!("hello {typed}");
+        let suggestions: Vec<_> = completer
+            .complete(&line, line.len())
+            .into_iter()
+            .map(|s| {
+                assert_eq!(s.span, Span::new(6, line.len()));
+                (s.value, s.match_indices)
+            })
+            .collect();
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(value, indices)| (value.to_string(), indices))
+            .collect();
+        assert_eq!(suggestions, expected);
+    }
+
+    #[test]
+    fn fuzzy_matches_replace_all_matched_words() {
+        let mut completer =
+            DefaultCompleter::default().with_matching_strategy(MatchingStrategy::Fuzzy);
+        completer.insert(vec!["hello world".into()]);
+        let suggestions = completer.complete("hello wor", 9);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].span, Span::new(0, 9));
+        assert_eq!(
+            suggestions[0].match_indices,
+            Some(vec![0, 1, 2, 3, 4, 5, 6, 7, 8])
+        );
+    }
 }
@@ -0,0 +1,393 @@
+use crate::{
+    utils::path::{expand_env_vars, expand_tilde},
+    Completer, CompletionContext, Span, Suggestion,
+};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Windows paths use `\` as separator, so it can't escape characters there
+const ESCAPES: bool = cfg!(not(windows));
+
+/// A completer for the files and directories of the path under the cursor
+///
+/// Relative paths are resolved against the working directory given to
+/// [`FilePathCompleter::with_cwd()`], otherwise against the one of the engine set with
+/// [`Reedline::with_cwd()`](crate::Reedline::with_cwd), or else against the one of the process.
+/// A leading `~` and environment variables like `$HOME` or `${HOME}` are expanded, but kept
+/// as typed in the completed text.
+///
+/// Directories get a trailing separator so their entries can be completed next.
+/// Names with spaces and special characters are escaped with `\`, or, if the path is
+/// inside quotes, completed within them.
+///
+/// ```rust
+/// use reedline::{FilePathCompleter, Reedline};
+///
+/// let cwd = std::env::current_dir()
+///     .ok()
+///     .map(|cwd| cwd.to_string_lossy().into_owned());
+/// let completer = FilePathCompleter::default().with_hidden_files(true);
+/// let line_editor = Reedline::create()
+///     .with_cwd(cwd)
+///     .with_completer(Box::new(completer));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FilePathCompleter {
+    cwd: Option<String>,
+    show_hidden: bool,
+    dirs_only: bool,
+}
+
+/// The path under the cursor, as typed and with quotes and escapes resolved
+#[derive(Debug, PartialEq, Eq)]
+struct TypedPath {
+    /// Byte position of the start of the path in the line
+    start: usize,
+    /// Byte position in the line after the last separator, or after an opened quote
+    name_start: usize,
+    /// The resolved text, e.g. `my dir/` of `"my dir/`
+    text: String,
+    /// The quote still open at the cursor
+    open_quote: Option<char>,
+}
+
+impl TypedPath {
+    /// Parses the whitespace-separated word of the line before the cursor like a shell
+    fn parse(line: &str) -> TypedPath {
+        let mut path = TypedPath {
+            start: 0,
+            name_start: 0,
+            text: String::new(),
+            open_quote: None,
+        };
+        let mut chars = line.char_indices();
+        while let Some((position, c)) = chars.next() {
+            let after = position + c.len_utf8();
+            match (c, path.open_quote) {
+                ('\\', quote) if ESCAPES && quote != Some('\'') => {
+                    if let Some((_, escaped)) = chars.next() {
+                        path.text.push(escaped);
+                    }
+                }
+                ('\'' | '"', None) => {
+                    path.open_quote = Some(c);
+                    path.name_start = path.name_start.max(after);
+                }
+                (c, Some(quote)) if c == quote => path.open_quote = None,
+                (c, None) if c.is_whitespace() => {
+                    path.start = after;
+                    path.name_start = after;
+                    path.text.clear();
+                }
+                (c, _) => {
+                    path.text.push(c);
+                    if is_separator(c) {
+                        path.name_start = after;
+                    }
+                }
+            }
+        }
+        path
+    }
+
+    /// The directory part and the beginning of the name
+    fn split(&self) -> (&str, &str) {
+        let name = self
+            .text
+            .rfind(is_separator)
+            .map_or(0, |separator| separator + 1);
+        self.text.split_at(name)
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    c == '/' || c == MAIN_SEPARATOR
+}
+
+/// Escapes the characters a shell would otherwise interpret
+fn escape(name: &str, quote: Option<char>) -> String {
+    let special: &[char] = match quote {
+        _ if !ESCAPES => &[],
+        Some('\'') => &[],
+        Some(_) => &['"', '\\', '$', '`'],
+        None => &[
+            ' ', '\t', '"', '\'', '\\', '$', '`', '&', '|', ';', '(', ')', '<', '>', '*', '?', '[',
+            ']', '{', '}', '!', '#',
+        ],
+    };
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl FilePathCompleter {
+    /// A builder that sets the directory relative paths are resolved against,
+    /// instead of the one of the engine
+    #[must_use]
+    pub fn with_cwd(mut self, cwd: Option<String>) -> Self {
+        self.cwd = cwd;
+        self
+    }
+
+    /// A builder that lists the names starting with `.` even if the typed name doesn't
+    #[must_use]
+    pub fn with_hidden_files(mut self, show_hidden: bool) -> Self {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    /// A builder that only completes directories, e.g. for `cd`
+    #[must_use]
+    pub fn with_dirs_only(mut self, dirs_only: bool) -> Self {
+        self.dirs_only = dirs_only;
+        self
+    }
+
+    fn resolve(&self, dir: &str, engine_cwd: Option<&str>) -> Option<PathBuf> {
+        let dir = PathBuf::from(expand_env_vars(&expand_tilde(dir)));
+        if dir.is_absolute() {
+            return Some(dir);
+        }
+        let cwd = match self.cwd.as_deref().or(engine_cwd) {
+            Some(cwd) => PathBuf::from(cwd),
+            None => std::env::current_dir().ok()?,
+        };
+        Some(cwd.join(dir))
+    }
+
+    /// The names of the directory starting with `prefix`, with whether they are directories
+    fn list(&self, dir: &Path, prefix: &str) -> Vec<(String, bool)> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return vec![];
+        };
+        let mut names: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                // names that aren't valid UTF-8 can't be typed in the line
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with(prefix)
+                    || (name.starts_with('.') && !self.show_hidden && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                // follows symlinks to directories
+                let is_dir = std::fs::metadata(entry.path()).map_or(false, |m| m.is_dir());
+                (is_dir || !self.dirs_only).then_some((name, is_dir))
+            })
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn complete_in(&self, line: &str, pos: usize, engine_cwd: Option<&str>) -> Vec<Suggestion> {
+        let line = if line.len() > pos { &line[..pos] } else { line };
+        let typed = TypedPath::parse(line);
+        let (dir, prefix) = typed.split();
+        let Some(resolved) = self.resolve(if dir.is_empty() { "." } else { dir }, engine_cwd)
+        else {
+            return vec![];
+        };
+        let matched = prefix.graphemes(true).count();
+        self.list(&resolved, prefix)
+            .into_iter()
+            .map(|(name, is_dir)| {
+                let escaped = escape(&name, typed.open_quote);
+                let mut value = format!("{}{escaped}", &line[typed.start..typed.name_start]);
+                let mut display = name;
+                if is_dir {
+                    value.push(MAIN_SEPARATOR);
+                    display.push(MAIN_SEPARATOR);
+                } else if let Some(quote) = typed.open_quote {
+                    value.push(quote);
+                }
+                Suggestion {
+                    value,
+                    display_override: Some(display),
+                    span: Span::new(typed.start, pos),
+                    append_whitespace: !is_dir,
+                    match_indices: Some((0..matched).collect()),
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+}
+
+impl Completer for FilePathCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        self.complete_in(line, pos, None)
+    }
+
+    fn complete_with_context(
+        &mut self,
+        line: &str,
+        pos: usize,
+        context: &CompletionContext,
+    ) -> Vec<Suggestion> {
+        self.complete_in(line, pos, context.cwd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use std::fs;
+
+    fn example_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("alpha.txt"), "").unwrap();
+        fs::write(dir.path().join("beta"), "").unwrap();
+        fs::write(dir.path().join("my file.txt"), "").unwrap();
+        fs::write(dir.path().join(".hidden"), "").unwrap();
+        fs::create_dir(dir.path().join("alps")).unwrap();
+        fs::write(dir.path().join("alps").join("summit"), "").unwrap();
+        dir
+    }
+
+    fn completer(dir: &tempfile::TempDir) -> FilePathCompleter {
+        FilePathCompleter::default().with_cwd(Some(dir.path().to_string_lossy().into_owned()))
+    }
+
+    fn values(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|s| s.value).collect()
+    }
+
+    #[rstest]
+    #[case(r#"cat "a"#, TypedPath { start: 4, name_start: 5, text: "a".into(), open_quote: Some('"') })]
+    #[case(r#"ls src/"my d"#, TypedPath { start: 3, name_start: 8, text: "src/my d".into(), open_quote: Some('"') })]
+    #[case(r#"ls 'it''s' x\ y/z"#, TypedPath { start: 11, name_start: 16, text: "x y/z".into(), open_quote: None })]
+    fn parses_the_path_under_the_cursor(#[case] line: &str, #[case] path: TypedPath) {
+        assert_eq!(TypedPath::parse(line), path);
+    }
+
+    #[test]
+    fn completes_files_and_directories() {
+        let dir = example_dir();
+        let suggestions = completer(&dir).complete("cat al", 6);
+        let sep = MAIN_SEPARATOR;
+        assert_eq!(
+            suggestions,
+            vec![
+                Suggestion {
+                    value: "alpha.txt".into(),
+                    display_override: Some("alpha.txt".into()),
+                    span: Span::new(4, 6),
+                    append_whitespace: true,
+                    match_indices: Some(vec![0, 1]),
+                    ..Default::default()
+                },
+                Suggestion {
+                    value: format!("alps{sep}"),
+                    display_override: Some(format!("alps{sep}")),
+                    span: Span::new(4, 6),
+                    append_whitespace: false,
+                    match_indices: Some(vec![0, 1]),
+                    ..Default::default()
+                },
+            ]
+        );
+        let line = format!("cat alps{sep}s");
+        assert_eq!(
+            values(completer(&dir).complete(&line, line.len())),
+            vec![format!("alps{sep}summit")]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn escapes_and_quotes_names() {
+        let dir = example_dir();
+        assert_eq!(
+            values(completer(&dir).complete("cat my", 6)),
+            vec![r"my\ file.txt"]
+        );
+        assert_eq!(
+            values(completer(&dir).complete(r#"cat "my"#, 7)),
+            vec![r#""my file.txt""#]
+        );
+        assert_eq!(
+            values(completer(&dir).complete(r"cat my\ f", 9)),
+            vec![r"my\ file.txt"]
+        );
+    }
+
+    #[test]
+    fn lists_hidden_files_and_directories_only_if_asked() {
+        let dir = example_dir();
+        assert_eq!(completer(&dir).complete("ls ", 3).len(), 4);
+        assert_eq!(
+            values(completer(&dir).complete("ls .h", 5)),
+            vec![".hidden"]
+        );
+        assert_eq!(
+            completer(&dir)
+                .with_hidden_files(true)
+                .complete("ls ", 3)
+                .len(),
+            5
+        );
+        assert_eq!(
+            values(completer(&dir).with_dirs_only(true).complete("cd ", 3)),
+            vec![format!("alps{MAIN_SEPARATOR}")]
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn expands_environment_variables_but_keeps_them() {
+        let dir = example_dir();
+        std::env::set_var("REEDLINE_TEST_COMPLETION_DIR", dir.path());
+        let line = "ls $REEDLINE_TEST_COMPLETION_DIR/alps/";
+        assert_eq!(
+            values(FilePathCompleter::default().complete(line, line.len())),
+            vec!["$REEDLINE_TEST_COMPLETION_DIR/alps/summit"]
+        );
+    }
+
+    #[test]
+    fn resolves_against_the_working_directory_of_the_engine() {
+        let dir = example_dir();
+        let engine_cwd = dir.path().to_string_lossy().into_owned();
+        let history = crate::FileBackedHistory::default();
+        let context = CompletionContext {
+            cwd: Some(&engine_cwd),
+            edit_mode: crate::PromptEditMode::Default,
+            menu_name: None,
+            history: &history,
+        };
+        assert_eq!(
+            values(FilePathCompleter::default().complete_with_context("cat be", 6, &context)),
+            vec!["beta"]
+        );
+        // also for the paging and the base ranges of the menus
+        let mut completer = FilePathCompleter::default();
+        assert_eq!(
+            completer.total_completions_with_context("cat be", 6, &context),
+            1
+        );
+        let (suggestions, ranges) =
+            completer.complete_with_base_ranges_and_context("cat be", 6, &context);
+        assert_eq!(values(suggestions), vec!["beta"]);
+        assert_eq!(ranges.first(), Some(&(4..6)));
+        // the own working directory comes first
+        let other = tempfile::tempdir().unwrap();
+        let mut completer = FilePathCompleter::default()
+            .with_cwd(Some(other.path().to_string_lossy().into_owned()));
+        assert!(completer
+            .complete_with_context("cat be", 6, &context)
+            .is_empty());
+    }
+
+    #[test]
+    fn completes_nothing_in_missing_directories() {
+        let dir = example_dir();
+        assert!(completer(&dir).complete("cat missing/a", 13).is_empty());
+    }
+}
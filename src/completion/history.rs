@@ -1,29 +1,135 @@
-use std::{collections::HashSet, ops::Deref};
+use std::{collections::HashSet, ops::Deref, path::Path};
+
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
 
 use crate::{
-    history::SearchQuery, menu_functions::parse_selection_char, Completer, History, HistoryItem,
-    Result, Span, Suggestion,
+    history::{CommandLineSearch, HistorySessionId, SearchQuery},
+    menu_functions::parse_selection_char,
+    result::{ReedlineError, ReedlineErrorVariants},
+    utils::path::expand_tilde,
+    Completer, History, HistoryItem, Result, Span, Suggestion,
 };
 
 const SELECTION_CHAR: char = '!';
 
+/// Parses the text typed into the history menu into a query for the entries containing the text.
+///
+/// Words of the form `key:value` narrow the search instead:
+/// - `cwd:~/src`: run in the directory or below it
+/// - `host:build01`: run on the host
+/// - `exit:0`: succeeded, any other exit status finds the failed commands
+/// - `session:current`: of the current session, see [`SearchFilter::session`](crate::SearchFilter::session)
+/// - `tag:deploy`: with the tag, see [`HistoryItem::tags`]
+/// - `after:2d`, `before:2026-01-01`: started after or before a date in the local time zone,
+///   or a time span ago in `s`, `m`, `h`, `d` or `w`
+///
+/// Words with other keys or invalid values are searched for as text, as are all words if the
+/// history doesn't support one of the filters.
+fn parse_history_query(
+    text: &str,
+    session: Option<HistorySessionId>,
+    now: DateTime<Utc>,
+) -> SearchQuery {
+    let mut query = SearchQuery::all_that_contain_rev(String::new());
+    let mut searched = vec![];
+    for word in text.split_whitespace() {
+        let parsed = word.split_once(':').and_then(|(key, value)| {
+            let filter = &mut query.filter;
+            match key {
+                "cwd" if !value.is_empty() => filter.cwd_prefix = Some(expand_tilde(value)),
+                "host" if !value.is_empty() => filter.hostname = Some(value.to_string()),
+                "exit" => filter.exit_successful = Some(value.parse::<i64>().ok()? == 0),
+                "session" if value == "current" => filter.session = session,
+                "tag" if !value.is_empty() => filter.tags.push(value.to_string()),
+                // the results are searched backward, from `start_time` to `end_time`
+                "after" => query.end_time = Some(parse_time(value, now)?),
+                "before" => query.start_time = Some(parse_time(value, now)?),
+                _ => return None,
+            }
+            Some(())
+        });
+        if parsed.is_none() {
+            searched.push(word);
+        }
+    }
+    let searched = if searched.len() == text.split_whitespace().count() {
+        // keep the whitespace of plain searches
+        text.to_string()
+    } else {
+        searched.join(" ")
+    };
+    query.filter.command_line = Some(CommandLineSearch::Substring(searched));
+    query
+}
+
+/// Parses a date like `2026-01-01` or a time span like `2d` before `now`
+fn parse_time(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some(midnight.with_timezone(&Utc));
+    }
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let span = match unit {
+        's' => Duration::seconds(amount),
+        'm' => Duration::minutes(amount),
+        'h' => Duration::hours(amount),
+        'd' => Duration::days(amount),
+        'w' => Duration::weeks(amount),
+        _ => return None,
+    };
+    Some(now - span)
+}
+
 // The HistoryCompleter is created just before updating the menu
 // It pulls data from the object that contains access to the History
-pub(crate) struct HistoryCompleter<'menu>(&'menu dyn History);
+pub(crate) struct HistoryCompleter<'menu> {
+    history: &'menu dyn History,
+    // list the pinned entries before all others
+    pinned_first: bool,
+}
 
 fn search_unique(
     completer: &HistoryCompleter,
     line: &str,
 ) -> Result<impl Iterator<Item = HistoryItem>> {
     let parsed = parse_selection_char(line, SELECTION_CHAR);
-    let values = completer.0.search(SearchQuery::all_that_contain_rev(
-        parsed.remainder.to_string(),
-    ))?;
+    let query = parse_history_query(parsed.remainder, completer.history.session(), Utc::now());
+    let (query, found) = match completer.history.search(query.clone()) {
+        // histories that can't filter like that search for the words as text
+        Err(ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported { .. })) => {
+            let text_query = SearchQuery::all_that_contain_rev(parsed.remainder.to_string());
+            let found = completer.history.search(text_query.clone())?;
+            (text_query, found)
+        }
+        found => (query.clone(), found?),
+    };
+    let mut values = vec![];
+    if completer.pinned_first {
+        let mut pinned = query.clone();
+        pinned.filter.tags = vec![HistoryItem::PINNED_TAG.to_string()];
+        match completer.history.search(pinned) {
+            Ok(pinned) => values = pinned,
+            // histories without tags have no pinned entries
+            Err(ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported { .. })) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    values.extend(found);
 
+    // the histories match the start of the path, which may end within a directory name
+    let dir = query.filter.cwd_prefix;
+    let in_dir = move |value: &HistoryItem| match (&dir, &value.cwd) {
+        (Some(dir), Some(cwd)) => Path::new(cwd).starts_with(dir),
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
     let mut seen_matching_command_lines = HashSet::new();
-    Ok(values
-        .into_iter()
-        .filter(move |value| seen_matching_command_lines.insert(value.command_line.clone())))
+    Ok(values.into_iter().filter(move |value| {
+        in_dir(value) && seen_matching_command_lines.insert(value.command_line.clone())
+    }))
 }
 
 impl Completer for HistoryCompleter<'_> {
@@ -45,7 +151,16 @@ impl Completer for HistoryCompleter<'_> {
 
 impl<'menu> HistoryCompleter<'menu> {
     pub fn new(history: &'menu dyn History) -> Self {
-        Self(history)
+        Self {
+            history,
+            pinned_first: false,
+        }
+    }
+
+    /// Lists the pinned entries, see [`HistoryItem::is_pinned()`], before all others
+    pub fn with_pinned_first(mut self, pinned_first: bool) -> Self {
+        self.pinned_first = pinned_first;
+        self
     }
 
     /// Assumes `line.len() <= pos` (i.e. `line` is the cursor-prefix slice).
@@ -86,6 +201,7 @@ mod tests {
             cwd: None,
             duration: None,
             exit_status: None,
+            tags: vec![],
             more_info: None,
         }
     }
@@ -155,4 +271,146 @@ mod tests {
         assert_eq!(actual, expected);
         Ok(())
     }
+
+    #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
+    #[test]
+    fn lists_pinned_entries_first() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        history.save(HistoryItem {
+            tags: vec![HistoryItem::PINNED_TAG.to_string()],
+            ..new_history_item("cargo publish")
+        })?;
+        for command_line in ["cargo build", "cargo test"] {
+            history.save(new_history_item(command_line))?;
+        }
+        let complete = |pinned_first| {
+            HistoryCompleter::new(&history)
+                .with_pinned_first(pinned_first)
+                .complete("cargo", 5)
+                .into_iter()
+                .map(|suggestion| suggestion.value)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            complete(false),
+            vec!["cargo test", "cargo build", "cargo publish"]
+        );
+        assert_eq!(
+            complete(true),
+            vec!["cargo publish", "cargo test", "cargo build"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pinned_first_works_with_histories_without_tags() -> Result<()> {
+        let mut history = FileBackedHistory::new(10)?;
+        history.save(new_history_item("ls"))?;
+        let mut sut = HistoryCompleter::new(&history).with_pinned_first(true);
+        assert_eq!(sut.complete("l", 1)[0].value, "ls");
+        Ok(())
+    }
+
+    fn searched_text(query: &SearchQuery) -> &str {
+        match &query.filter.command_line {
+            Some(CommandLineSearch::Substring(text)) => text,
+            _ => panic!("history menu queries search for a substring"),
+        }
+    }
+
+    #[test]
+    fn parses_filters_of_the_history_query() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 10, 12, 0, 0).unwrap();
+        let session = Some(HistorySessionId::new(7));
+        let query = parse_history_query(
+            "cargo host:build01 exit:0 session:current tag:ci after:2d before:2026-03-09 test",
+            session,
+            now,
+        );
+        assert_eq!(searched_text(&query), "cargo test");
+        assert_eq!(query.filter.hostname.as_deref(), Some("build01"));
+        assert_eq!(query.filter.exit_successful, Some(true));
+        assert_eq!(query.filter.session, session);
+        assert_eq!(query.filter.tags, vec!["ci".to_string()]);
+        assert_eq!(query.end_time, Some(now - Duration::days(2)));
+        let midnight = Local.with_ymd_and_hms(2026, 3, 9, 0, 0, 0).unwrap();
+        assert_eq!(query.start_time, Some(midnight.with_timezone(&Utc)));
+    }
+
+    #[rstest]
+    #[case("git  commit", "git  commit")]
+    #[case("exit:1 make", "make")]
+    #[case("echo a:b after:soon exit:", "echo a:b after:soon exit:")]
+    fn keeps_other_words_as_text(#[case] text: &str, #[case] searched: &str) {
+        let query = parse_history_query(text, None, Utc::now());
+        assert_eq!(searched_text(&query), searched);
+    }
+
+    #[test]
+    fn expands_the_home_directory_in_cwd_filters() {
+        let query = parse_history_query("cwd:~/src", None, Utc::now());
+        let home = crate::utils::path::home_dir().unwrap();
+        assert_eq!(
+            query.filter.cwd_prefix,
+            Some(format!("{}/src", home.display()))
+        );
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
+    #[test]
+    fn filters_the_sqlite_history() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        let entries = [
+            ("cargo build", "/src/reedline", 0, 50),
+            ("cargo build", "/tmp", 0, 2),
+            ("cargo test", "/src/nushell", 101, 1),
+            ("cargo test", "/src/reedline", 0, 1),
+        ];
+        for (command_line, cwd, exit_status, hours_ago) in entries {
+            history.save(HistoryItem {
+                cwd: Some(cwd.to_string()),
+                exit_status: Some(exit_status),
+                start_timestamp: Some(Utc::now() - Duration::hours(hours_ago)),
+                ..new_history_item(command_line)
+            })?;
+        }
+        let mut completer = HistoryCompleter::new(&history);
+        let mut complete = |line: &str| -> Vec<String> {
+            completer
+                .complete(line, line.len())
+                .into_iter()
+                .map(|suggestion| suggestion.value)
+                .collect()
+        };
+        assert_eq!(
+            complete("cwd:/src exit:0 cargo"),
+            vec!["cargo test", "cargo build"]
+        );
+        assert_eq!(complete("cargo after:1d cwd:/src"), vec!["cargo test"]);
+        assert_eq!(complete("before:1d cargo"), vec!["cargo build"]);
+        assert_eq!(complete("exit:1"), vec!["cargo test"]);
+        // not a directory below `/src`
+        assert!(complete("cwd:/src/reed").is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn searches_for_filters_as_text_if_the_history_cant_filter() -> Result<()> {
+        let mut history = FileBackedHistory::new(10)?;
+        for command_line in ["ssh host:build01", "cargo build", "echo tag:v1 after:1d"] {
+            history.save(new_history_item(command_line))?;
+        }
+        let mut completer = HistoryCompleter::new(&history);
+        let mut complete = |line: &str| -> Vec<String> {
+            completer
+                .complete(line, line.len())
+                .into_iter()
+                .map(|suggestion| suggestion.value)
+                .collect()
+        };
+        assert_eq!(complete("host:build01"), vec!["ssh host:build01"]);
+        assert_eq!(complete("tag:v1 after"), vec!["echo tag:v1 after:1d"]);
+        assert!(complete("cwd:/src cargo").is_empty());
+        Ok(())
+    }
 }
@@ -1,6 +1,15 @@
+mod background;
 mod base;
+mod combinators;
+mod context;
 mod default;
+mod file_path;
 pub(crate) mod history;
 
+pub use background::BackgroundCompleter;
 pub use base::{Completer, Span, Suggestion};
-pub use default::DefaultCompleter;
+pub use combinators::{FallbackCompleter, MappedCompleter, MergedCompleter, RoutedCompleter};
+pub use context::CompletionContext;
+pub(crate) use context::ContextCompleter;
+pub use default::{DefaultCompleter, MatchingStrategy};
+pub use file_path::FilePathCompleter;
@@ -6,7 +6,10 @@ use nu_ansi_term::{Color, Style};
 use crate::{enums::ReedlineRawEvent, CursorConfig};
 #[cfg(feature = "bashisms")]
 use crate::{
-    history::SearchFilter,
+    history::{
+        expansion::{find_last_command_shorthand, parse_designators, parse_quick_substitution},
+        SearchFilter, SearchOrder,
+    },
     menu_functions::{parse_selection_char, ParseAction},
 };
 #[cfg(feature = "external_printer")]
@@ -17,15 +20,15 @@ use {
 };
 use {
     crate::{
-        completion::{Completer, DefaultCompleter},
+        completion::{Completer, CompletionContext, DefaultCompleter},
         core_editor::Editor,
         edit_mode::{EditMode, Emacs},
         enums::{EventStatus, ReedlineEvent},
         highlighter::SimpleMatchHighlighter,
         hinter::Hinter,
         history::{
-            FileBackedHistory, History, HistoryCursor, HistoryItem, HistoryItemId,
-            HistoryNavigationQuery, HistorySessionId, SearchDirection, SearchQuery,
+            FileBackedHistory, FilteredHistory, History, HistoryCursor, HistoryFilter, HistoryItem,
+            HistoryItemId, HistoryNavigationQuery, HistorySessionId, SearchDirection, SearchQuery,
         },
         painting::{Painter, PainterSuspendedState, PromptLines, RenderSnapshot, W},
         prompt::{PromptEditMode, PromptHistorySearchStatus},
@@ -35,10 +38,10 @@ use {
             kitty::KittyProtocolGuard,
             semantic_prompt::{Osc133ClickEventsMarkers, SemanticPromptMarkers},
         },
-        utils::text_manipulation,
+        utils::{fuzzy::fuzzy_match, text_manipulation},
         AbbrExpandContext, EditCommand, ExampleHighlighter, Highlighter, LineBuffer, Menu,
-        MenuEvent, MouseButton, Prompt, PromptHistorySearch, ReedlineMenu, Signal, UndoBehavior,
-        ValidationResult, Validator,
+        MenuEvent, MouseButton, Prompt, PromptHistorySearch, ReedlineMenu, Signal, StyledText,
+        UndoBehavior, ValidationResult, Validator,
     },
     crossterm::{
         cursor::{SetCursorStyle, Show},
@@ -56,6 +59,7 @@ use {
         time::Duration,
         time::SystemTime,
     },
+    unicode_segmentation::UnicodeSegmentation,
 };
 
 // The POLL_WAIT is used to specify for how long the POLL should wait for
@@ -141,6 +145,16 @@ pub struct Reedline {
     history_exclusion_prefix: Option<String>,
     history_excluded_item: Option<HistoryItem>,
     history_cursor_on_excluded: bool,
+    history_filter: Option<Box<dyn HistoryFilter>>,
+    // Sync the history before every prompt and after every submitted line
+    history_shared: bool,
+    // Use fuzzy matching instead of substring/prefix search
+    history_fuzzy_search: bool,
+    history_error_callback: Option<Box<dyn FnMut(ReedlineError) + Send>>,
+    // Shown in place of the hint after the history failed
+    history_error_notice: Option<String>,
+    // Set by the first error of the history, which is skipped from then on
+    history_disabled: bool,
     input_mode: InputMode,
 
     // State of the painter after a `ReedlineEvent::ExecuteHostCommand` was requested, used after
@@ -170,6 +184,9 @@ pub struct Reedline {
     // Style used for visual selection
     visual_selection_style: Style,
 
+    // Style of the substring found while browsing the history with a substring search
+    history_substring_match_style: Style,
+
     // Showcase hints based on various strategies (history, language-completion, spellcheck, etc)
     hinter: Option<Box<dyn Hinter>>,
     hide_hints: bool,
@@ -274,6 +291,12 @@ impl Reedline {
             history_exclusion_prefix: None,
             history_excluded_item: None,
             history_cursor_on_excluded: false,
+            history_filter: None,
+            history_shared: false,
+            history_fuzzy_search: false,
+            history_error_callback: None,
+            history_error_notice: None,
+            history_disabled: false,
             input_mode: InputMode::Regular,
             suspended_state: None,
             last_render_snapshot: None,
@@ -285,6 +308,7 @@ impl Reedline {
             partial_completions: false,
             highlighter: buffer_highlighter,
             visual_selection_style,
+            history_substring_match_style: Style::new().bold().fg(Color::White).on(Color::Magenta),
             hinter,
             hide_hints: false,
             validator,
@@ -357,6 +381,17 @@ impl Reedline {
         Ok(())
     }
 
+    /// Whether the history is used, which stops after its first error
+    pub fn is_history_enabled(&self) -> bool {
+        !self.history_disabled
+    }
+
+    /// Use the history again after it was disabled by an error,
+    /// e.g. once another session released a lock
+    pub fn enable_history(&mut self) {
+        self.history_disabled = false;
+    }
+
     /// A builder to include a [`Hinter`] in your instance of the Reedline engine
     /// # Example
     /// ```rust
@@ -503,6 +538,14 @@ impl Reedline {
         self
     }
 
+    /// A builder that configures the style highlighting the search string in the buffer
+    /// while browsing the history with [`ReedlineEvent::PreviousHistorySubstring`]
+    #[must_use]
+    pub fn with_history_substring_match_style(mut self, style: Style) -> Self {
+        self.history_substring_match_style = style;
+        self
+    }
+
     /// A builder which configures the history for your instance of the Reedline engine
     /// # Example
     /// ```rust,no_run
@@ -523,6 +566,42 @@ impl Reedline {
         self
     }
 
+    /// A builder which enables fuzzy matching when searching the history
+    ///
+    /// When enabled, the reverse history search and the up-arrow search from a non-empty
+    /// line buffer use [`HistoryNavigationQuery::Fuzzy`] instead of substring or prefix
+    /// search, e.g. `gpfl` finds `git push --force-with-lease`.
+    #[must_use]
+    pub fn with_fuzzy_history_search(mut self, enable: bool) -> Self {
+        self.history_fuzzy_search = enable;
+        self
+    }
+
+    /// A builder which sets a callback for errors of the history
+    ///
+    /// Instead of failing the prompt, the editor shows a notice in place of the hint and
+    /// disables the history for the session, until [`Reedline::enable_history()`] is called.
+    /// The callback receives the error, e.g. to log it.
+    #[must_use]
+    pub fn with_history_error_callback(
+        mut self,
+        callback: impl FnMut(ReedlineError) + Send + 'static,
+    ) -> Self {
+        self.history_error_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// A builder which syncs the history before every prompt and after every submitted line,
+    /// so that entries of other sessions show up in the history navigation and hints right away
+    ///
+    /// Meant for histories in a shared mode, like zsh's `share_history`, see
+    /// [`FileBackedHistory::with_shared_history()`] and `SqliteBackedHistory::with_shared_history()`.
+    #[must_use]
+    pub fn with_shared_history(mut self, enable: bool) -> Self {
+        self.history_shared = enable;
+        self
+    }
+
     /// A builder which configures history exclusion for your instance of the Reedline engine
     /// # Example
     /// ```rust,no_run
@@ -544,6 +623,24 @@ impl Reedline {
         self
     }
 
+    /// A builder which sets a [`HistoryFilter`] deciding whether and how submitted lines are
+    /// saved to the history, e.g. to mask secrets with [`DefaultHistoryFilter`](crate::DefaultHistoryFilter)
+    ///
+    /// The filter is also applied to the entries shown in hints and menus.
+    /// Lines it drops are treated like lines with the history exclusion prefix.
+    /// # Example
+    /// ```rust
+    /// use reedline::{DefaultHistoryFilter, Reedline};
+    ///
+    /// let line_editor =
+    ///     Reedline::create().with_history_filter(Box::new(DefaultHistoryFilter::default()));
+    /// ```
+    #[must_use]
+    pub fn with_history_filter(mut self, filter: Box<dyn HistoryFilter>) -> Self {
+        self.history_filter = Some(filter);
+        self
+    }
+
     /// A builder that configures the validator for your instance of the Reedline engine
     /// # Example
     /// ```rust
@@ -692,10 +789,13 @@ impl Reedline {
 
     /// Output the complete [`History`] chronologically with numbering to the terminal
     pub fn print_history(&mut self) -> Result<()> {
-        let history: Vec<_> = self
+        let history: Vec<_> = match self
             .history
             .search(SearchQuery::everything(SearchDirection::Forward, None))
-            .expect("todo: error handling");
+        {
+            Ok(history) => history,
+            Err(err) => return self.print_history_error(err),
+        };
 
         for (i, entry) in history.iter().enumerate() {
             self.print_line(&format!("{}\t{}", i, entry.command_line))?;
@@ -705,13 +805,13 @@ impl Reedline {
 
     /// Output the complete [`History`] for this session, chronologically with numbering to the terminal
     pub fn print_history_session(&mut self) -> Result<()> {
-        let history: Vec<_> = self
-            .history
-            .search(SearchQuery::everything(
-                SearchDirection::Forward,
-                self.get_history_session_id(),
-            ))
-            .expect("todo: error handling");
+        let history: Vec<_> = match self.history.search(SearchQuery::everything(
+            SearchDirection::Forward,
+            self.get_history_session_id(),
+        )) {
+            Ok(history) => history,
+            Err(err) => return self.print_history_error(err),
+        };
 
         for (i, entry) in history.iter().enumerate() {
             self.print_line(&format!("{}\t{}", i, entry.command_line))?;
@@ -719,6 +819,12 @@ impl Reedline {
         Ok(())
     }
 
+    fn print_history_error(&mut self, err: ReedlineError) -> Result<()> {
+        self.report_history_error(err);
+        let notice = self.history_error_notice.take().unwrap_or_default();
+        self.print_line(notice.trim_start())
+    }
+
     /// Print the history session id
     pub fn print_history_session_id(&mut self) -> Result<()> {
         println!("History Session Id: {:?}", self.get_history_session_id());
@@ -836,6 +942,9 @@ impl Reedline {
             self.suspended_state = None;
         }
         self.hide_hints = false;
+        if self.history_shared {
+            self.sync_history_or_report();
+        }
 
         self.repaint(prompt)?;
 
@@ -888,7 +997,14 @@ impl Reedline {
                     menu.update_values(
                         &mut self.editor,
                         self.completer.as_mut(),
-                        self.history.as_ref(),
+                        &CompletionContext::new(
+                            &FilteredHistory::new(
+                                self.history.as_mut(),
+                                self.history_filter.as_deref_mut(),
+                            ),
+                            self.cwd.as_deref(),
+                            self.edit_mode.edit_mode(),
+                        ),
                     );
                     self.repaint(prompt)?;
                 }
@@ -1136,21 +1252,20 @@ impl Reedline {
                 // A handled Event causes a repaint
                 Ok(EventStatus::Handled)
             }
-            ReedlineEvent::PreviousHistory | ReedlineEvent::Up | ReedlineEvent::SearchHistory => {
-                self.history_cursor
-                    .back(self.history.as_ref())
-                    .expect("todo: error handling");
+            ReedlineEvent::PreviousHistory
+            | ReedlineEvent::PreviousHistorySubstring
+            | ReedlineEvent::Up
+            | ReedlineEvent::SearchHistory => {
+                self.history_cursor_back();
                 Ok(EventStatus::Handled)
             }
-            ReedlineEvent::NextHistory | ReedlineEvent::Down => {
-                self.history_cursor
-                    .forward(self.history.as_ref())
-                    .expect("todo: error handling");
+            ReedlineEvent::NextHistory
+            | ReedlineEvent::NextHistorySubstring
+            | ReedlineEvent::Down => {
+                self.history_cursor_forward();
                 // Hacky way to ensure that we don't fall of into failed search going forward
                 if self.history_cursor.string_at_cursor().is_none() {
-                    self.history_cursor
-                        .back(self.history.as_ref())
-                        .expect("todo: error handling");
+                    self.history_cursor_back();
                 }
                 Ok(EventStatus::Handled)
             }
@@ -1195,7 +1310,14 @@ impl Reedline {
                             menu.update_values(
                                 &mut self.editor,
                                 self.completer.as_mut(),
-                                self.history.as_ref(),
+                                &CompletionContext::new(
+                                    &FilteredHistory::new(
+                                        self.history.as_mut(),
+                                        self.history_filter.as_deref_mut(),
+                                    ),
+                                    self.cwd.as_deref(),
+                                    self.edit_mode.edit_mode(),
+                                ),
                             );
 
                             if menu.get_values().len() == 1 {
@@ -1208,7 +1330,14 @@ impl Reedline {
                                 self.quick_completions,
                                 &mut self.editor,
                                 self.completer.as_mut(),
-                                self.history.as_ref(),
+                                &CompletionContext::new(
+                                    &FilteredHistory::new(
+                                        self.history.as_mut(),
+                                        self.history_filter.as_deref_mut(),
+                                    ),
+                                    self.cwd.as_deref(),
+                                    self.edit_mode.edit_mode(),
+                                ),
                             )
                         {
                             return Ok(EventStatus::Handled);
@@ -1229,7 +1358,14 @@ impl Reedline {
                                 self.quick_completions,
                                 &mut self.editor,
                                 self.completer.as_mut(),
-                                self.history.as_ref(),
+                                &CompletionContext::new(
+                                    &FilteredHistory::new(
+                                        self.history.as_mut(),
+                                        self.history_filter.as_deref_mut(),
+                                    ),
+                                    self.cwd.as_deref(),
+                                    self.edit_mode.edit_mode(),
+                                ),
                             );
                         }
                         menu.menu_event(MenuEvent::NextElement);
@@ -1419,7 +1555,14 @@ impl Reedline {
                                 menu.update_values(
                                     &mut self.editor,
                                     self.completer.as_mut(),
-                                    self.history.as_ref(),
+                                    &CompletionContext::new(
+                                        &FilteredHistory::new(
+                                            self.history.as_mut(),
+                                            self.history_filter.as_deref_mut(),
+                                        ),
+                                        self.cwd.as_deref(),
+                                        self.edit_mode.edit_mode(),
+                                    ),
                                 );
                                 if let Some(&EditCommand::Complete) = commands.first() {
                                     if menu.get_values().len() == 1 {
@@ -1430,7 +1573,14 @@ impl Reedline {
                                             self.quick_completions,
                                             &mut self.editor,
                                             self.completer.as_mut(),
-                                            self.history.as_ref(),
+                                            &CompletionContext::new(
+                                                &FilteredHistory::new(
+                                                    self.history.as_mut(),
+                                                    self.history_filter.as_deref_mut(),
+                                                ),
+                                                self.cwd.as_deref(),
+                                                self.edit_mode.edit_mode(),
+                                            ),
                                         )
                                     {
                                         return Ok(EventStatus::Handled);
@@ -1465,6 +1615,16 @@ impl Reedline {
                 self.next_history();
                 Ok(EventStatus::Handled)
             }
+            ReedlineEvent::PreviousHistorySubstring => {
+                self.start_history_substring_search();
+                self.previous_history();
+                Ok(EventStatus::Handled)
+            }
+            ReedlineEvent::NextHistorySubstring => {
+                self.start_history_substring_search();
+                self.next_history();
+                Ok(EventStatus::Handled)
+            }
             ReedlineEvent::Up => {
                 self.up_command();
                 Ok(EventStatus::Handled)
@@ -1577,6 +1737,46 @@ impl Reedline {
             .for_each(|menu| menu.menu_event(MenuEvent::Deactivate));
     }
 
+    /// Reports an error of the history and disables it for the session
+    ///
+    /// Shows the error in place of the hint until the next edit and passes it to the
+    /// callback set with [`Reedline::with_history_error_callback()`]. Navigating, searching,
+    /// saving and syncing skip the history until [`Reedline::enable_history()`] is called.
+    fn report_history_error(&mut self, err: ReedlineError) {
+        self.history_disabled = true;
+        self.history_error_notice = Some(format!("  history error: {err}"));
+        if let Some(callback) = self.history_error_callback.as_mut() {
+            callback(err);
+        }
+    }
+
+    fn history_cursor_back(&mut self) {
+        if self.history_disabled {
+            return;
+        }
+        if let Err(err) = self.history_cursor.back(self.history.as_ref()) {
+            self.report_history_error(err);
+        }
+    }
+
+    fn history_cursor_forward(&mut self) {
+        if self.history_disabled {
+            return;
+        }
+        if let Err(err) = self.history_cursor.forward(self.history.as_ref()) {
+            self.report_history_error(err);
+        }
+    }
+
+    fn sync_history_or_report(&mut self) {
+        if self.history_disabled {
+            return;
+        }
+        if let Err(err) = self.history.sync() {
+            self.report_history_error(err.into());
+        }
+    }
+
     fn previous_history(&mut self) {
         self.history_cursor_on_excluded = false;
         if self.input_mode != InputMode::HistoryTraversal {
@@ -1592,13 +1792,12 @@ impl Reedline {
         }
 
         if !self.history_cursor_on_excluded {
-            self.history_cursor
-                .back(self.history.as_ref())
-                .expect("todo: error handling");
+            self.history_cursor_back();
         }
         self.update_buffer_from_history();
         self.editor.move_to_start(false);
         self.editor.move_to_line_end(false);
+        self.move_to_history_substring_match();
         // History navigation positions the cursor outside the command path, so
         // settle it under the rest policy (vi-normal must not rest past the line).
         self.editor.commit_cursor();
@@ -1619,9 +1818,7 @@ impl Reedline {
             self.history_cursor_on_excluded = false;
         } else {
             let cursor_was_on_item = self.history_cursor.string_at_cursor().is_some();
-            self.history_cursor
-                .forward(self.history.as_ref())
-                .expect("todo: error handling");
+            self.history_cursor_forward();
 
             if cursor_was_on_item
                 && self.history_cursor.string_at_cursor().is_none()
@@ -1636,6 +1833,7 @@ impl Reedline {
         }
         self.update_buffer_from_history();
         self.editor.move_to_end(false);
+        self.move_to_history_substring_match();
         // See `previous_history`: settle the out-of-band cursor under the policy.
         self.editor.commit_cursor();
         self.editor
@@ -1659,7 +1857,60 @@ impl Reedline {
             // Continuing with typing will leave the search
             // but next invocation of this method will start the next search
             let buffer = self.editor.get_buffer().to_string();
-            HistoryNavigationQuery::PrefixSearch(buffer)
+            if self.history_fuzzy_search {
+                HistoryNavigationQuery::Fuzzy(buffer)
+            } else {
+                HistoryNavigationQuery::PrefixSearch(buffer)
+            }
+        }
+    }
+
+    /// Start browsing the history for entries containing the current buffer
+    ///
+    /// Keeps an ongoing traversal going, and falls back to the regular traversal of
+    /// [`Self::previous_history`] and [`Self::next_history`] for an empty buffer.
+    fn start_history_substring_search(&mut self) {
+        if self.input_mode != InputMode::HistoryTraversal && !self.editor.is_empty() {
+            self.input_mode = InputMode::HistoryTraversal;
+            self.history_cursor_on_excluded = false;
+            self.history_cursor = HistoryCursor::new(
+                HistoryNavigationQuery::SubstringSearch(self.editor.get_buffer().to_string()),
+                self.get_history_session_id(),
+            );
+        }
+    }
+
+    /// The first match of the substring search in the buffer, if it shows a found entry
+    fn history_substring_match(&self) -> Option<(usize, usize)> {
+        if self.input_mode != InputMode::HistoryTraversal || self.history_cursor_on_excluded {
+            return None;
+        }
+        match self.history_cursor.get_navigation() {
+            HistoryNavigationQuery::SubstringSearch(substring) if !substring.is_empty() => {
+                self.history_cursor.string_at_cursor()?;
+                let start = self.editor.get_buffer().find(&substring)?;
+                Some((start, start + substring.len()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Place the cursor at the start of the substring found in a history entry
+    fn move_to_history_substring_match(&mut self) {
+        if let Some((position, _)) = self.history_substring_match() {
+            self.editor.run_edit_command(&EditCommand::MoveToPosition {
+                position,
+                select: false,
+            });
+        }
+    }
+
+    /// The navigation query used by the reverse history search for a search string
+    fn history_search_query(&self, search: String) -> HistoryNavigationQuery {
+        if self.history_fuzzy_search {
+            HistoryNavigationQuery::Fuzzy(search)
+        } else {
+            HistoryNavigationQuery::SubstringSearch(search)
         }
     }
 
@@ -1668,7 +1919,7 @@ impl Reedline {
     /// This mode uses a separate prompt and handles keybindings slightly differently!
     fn enter_history_search(&mut self) {
         self.history_cursor = HistoryCursor::new(
-            HistoryNavigationQuery::SubstringSearch("".to_string()),
+            self.history_search_query(String::new()),
             self.get_history_session_id(),
         );
         self.input_mode = InputMode::HistorySearch;
@@ -1682,35 +1933,35 @@ impl Reedline {
             match command {
                 EditCommand::InsertChar(c) => {
                     let navigation = self.history_cursor.get_navigation();
-                    if let HistoryNavigationQuery::SubstringSearch(mut substring) = navigation {
+                    if let HistoryNavigationQuery::SubstringSearch(mut substring)
+                    | HistoryNavigationQuery::Fuzzy(mut substring) = navigation
+                    {
                         substring.push(*c);
                         self.history_cursor = HistoryCursor::new(
-                            HistoryNavigationQuery::SubstringSearch(substring),
+                            self.history_search_query(substring),
                             self.get_history_session_id(),
                         );
                     } else {
                         self.history_cursor = HistoryCursor::new(
-                            HistoryNavigationQuery::SubstringSearch(String::from(*c)),
+                            self.history_search_query(String::from(*c)),
                             self.get_history_session_id(),
                         );
                     }
-                    self.history_cursor
-                        .back(self.history.as_mut())
-                        .expect("todo: error handling");
+                    self.history_cursor_back();
                 }
                 EditCommand::Backspace => {
                     let navigation = self.history_cursor.get_navigation();
 
-                    if let HistoryNavigationQuery::SubstringSearch(substring) = navigation {
+                    if let HistoryNavigationQuery::SubstringSearch(substring)
+                    | HistoryNavigationQuery::Fuzzy(substring) = navigation
+                    {
                         let new_substring = text_manipulation::remove_last_grapheme(&substring);
 
                         self.history_cursor = HistoryCursor::new(
-                            HistoryNavigationQuery::SubstringSearch(new_substring.to_string()),
+                            self.history_search_query(new_substring.to_string()),
                             self.get_history_session_id(),
                         );
-                        self.history_cursor
-                            .back(self.history.as_mut())
-                            .expect("todo: error handling");
+                        self.history_cursor_back();
                     }
                 }
                 _ => {
@@ -1744,16 +1995,17 @@ impl Reedline {
                         .set_line_buffer(original, UndoBehavior::HistoryNavigation);
                 }
             }
-            HistoryNavigationQuery::PrefixSearch(prefix) => {
-                if let Some(prefix_result) = self.history_cursor.string_at_cursor() {
+            HistoryNavigationQuery::PrefixSearch(search)
+            | HistoryNavigationQuery::SubstringSearch(search)
+            | HistoryNavigationQuery::Fuzzy(search) => {
+                if let Some(search_result) = self.history_cursor.string_at_cursor() {
                     self.editor
-                        .set_buffer(prefix_result, UndoBehavior::HistoryNavigation);
+                        .set_buffer(search_result, UndoBehavior::HistoryNavigation);
                 } else {
                     self.editor
-                        .set_buffer(prefix, UndoBehavior::HistoryNavigation);
+                        .set_buffer(search, UndoBehavior::HistoryNavigation);
                 }
             }
-            HistoryNavigationQuery::SubstringSearch(_) => todo!(),
         }
     }
 
@@ -1771,6 +2023,7 @@ impl Reedline {
         // vi `Esc`→normal `MoveLeft`. The commands settle the cursor themselves,
         // and the pre-paint `set_edit_mode` makes the final commit.
         self.editor.sync_edit_mode(self.edit_mode.edit_mode());
+        self.history_error_notice = None;
 
         // Run the commands over the edit buffer
         for command in commands {
@@ -1898,14 +2151,38 @@ impl Reedline {
     }
 
     #[cfg(feature = "bashisms")]
-    /// Parses the ! command to replace entries from the history
+    /// Parses the ! command and the `^old^new^` quick substitution to replace entries from the history
+    ///
+    /// Event designators like `!!` or `!-2` may be followed by word designators and modifiers,
+    /// e.g. `!!:2`, `!$:h` or `!-2:s/foo/bar/`.
     fn parse_bang_command(&mut self) -> Option<ReedlineEvent> {
         let buffer = self.editor.get_buffer();
+        if let Some((designators, size)) = parse_quick_substitution(buffer) {
+            if !self
+                .highlighter
+                .should_expand_abbr(buffer, 0, AbbrExpandContext::BangExpansion)
+            {
+                return None;
+            }
+            let expansion = designators.expand(&self.last_history_command_line()?)?;
+            return Some(Self::bang_expansion_edits(0, size, expansion));
+        }
+
         let parsed = parse_selection_char(buffer, '!');
-        let parsed_prefix = parsed.prefix.unwrap_or_default().to_string();
+        let shorthand = find_last_command_shorthand(buffer)
+            .filter(|&position| parsed.marker.is_none() || position < parsed.remainder.len());
+        let remainder = shorthand.map_or(parsed.remainder, |position| &buffer[..position]);
+        // the prefix of `!prefix` ends where its designators start
+        let parsed_prefix = parsed
+            .prefix
+            .unwrap_or_default()
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_string();
         let parsed_marker = parsed.marker.unwrap_or_default().to_string();
 
-        if let Some(last) = parsed.remainder.chars().last() {
+        if let Some(last) = remainder.chars().last() {
             if last != ' ' {
                 return None;
             }
@@ -1913,84 +2190,73 @@ impl Reedline {
 
         if !self.highlighter.should_expand_abbr(
             buffer,
-            parsed.remainder.len(),
+            remainder.len(),
             AbbrExpandContext::BangExpansion,
         ) {
             return None;
         }
 
-        let history_result = parsed
-            .index
-            .zip(parsed.marker)
-            .and_then(|(index, indicator)| match parsed.action {
-                ParseAction::LastCommand => self
-                    .history
-                    .search(SearchQuery {
-                        direction: SearchDirection::Backward,
-                        start_time: None,
-                        end_time: None,
-                        start_id: None,
-                        end_id: None,
-                        limit: Some(1), // fetch the latest one entries
-                        filter: SearchFilter::anything(self.get_history_session_id()),
-                    })
-                    .unwrap_or_else(|_| Vec::new())
-                    .get(index.saturating_sub(1))
-                    .map(|history| {
-                        (
-                            parsed.remainder.len(),
-                            indicator.len(),
-                            history.command_line.clone(),
-                        )
-                    }),
-                ParseAction::BackwardSearch => self
-                    .history
-                    .search(SearchQuery {
-                        direction: SearchDirection::Backward,
-                        start_time: None,
-                        end_time: None,
-                        start_id: None,
-                        end_id: None,
-                        limit: Some(index as i64), // fetch the latest n entries
-                        filter: SearchFilter::anything(self.get_history_session_id()),
-                    })
-                    .unwrap_or_else(|_| Vec::new())
-                    .get(index.saturating_sub(1))
-                    .map(|history| {
-                        (
-                            parsed.remainder.len(),
-                            indicator.len(),
-                            history.command_line.clone(),
-                        )
-                    }),
-                ParseAction::BackwardPrefixSearch => {
-                    let history_search_by_session = self
+        let history_result = if let Some(position) = shorthand {
+            self.last_history_command_line()
+                .map(|command_line| (position, 1, command_line))
+        } else {
+            parsed
+                .index
+                .zip(parsed.marker)
+                .and_then(|(index, indicator)| match parsed.action {
+                    ParseAction::LastCommand => self
                         .history
-                        .search(SearchQuery::last_with_prefix_and_cwd(
-                            parsed.prefix.unwrap().to_string(),
-                            self.cwd.clone().unwrap_or_else(|| {
-                                std::env::current_dir()
-                                    .unwrap_or_default()
-                                    .to_string_lossy()
-                                    .to_string()
-                            }),
-                            self.get_history_session_id(),
-                        ))
+                        .search(SearchQuery {
+                            direction: SearchDirection::Backward,
+                            order: SearchOrder::Chronological,
+                            start_time: None,
+                            end_time: None,
+                            start_id: None,
+                            end_id: None,
+                            limit: Some(1), // fetch the latest one entries
+                            filter: SearchFilter::anything(self.get_history_session_id()),
+                        })
+                        .unwrap_or_else(|_| Vec::new())
+                        .get(index.saturating_sub(1))
+                        .map(|history| {
+                            (
+                                parsed.remainder.len(),
+                                indicator.len(),
+                                history.command_line.clone(),
+                            )
+                        }),
+                    ParseAction::BackwardSearch => self
+                        .history
+                        .search(SearchQuery {
+                            direction: SearchDirection::Backward,
+                            order: SearchOrder::Chronological,
+                            start_time: None,
+                            end_time: None,
+                            start_id: None,
+                            end_id: None,
+                            limit: Some(index as i64), // fetch the latest n entries
+                            filter: SearchFilter::anything(self.get_history_session_id()),
+                        })
                         .unwrap_or_else(|_| Vec::new())
                         .get(index.saturating_sub(1))
                         .map(|history| {
                             (
                                 parsed.remainder.len(),
-                                parsed_prefix.len() + parsed_marker.len(),
+                                indicator.len(),
                                 history.command_line.clone(),
                             )
-                        });
-                    // If we don't find any history searching by session id, then let's
-                    // search everything, otherwise use the result from the session search
-                    if history_search_by_session.is_none() {
-                        self.history
-                            .search(SearchQuery::last_with_prefix(
+                        }),
+                    ParseAction::BackwardPrefixSearch => {
+                        let history_search_by_session = self
+                            .history
+                            .search(SearchQuery::last_with_prefix_and_cwd(
                                 parsed_prefix.clone(),
+                                self.cwd.clone().unwrap_or_else(|| {
+                                    std::env::current_dir()
+                                        .unwrap_or_default()
+                                        .to_string_lossy()
+                                        .to_string()
+                                }),
                                 self.get_history_session_id(),
                             ))
                             .unwrap_or_else(|_| Vec::new())
@@ -2001,56 +2267,89 @@ impl Reedline {
                                     parsed_prefix.len() + parsed_marker.len(),
                                     history.command_line.clone(),
                                 )
-                            })
-                    } else {
-                        history_search_by_session
+                            });
+                        // If we don't find any history searching by session id, then let's
+                        // search everything, otherwise use the result from the session search
+                        if history_search_by_session.is_none() {
+                            self.history
+                                .search(SearchQuery::last_with_prefix(
+                                    parsed_prefix.clone(),
+                                    self.get_history_session_id(),
+                                ))
+                                .unwrap_or_else(|_| Vec::new())
+                                .get(index.saturating_sub(1))
+                                .map(|history| {
+                                    (
+                                        parsed.remainder.len(),
+                                        parsed_prefix.len() + parsed_marker.len(),
+                                        history.command_line.clone(),
+                                    )
+                                })
+                        } else {
+                            history_search_by_session
+                        }
                     }
-                }
-                ParseAction::ForwardSearch => self
-                    .history
-                    .search(SearchQuery {
-                        direction: SearchDirection::Forward,
-                        start_time: None,
-                        end_time: None,
-                        start_id: None,
-                        end_id: None,
-                        limit: Some((index + 1) as i64), // fetch the oldest n entries
-                        filter: SearchFilter::anything(self.get_history_session_id()),
-                    })
-                    .unwrap_or_else(|_| Vec::new())
-                    .get(index)
-                    .map(|history| {
-                        (
-                            parsed.remainder.len(),
-                            indicator.len(),
-                            history.command_line.clone(),
-                        )
-                    }),
-                ParseAction::LastToken => self
-                    .history
-                    .search(SearchQuery::last_with_search(SearchFilter::anything(
-                        self.get_history_session_id(),
-                    )))
-                    .unwrap_or_else(|_| Vec::new())
-                    .first()
-                    //BUGBUG: This returns the wrong results with paths with spaces in them
-                    .and_then(|history| history.command_line.split_whitespace().next_back())
-                    .map(|token| (parsed.remainder.len(), indicator.len(), token.to_string())),
-            });
-
-        if let Some((start, size, history)) = history_result {
-            let edits = vec![
-                EditCommand::MoveToPosition {
-                    position: start,
-                    select: false,
-                },
-                EditCommand::ReplaceChars(size, history),
-            ];
+                    ParseAction::ForwardSearch => self
+                        .history
+                        .search(SearchQuery {
+                            direction: SearchDirection::Forward,
+                            order: SearchOrder::Chronological,
+                            start_time: None,
+                            end_time: None,
+                            start_id: None,
+                            end_id: None,
+                            limit: Some((index + 1) as i64), // fetch the oldest n entries
+                            filter: SearchFilter::anything(self.get_history_session_id()),
+                        })
+                        .unwrap_or_else(|_| Vec::new())
+                        .get(index)
+                        .map(|history| {
+                            (
+                                parsed.remainder.len(),
+                                indicator.len(),
+                                history.command_line.clone(),
+                            )
+                        }),
+                    // the `$` is parsed as word designator
+                    ParseAction::LastToken => self
+                        .last_history_command_line()
+                        .map(|command_line| (parsed.remainder.len(), 1, command_line)),
+                })
+        };
 
-            Some(ReedlineEvent::Edit(edits))
-        } else {
-            None
+        let (start, size, command_line) = history_result?;
+        let (designators, designators_size) = parse_designators(&buffer[start + size..]);
+        if shorthand.is_some() && designators_size == 0 {
+            return None;
         }
+        let expansion = designators.expand(&command_line)?;
+        Some(Self::bang_expansion_edits(
+            start,
+            size + designators_size,
+            expansion,
+        ))
+    }
+
+    #[cfg(feature = "bashisms")]
+    fn last_history_command_line(&self) -> Option<String> {
+        self.history
+            .search(SearchQuery::last_with_search(SearchFilter::anything(
+                self.get_history_session_id(),
+            )))
+            .unwrap_or_else(|_| Vec::new())
+            .pop()
+            .map(|history| history.command_line)
+    }
+
+    #[cfg(feature = "bashisms")]
+    fn bang_expansion_edits(start: usize, size: usize, expansion: String) -> ReedlineEvent {
+        ReedlineEvent::Edit(vec![
+            EditCommand::MoveToPosition {
+                position: start,
+                select: false,
+            },
+            EditCommand::ReplaceChars(size, expansion),
+        ])
     }
 
     fn open_editor(&mut self) -> Result<()> {
@@ -2107,7 +2406,9 @@ impl Reedline {
     fn history_search_paint(&mut self, prompt: &dyn Prompt) -> Result<()> {
         let navigation = self.history_cursor.get_navigation();
 
-        if let HistoryNavigationQuery::SubstringSearch(substring) = navigation {
+        if let HistoryNavigationQuery::SubstringSearch(substring)
+        | HistoryNavigationQuery::Fuzzy(substring) = navigation.clone()
+        {
             let status =
                 if !substring.is_empty() && self.history_cursor.string_at_cursor().is_none() {
                     PromptHistorySearchStatus::Failing
@@ -2121,8 +2422,23 @@ impl Reedline {
 
             // Highlight matches
             let res_string = if self.use_ansi_coloring {
-                let match_highlighter = SimpleMatchHighlighter::new(substring);
-                let styled = match_highlighter.highlight(&res_string, 0);
+                let styled = if let HistoryNavigationQuery::Fuzzy(_) = navigation {
+                    let match_style = Style::new().fg(Color::Green);
+                    let mut styled = StyledText::new();
+                    styled.push((Style::default(), res_string.clone()));
+                    if let Some(fuzzy) = fuzzy_match(&substring, &res_string) {
+                        for (idx, (start, grapheme)) in
+                            res_string.grapheme_indices(true).enumerate()
+                        {
+                            if fuzzy.indices.contains(&idx) {
+                                styled.style_range(start, start + grapheme.len(), match_style);
+                            }
+                        }
+                    }
+                    styled
+                } else {
+                    SimpleMatchHighlighter::new(substring).highlight(&res_string, 0)
+                };
                 styled.render_simple()
             } else {
                 res_string
@@ -2160,6 +2476,9 @@ impl Reedline {
         let mut styled_text = self
             .highlighter
             .highlight(buffer_to_paint, cursor_position_in_buffer);
+        if let Some((from, to)) = self.history_substring_match() {
+            styled_text.style_range(from, to, self.history_substring_match_style);
+        }
         if let Some((from, to)) = self.editor.get_selection() {
             styled_text.style_range(from, to, self.visual_selection_style);
         }
@@ -2171,12 +2490,21 @@ impl Reedline {
             self.painter.semantic_markers(),
         );
 
-        let hint: String = if self.hints_active() {
+        let hint: String = if let Some(notice) = &self.history_error_notice {
+            if self.use_ansi_coloring {
+                Color::Red.paint(notice).to_string()
+            } else {
+                notice.clone()
+            }
+        } else if self.hints_active() {
             self.hinter.as_mut().map_or_else(String::new, |hinter| {
                 hinter.handle(
                     buffer_to_paint,
                     cursor_position_in_buffer,
-                    self.history.as_ref(),
+                    &FilteredHistory::new(
+                        self.history.as_mut(),
+                        self.history_filter.as_deref_mut(),
+                    ),
                     self.use_ansi_coloring,
                     &self.cwd.clone().unwrap_or_else(|| {
                         std::env::current_dir()
@@ -2213,7 +2541,14 @@ impl Reedline {
                 menu.update_working_details(
                     &mut self.editor,
                     self.completer.as_mut(),
-                    self.history.as_ref(),
+                    &CompletionContext::new(
+                        &FilteredHistory::new(
+                            self.history.as_mut(),
+                            self.history_filter.as_deref_mut(),
+                        ),
+                        self.cwd.as_deref(),
+                        self.edit_mode.edit_mode(),
+                    ),
                     &self.painter,
                 );
             }
@@ -2343,23 +2678,38 @@ impl Reedline {
         } else {
             self.repaint(prompt)?;
         }
-        if !buffer.is_empty() {
+        if self.history_disabled {
+            self.history_last_run_id = None;
+        } else if !buffer.is_empty() {
             let mut entry = HistoryItem::from_command_line(&buffer);
             entry.session_id = self.get_history_session_id();
 
-            if self
+            let excluded = self
                 .history_exclusion_prefix
                 .as_ref()
                 .map(|prefix| buffer.starts_with(prefix))
-                .unwrap_or(false)
-            {
+                .unwrap_or(false);
+            let admitted = match &self.history_filter {
+                _ if excluded => None,
+                Some(filter) => filter.filter(entry.clone()),
+                None => Some(entry.clone()),
+            };
+            if let Some(admitted) = admitted {
+                match self.history.save(admitted) {
+                    Ok(saved) => self.history_last_run_id = saved.id,
+                    Err(err) => {
+                        self.history_last_run_id = None;
+                        self.report_history_error(err);
+                    }
+                }
+                self.history_excluded_item = None;
+                if self.history_shared {
+                    self.sync_history_or_report();
+                }
+            } else {
                 entry.id = Some(Self::FILTERED_ITEM_ID);
                 self.history_last_run_id = entry.id;
                 self.history_excluded_item = Some(entry);
-            } else {
-                entry = self.history.save(entry).expect("todo: error handling");
-                self.history_last_run_id = entry.id;
-                self.history_excluded_item = None;
             }
         }
         self.run_edit_commands(&[EditCommand::Clear]);
@@ -2942,6 +3292,45 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[case("!!", "cp -r \"my dir\" /tmp/backup/old.tar")]
+    #[case("ls !$", "ls /tmp/backup/old.tar")]
+    #[case("ls !$:h", "ls /tmp/backup")]
+    #[case("echo !^", "echo -r")]
+    #[case("echo !*", "echo -r \"my dir\" /tmp/backup/old.tar")]
+    #[case("cd !:2", "cd \"my dir\"")]
+    #[case("!!:s/old/new/ -v", "cp -r \"my dir\" /tmp/backup/new.tar -v")]
+    #[case("tar xf !-1:3:t", "tar xf old.tar")]
+    #[case("!c:0", "cp")]
+    #[case("^old^new^", "cp -r \"my dir\" /tmp/backup/new.tar")]
+    #[case("^my dir^your dir", "cp -r \"your dir\" /tmp/backup/old.tar")]
+    #[cfg(feature = "bashisms")]
+    fn bang_expansion_previews_designators_and_modifiers(
+        #[case] buffer: &str,
+        #[case] expanded: &str,
+    ) {
+        let mut reedline =
+            reedline_with_history_default(&["ls", "cp -r \"my dir\" /tmp/backup/old.tar"]);
+        set_buffer_at_end(&mut reedline, buffer);
+        let Some(ReedlineEvent::Edit(edits)) = reedline.parse_bang_command() else {
+            panic!("{buffer} should expand");
+        };
+        reedline.run_edit_commands(&edits);
+        assert_eq!(reedline.editor.get_buffer(), expanded);
+    }
+
+    #[rstest]
+    #[case("echo !:5")]
+    #[case("!!:s/missing/found/")]
+    #[case("^missing^found^")]
+    #[case("echo !:")]
+    #[cfg(feature = "bashisms")]
+    fn bang_expansion_fails_like_bash(#[case] buffer: &str) {
+        let mut reedline = reedline_with_history_default(&["cp a b"]);
+        set_buffer_at_end(&mut reedline, buffer);
+        assert!(reedline.parse_bang_command().is_none());
+    }
+
     #[rstest]
     #[case("")]
     #[case("line of text")]
@@ -2961,6 +3350,151 @@ mod tests {
         assert_eq!(reedline.editor.line_buffer().insertion_point(), 0);
     }
 
+    #[test]
+    fn history_substring_search_browses_entries_containing_the_buffer() {
+        let mut reedline = Reedline::create();
+        for command_line in ["cargo build", "git status", "echo cargo", "ls"] {
+            reedline
+                .history
+                .save(HistoryItem::from_command_line(command_line))
+                .unwrap();
+        }
+        reedline.run_edit_commands(&[EditCommand::InsertString("cargo".to_string())]);
+        let buffer_and_match = |reedline: &Reedline| {
+            (
+                reedline.editor.get_buffer().to_string(),
+                reedline.editor.insertion_point(),
+                reedline.history_substring_match(),
+            )
+        };
+
+        reedline.start_history_substring_search();
+        reedline.previous_history();
+        assert_eq!(
+            buffer_and_match(&reedline),
+            ("echo cargo".to_string(), 5, Some((5, 10)))
+        );
+        reedline.previous_history();
+        assert_eq!(
+            buffer_and_match(&reedline),
+            ("cargo build".to_string(), 0, Some((0, 5)))
+        );
+        reedline.next_history();
+        reedline.next_history();
+        assert_eq!(buffer_and_match(&reedline), ("cargo".to_string(), 5, None));
+        assert_eq!(reedline.input_mode, InputMode::Regular);
+    }
+
+    /// Fails while `failing` is set, like a database locked by another session
+    #[derive(Default)]
+    struct FlakyHistory {
+        history: FileBackedHistory,
+        failing: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl FlakyHistory {
+        fn check(&self) -> crate::Result<()> {
+            if self.failing.load(std::sync::atomic::Ordering::SeqCst) {
+                Err(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                    "database is locked",
+                )))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl History for FlakyHistory {
+        fn save(&mut self, h: HistoryItem) -> crate::Result<HistoryItem> {
+            self.check()?;
+            self.history.save(h)
+        }
+        fn load(&self, id: HistoryItemId) -> crate::Result<HistoryItem> {
+            self.check()?;
+            self.history.load(id)
+        }
+        fn count(&self, query: SearchQuery) -> crate::Result<i64> {
+            self.check()?;
+            self.history.count(query)
+        }
+        fn search(&self, query: SearchQuery) -> crate::Result<Vec<HistoryItem>> {
+            self.check()?;
+            self.history.search(query)
+        }
+        fn update(
+            &mut self,
+            id: HistoryItemId,
+            updater: &dyn Fn(HistoryItem) -> HistoryItem,
+        ) -> crate::Result<()> {
+            self.check()?;
+            self.history.update(id, updater)
+        }
+        fn clear(&mut self) -> crate::Result<()> {
+            self.check()?;
+            self.history.clear()
+        }
+        fn delete(&mut self, h: HistoryItemId) -> crate::Result<()> {
+            self.check()?;
+            self.history.delete(h)
+        }
+        fn sync(&mut self) -> std::io::Result<()> {
+            self.check()
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            self.history.sync()
+        }
+        fn session(&self) -> Option<HistorySessionId> {
+            None
+        }
+    }
+
+    #[test]
+    fn history_errors_are_reported_once_and_disable_the_history() {
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let received = errors.clone();
+        let mut history = FlakyHistory::default();
+        history
+            .history
+            .save(HistoryItem::from_command_line("cargo build"))
+            .unwrap();
+        let failing = history.failing.clone();
+        failing.store(true, std::sync::atomic::Ordering::SeqCst);
+        let mut reedline = Reedline::create()
+            .with_history(Box::new(history))
+            .with_shared_history(true)
+            .with_history_error_callback(move |err| received.lock().unwrap().push(err.to_string()));
+        reedline.painter.force_prompt_anchored_for_test(0);
+
+        reedline.previous_history();
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert!(!reedline.is_history_enabled());
+        assert!(reedline
+            .history_error_notice
+            .as_deref()
+            .unwrap()
+            .contains("database is locked"));
+        assert_eq!(reedline.current_buffer_contents(), "");
+        reedline.run_edit_commands(&[EditCommand::InsertString("ls".to_string())]);
+        assert_eq!(reedline.history_error_notice, None);
+
+        // the history is skipped for the rest of the session
+        let status = reedline.submit_buffer(&DefaultPrompt::default()).unwrap();
+        assert!(matches!(status, EventStatus::Exits(Signal::Success(line)) if line == "ls"));
+        assert!(!reedline.has_last_command_context());
+        reedline.previous_history();
+        reedline.run_history_commands(&[EditCommand::InsertChar('c')]);
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert_eq!(reedline.history_error_notice, None);
+
+        // until the host enables it again
+        failing.store(false, std::sync::atomic::Ordering::SeqCst);
+        reedline.enable_history();
+        reedline.input_mode = InputMode::Regular;
+        reedline.previous_history();
+        assert_eq!(reedline.current_buffer_contents(), "cargo build");
+        assert_eq!(reedline.history.count_all().unwrap(), 1);
+        assert_eq!(errors.lock().unwrap().len(), 1);
+    }
+
     #[rstest]
     #[case("")]
     #[case("line of text")]
@@ -3107,6 +3641,85 @@ mod tests {
         assert_eq!(reedline.current_buffer_contents(), "67x");
     }
 
+    #[test]
+    fn completers_receive_the_context_of_the_engine() {
+        /// Suggests what it knows from the context
+        struct ContextCompleter;
+
+        impl Completer for ContextCompleter {
+            fn complete(&mut self, _line: &str, _pos: usize) -> Vec<crate::Suggestion> {
+                vec![]
+            }
+
+            fn complete_with_context(
+                &mut self,
+                _line: &str,
+                pos: usize,
+                context: &CompletionContext,
+            ) -> Vec<crate::Suggestion> {
+                let entries = context.history.count_all().unwrap();
+                vec![crate::Suggestion {
+                    value: format!(
+                        "{} {} {entries} {:?}",
+                        context.cwd.unwrap_or_default(),
+                        context.menu_name.unwrap_or_default(),
+                        context.edit_mode,
+                    ),
+                    span: crate::Span::new(0, pos),
+                    ..Default::default()
+                }]
+            }
+        }
+
+        let completion_menu = ReedlineMenu::EngineCompleter(Box::new(
+            ColumnarMenu::default().with_name("completion_menu"),
+        ));
+        let mut reedline = Reedline::create()
+            .with_cwd(Some("/srv/app".to_string()))
+            .with_quick_completions(true)
+            .with_completer(Box::new(ContextCompleter))
+            .with_menu(completion_menu);
+        reedline
+            .history
+            .save(HistoryItem::from_command_line("ls"))
+            .unwrap();
+        reedline.run_edit_commands(&[EditCommand::InsertString("x".to_string())]);
+
+        let prompt = DefaultPrompt::default();
+        let completion = ReedlineEvent::Menu(String::from("completion_menu"));
+        reedline.handle_event(&prompt, completion).unwrap();
+        assert_eq!(
+            reedline.current_buffer_contents(),
+            "/srv/app completion_menu 1 Emacs"
+        );
+    }
+
+    #[test]
+    fn history_filter_redacts_and_drops_submitted_lines() {
+        let mut reedline = Reedline::create().with_history_filter(Box::new(|item: HistoryItem| {
+            let item = (!item.command_line.starts_with("vault")).then_some(item)?;
+            crate::DefaultHistoryFilter::default().filter(item)
+        }));
+        reedline.painter.force_prompt_anchored_for_test(0);
+        let prompt = DefaultPrompt::default();
+        for line in ["ls", "mysql --password hunter2", "vault read secret/db"] {
+            reedline.run_edit_commands(&[EditCommand::InsertString(line.to_string())]);
+            reedline.submit_buffer(&prompt).unwrap();
+        }
+
+        let saved: Vec<_> = reedline
+            .history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        assert_eq!(saved, vec!["ls", "mysql --password ***"]);
+        // dropped lines are handled like lines with the exclusion prefix
+        assert!(reedline.has_last_command_context());
+        assert!(reedline.update_last_command_context(&|item| item).is_ok());
+    }
+
     /// A hinter that always offers a fixed suggestion, so the completion flow can
     /// be driven without the paint cycle that normally refreshes the hint.
     struct FixedHinter(&'static str);
@@ -999,6 +999,14 @@ pub enum ReedlineEvent {
     /// Navigate to the next historic buffer
     NextHistory,
 
+    /// Navigate to the previous historic buffer containing the current buffer anywhere,
+    /// like zsh's `history-substring-search-up`
+    PreviousHistorySubstring,
+
+    /// Navigate to the next historic buffer containing the search string,
+    /// like zsh's `history-substring-search-down`
+    NextHistorySubstring,
+
     /// Search the history for a string
     SearchHistory,
 
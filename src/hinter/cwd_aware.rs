@@ -1,6 +1,6 @@
 use crate::{
     hinter::get_first_token,
-    history::SearchQuery,
+    history::{SearchOrder, SearchQuery},
     result::{ReedlineError, ReedlineErrorVariants::HistoryFeatureUnsupported},
     Hinter, History,
 };
@@ -13,6 +13,7 @@ pub struct CwdAwareHinter {
     style: Style,
     current_hint: String,
     min_chars: usize,
+    order: SearchOrder,
 }
 
 impl Hinter for CwdAwareHinter {
@@ -26,17 +27,20 @@ impl Hinter for CwdAwareHinter {
     ) -> String {
         self.current_hint = if line.chars().count() >= self.min_chars {
             let with_cwd = history
-                .search(SearchQuery::last_with_prefix_and_cwd(
-                    line.to_string(),
-                    cwd.to_string(),
-                    history.session(),
-                ))
+                .search(
+                    SearchQuery::last_with_prefix_and_cwd(
+                        line.to_string(),
+                        cwd.to_string(),
+                        history.session(),
+                    )
+                    .with_order(self.order),
+                )
                 .or_else(|err| {
                     if let ReedlineError(HistoryFeatureUnsupported { .. }) = err {
-                        history.search(SearchQuery::last_with_prefix(
-                            line.to_string(),
-                            history.session(),
-                        ))
+                        history.search(
+                            SearchQuery::last_with_prefix(line.to_string(), history.session())
+                                .with_order(self.order),
+                        )
                     } else {
                         Err(err)
                     }
@@ -50,10 +54,10 @@ impl Hinter for CwdAwareHinter {
                     .to_string()
             } else {
                 history
-                    .search(SearchQuery::last_with_prefix(
-                        line.to_string(),
-                        history.session(),
-                    ))
+                    .search(
+                        SearchQuery::last_with_prefix(line.to_string(), history.session())
+                            .with_order(self.order),
+                    )
                     .unwrap_or_default()
                     .first()
                     .map_or_else(String::new, |entry| {
@@ -90,6 +94,7 @@ impl Default for CwdAwareHinter {
             style: Style::new().fg(Color::LightGray),
             current_hint: String::new(),
             min_chars: 1,
+            order: SearchOrder::Chronological,
         }
     }
 }
@@ -108,4 +113,14 @@ impl CwdAwareHinter {
         self.min_chars = min_chars;
         self
     }
+
+    /// A builder that sets the order in which history entries are considered for the hint
+    ///
+    /// With [`SearchOrder::Frecency`] the hint is taken from the most frequently and recently
+    /// used matching command instead of the most recent one.
+    #[must_use]
+    pub fn with_search_order(mut self, order: SearchOrder) -> Self {
+        self.order = order;
+        self
+    }
 }
@@ -1,4 +1,8 @@
-use crate::{hinter::get_first_token, history::SearchQuery, Hinter, History};
+use crate::{
+    hinter::get_first_token,
+    history::{SearchOrder, SearchQuery},
+    Hinter, History,
+};
 use nu_ansi_term::{Color, Style};
 
 /// A hinter that uses the completions or the history to show a hint to the user
@@ -6,6 +10,7 @@ pub struct DefaultHinter {
     style: Style,
     current_hint: String,
     min_chars: usize,
+    order: SearchOrder,
 }
 
 impl Hinter for DefaultHinter {
@@ -19,12 +24,12 @@ impl Hinter for DefaultHinter {
     ) -> String {
         self.current_hint = if line.chars().count() >= self.min_chars {
             history
-                .search(SearchQuery::last_with_prefix(
-                    line.to_string(),
-                    history.session(),
-                ))
-                .expect("todo: error handling")
-                .first()
+                .search(
+                    SearchQuery::last_with_prefix(line.to_string(), history.session())
+                        .with_order(self.order),
+                )
+                .ok()
+                .and_then(|entries| entries.into_iter().next())
                 .map_or_else(String::new, |entry| {
                     entry
                         .command_line
@@ -58,6 +63,7 @@ impl Default for DefaultHinter {
             style: Style::new().fg(Color::LightGray),
             current_hint: String::new(),
             min_chars: 1,
+            order: SearchOrder::Chronological,
         }
     }
 }
@@ -76,4 +82,14 @@ impl DefaultHinter {
         self.min_chars = min_chars;
         self
     }
+
+    /// A builder that sets the order in which history entries are considered for the hint
+    ///
+    /// With [`SearchOrder::Frecency`] the hint is taken from the most frequently and recently
+    /// used matching command instead of the most recent one.
+    #[must_use]
+    pub fn with_search_order(mut self, order: SearchOrder) -> Self {
+        self.order = order;
+        self
+    }
 }
@@ -0,0 +1,630 @@
+use super::{
+    History, HistoryItem, HistoryItemId, HistorySessionId, IgnoreAllExtraInfo, NewItemCheck,
+    SearchDirection, SearchQuery,
+};
+use crate::{
+    result::{ReedlineError, ReedlineErrorVariants},
+    Result,
+};
+use chrono::Utc;
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+/// Changes queued for the worker thread, referring to entries by the ids handed out by the
+/// [`BackgroundHistory`]
+enum Change {
+    Save(HistoryItem),
+    Update(HistoryItem),
+    Delete(HistoryItemId),
+    Clear,
+    Sync,
+    ReportErrorsTo(Sender<ReedlineError>),
+    Flush(Sender<()>),
+}
+
+/// A [`History`] writing to another history on a worker thread, so slow disks don't hold up
+/// the prompt
+///
+/// Reads the entries of the wrapped history once when created and serves all searches from
+/// memory, including the entries saved since. Entries other sessions save to the wrapped history
+/// later on are not picked up. Queries filtering for the session use the session start of the
+/// wrapped history, see [`History::session_timestamp()`].
+///
+/// New entries the wrapped history would skip, see [`History::new_item_check()`], are skipped
+/// right away. The ids handed out stay valid when the ids of the wrapped history shift, as
+/// those of [`FileBackedHistory`](crate::FileBackedHistory) do after a deletion.
+///
+/// Changes are queued and applied in order, errors are sent to the channel set with
+/// [`BackgroundHistory::with_error_sender()`] and otherwise ignored.
+/// Dropping the history waits until all queued changes are written and the wrapped history
+/// is dropped.
+///
+/// ```rust
+/// use reedline::{BackgroundHistory, FileBackedHistory, Reedline};
+/// use std::sync::mpsc;
+///
+/// let (errors, history_errors) = mpsc::channel();
+/// let history = BackgroundHistory::new(Box::new(FileBackedHistory::default()))
+///     .unwrap()
+///     .with_error_sender(errors);
+/// let line_editor = Reedline::create().with_history(Box::new(history));
+/// // show the errors of saving the history, e.g. before every prompt
+/// for error in history_errors.try_iter() {
+///     eprintln!("{error}");
+/// }
+/// ```
+pub struct BackgroundHistory {
+    // ordered by id
+    entries: Vec<HistoryItem>,
+    next_id: i64,
+    session: Option<HistorySessionId>,
+    session_timestamp: Option<chrono::DateTime<Utc>>,
+    new_item_check: NewItemCheck,
+    changes: Option<Sender<Change>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl BackgroundHistory {
+    /// Reads the entries of the history and moves it to a new worker thread
+    pub fn new(history: Box<dyn History>) -> Result<Self> {
+        let mut entries =
+            history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+        entries.sort_by_key(|item| item.id);
+        let next_id = entries
+            .last()
+            .and_then(|item| item.id)
+            .map_or(0, |id| id.0 + 1);
+        let session = history.session();
+        let session_timestamp = history.session_timestamp();
+        let new_item_check = history.new_item_check();
+        let ids = IdMap::new(&entries);
+        let (changes, queue) = mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("reedline-history".to_string())
+            .spawn(move || write_changes(history, ids, queue))?;
+        Ok(BackgroundHistory {
+            entries,
+            next_id,
+            session,
+            session_timestamp,
+            new_item_check,
+            changes: Some(changes),
+            worker: Some(worker),
+        })
+    }
+
+    /// A builder that sends the errors of writing to the wrapped history to the channel
+    #[must_use]
+    pub fn with_error_sender(self, errors: Sender<ReedlineError>) -> Self {
+        self.queue(Change::ReportErrorsTo(errors));
+        self
+    }
+
+    /// Waits until all changes queued so far are written
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        self.queue(Change::Flush(done));
+        // the worker only hangs up without answering if it panicked
+        let _ = wait.recv();
+    }
+
+    fn queue(&self, change: Change) {
+        if let Some(changes) = &self.changes {
+            // the worker only stops when dropped, or if it panicked
+            let _ = changes.send(change);
+        }
+    }
+
+    fn position(&self, id: HistoryItemId) -> Result<usize> {
+        self.entries
+            .binary_search_by_key(&Some(id), |item| item.id)
+            .map_err(|_| {
+                ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                    "Item does not exist",
+                ))
+            })
+    }
+}
+
+impl Drop for BackgroundHistory {
+    fn drop(&mut self) {
+        // hanging up stops the worker once it wrote everything
+        self.changes.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// The ids of the entries in the wrapped history, by the ids handed out by the
+/// [`BackgroundHistory`]
+///
+/// Histories like [`FileBackedHistory`](crate::FileBackedHistory) number their entries by
+/// position, so their ids shift when entries are deleted, dropped for the capacity or added
+/// by a sync. The ids are realigned by the command lines then.
+struct IdMap {
+    // outer id, inner id and command line, in the order of the wrapped history
+    entries: Vec<(HistoryItemId, HistoryItemId, String)>,
+    // number of entries in the wrapped history, including those of other sessions
+    inner_len: usize,
+}
+
+impl IdMap {
+    /// Maps the ids of the entries read from the wrapped history to themselves
+    fn new(items: &[HistoryItem]) -> Self {
+        IdMap {
+            entries: items
+                .iter()
+                .filter_map(|item| Some((item.id?, item.id?, item.command_line.clone())))
+                .collect(),
+            inner_len: items.len(),
+        }
+    }
+
+    fn position(&self, id: HistoryItemId) -> Result<usize> {
+        self.entries
+            .iter()
+            .position(|(outer, _, _)| *outer == id)
+            .ok_or(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                "Item does not exist",
+            )))
+    }
+
+    fn inner(&self, id: HistoryItemId) -> Result<HistoryItemId> {
+        Ok(self.entries[self.position(id)?].1)
+    }
+
+    fn saved(
+        &mut self,
+        history: &dyn History,
+        id: HistoryItemId,
+        saved: HistoryItem,
+    ) -> Result<()> {
+        let Some(saved_id) = saved.id else {
+            return Ok(());
+        };
+        // without a larger id the ids of the other entries may have shifted
+        let shift = self
+            .entries
+            .last()
+            .map_or(0, |(_, last, _)| (last.0 + 1 - saved_id.0).max(0));
+        if shift == 0 {
+            self.entries.push((id, saved_id, saved.command_line));
+            self.inner_len += 1;
+            return Ok(());
+        }
+        // e.g. the oldest entries were dropped for the capacity
+        let shifted = self
+            .entries
+            .last()
+            .map_or(false, |(_, last, command_line)| {
+                history
+                    .load(HistoryItemId::new(last.0 - shift))
+                    .map_or(false, |item| item.command_line == *command_line)
+            });
+        if !shifted {
+            self.entries.push((id, saved_id, saved.command_line));
+            return self.realign(history);
+        }
+        for (_, inner, _) in &mut self.entries {
+            inner.0 -= shift;
+        }
+        self.entries.retain(|(_, inner, _)| inner.0 >= 0);
+        self.entries.push((id, saved_id, saved.command_line));
+        self.inner_len = (self.inner_len + 1).saturating_sub(shift as usize);
+        Ok(())
+    }
+
+    fn updated(&mut self, id: HistoryItemId, command_line: &str) -> Result<()> {
+        let position = self.position(id)?;
+        command_line.clone_into(&mut self.entries[position].2);
+        Ok(())
+    }
+
+    fn delete(&mut self, history: &mut dyn History, id: HistoryItemId) -> Result<()> {
+        let position = self.position(id)?;
+        history.delete(self.entries[position].1)?;
+        self.entries.remove(position);
+        self.inner_len = self.inner_len.saturating_sub(1);
+        self.realign(history)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.inner_len = 0;
+    }
+
+    /// Realigns the ids if a sync changed the entries of the wrapped history
+    fn synced(&mut self, history: &dyn History) -> Result<()> {
+        let unchanged = history.count_all()? as usize == self.inner_len
+            && self
+                .entries
+                .last()
+                .map_or(true, |(_, inner, command_line)| {
+                    history
+                        .load(*inner)
+                        .map_or(false, |item| item.command_line == *command_line)
+                });
+        if unchanged {
+            Ok(())
+        } else {
+            self.realign(history)
+        }
+    }
+
+    /// Matches the entries to those of the wrapped history with the same command line, from
+    /// the most recent one on, skipping the entries of other sessions
+    fn realign(&mut self, history: &dyn History) -> Result<()> {
+        let mut items = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+        items.sort_by_key(|item| item.id);
+        self.inner_len = items.len();
+        let mut end = items.len();
+        let mut entries = vec![];
+        for (outer, _, command_line) in self.entries.drain(..).rev() {
+            // entries no longer in the wrapped history are left out
+            if let Some(position) = items[..end]
+                .iter()
+                .rposition(|item| item.command_line == command_line)
+            {
+                end = position;
+                if let Some(inner) = items[position].id {
+                    entries.push((outer, inner, command_line));
+                }
+            }
+        }
+        entries.reverse();
+        self.entries = entries;
+        Ok(())
+    }
+}
+
+/// The loop of the worker thread, until the [`BackgroundHistory`] is dropped
+fn write_changes(mut history: Box<dyn History>, mut ids: IdMap, queue: Receiver<Change>) {
+    let mut errors = None;
+
+    for change in queue {
+        let result = match change {
+            Change::Save(item) => {
+                let id = item.id.expect("queued entries have an id");
+                history
+                    .save(HistoryItem { id: None, ..item })
+                    .and_then(|saved| ids.saved(history.as_ref(), id, saved))
+            }
+            Change::Update(item) => {
+                let outer = item.id.expect("queued entries have an id");
+                ids.inner(outer).and_then(|id| {
+                    history.update(id, &|_| HistoryItem {
+                        id: Some(id),
+                        ..item.clone()
+                    })?;
+                    ids.updated(outer, &item.command_line)
+                })
+            }
+            Change::Delete(id) => ids.delete(history.as_mut(), id),
+            Change::Clear => history.clear().map(|()| ids.clear()),
+            Change::Sync => history
+                .sync()
+                .map_err(ReedlineError::from)
+                .and_then(|()| ids.synced(history.as_ref())),
+            Change::ReportErrorsTo(sender) => {
+                errors = Some(sender);
+                Ok(())
+            }
+            Change::Flush(done) => {
+                let _ = done.send(());
+                Ok(())
+            }
+        };
+        if let (Err(err), Some(errors)) = (result, &errors) {
+            let _ = errors.send(err);
+        }
+    }
+    if let (Err(err), Some(errors)) = (history.sync(), &errors) {
+        let _ = errors.send(err.into());
+    }
+}
+
+impl History for BackgroundHistory {
+    /// Adds new entries right away and queues writing them
+    fn save(&mut self, mut h: HistoryItem) -> Result<HistoryItem> {
+        match h.id {
+            Some(id) => {
+                let position = self.position(id)?;
+                self.entries[position] = h.clone();
+                self.queue(Change::Update(h.clone()));
+            }
+            // entries the wrapped history skips aren't saved at all, as it returns them
+            None if !(self.new_item_check)(&h, self.entries.last()) => {}
+            None => {
+                h.id = Some(HistoryItemId::new(self.next_id));
+                self.next_id += 1;
+                self.entries.push(h.clone());
+                self.queue(Change::Save(h.clone()));
+            }
+        }
+        Ok(h)
+    }
+
+    fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+        Ok(self.entries[self.position(id)?].clone())
+    }
+
+    fn count(&self, query: SearchQuery) -> Result<i64> {
+        query.check_more_info::<IgnoreAllExtraInfo>("BackgroundHistory")?;
+        Ok(query
+            .run_in_memory(self.entries.iter(), self.session_timestamp)
+            .len() as i64)
+    }
+
+    fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+        query.check_more_info::<IgnoreAllExtraInfo>("BackgroundHistory")?;
+        Ok(query
+            .run_in_memory(self.entries.iter(), self.session_timestamp)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    fn update(
+        &mut self,
+        id: HistoryItemId,
+        updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> Result<()> {
+        let position = self.position(id)?;
+        let mut updated = updater(self.entries[position].clone());
+        updated.id = Some(id);
+        self.entries[position] = updated.clone();
+        self.queue(Change::Update(updated));
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.queue(Change::Clear);
+        Ok(())
+    }
+
+    fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+        let position = self.position(h)?;
+        self.entries.remove(position);
+        self.queue(Change::Delete(h));
+        Ok(())
+    }
+
+    /// Queues syncing the wrapped history, without waiting for it
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.queue(Change::Sync);
+        Ok(())
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        self.session
+    }
+
+    fn session_timestamp(&self) -> Option<chrono::DateTime<Utc>> {
+        self.session_timestamp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileBackedHistory;
+    use pretty_assertions::assert_eq;
+    use std::sync::{Arc, Mutex};
+
+    /// Shares the entries written by the worker with the test
+    #[derive(Clone, Default)]
+    struct SharedHistory(Arc<Mutex<FileBackedHistory>>);
+
+    impl History for SharedHistory {
+        fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
+            self.0.lock().unwrap().save(h)
+        }
+        fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+            self.0.lock().unwrap().load(id)
+        }
+        fn count(&self, query: SearchQuery) -> Result<i64> {
+            self.0.lock().unwrap().count(query)
+        }
+        fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+            self.0.lock().unwrap().search(query)
+        }
+        fn update(
+            &mut self,
+            id: HistoryItemId,
+            updater: &dyn Fn(HistoryItem) -> HistoryItem,
+        ) -> Result<()> {
+            self.0.lock().unwrap().update(id, updater)
+        }
+        fn clear(&mut self) -> Result<()> {
+            self.0.lock().unwrap().clear()
+        }
+        fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+            self.0.lock().unwrap().delete(h)
+        }
+        fn sync(&mut self) -> std::io::Result<()> {
+            self.0.lock().unwrap().sync()
+        }
+        fn session(&self) -> Option<HistorySessionId> {
+            None
+        }
+        fn new_item_check(&self) -> NewItemCheck {
+            self.0.lock().unwrap().new_item_check()
+        }
+    }
+
+    fn command_lines(history: &dyn History) -> Vec<String> {
+        history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect()
+    }
+
+    #[test]
+    fn writes_in_the_background_and_reads_from_memory() -> Result<()> {
+        let shared = SharedHistory::default();
+        shared.clone().save(HistoryItem::from_command_line("ls"))?;
+        let mut history = BackgroundHistory::new(Box::new(shared.clone()))?;
+
+        let saved = history.save(HistoryItem::from_command_line("cd src"))?;
+        history.save(HistoryItem::from_command_line("make"))?;
+        assert_eq!(history.load(saved.id.unwrap())?.command_line, "cd src");
+        history.delete(saved.id.unwrap())?;
+        assert_eq!(command_lines(&history), vec!["ls", "make"]);
+
+        history.flush();
+        assert_eq!(command_lines(&shared), vec!["ls", "make"]);
+        history.save(HistoryItem::from_command_line("make test"))?;
+        drop(history);
+        assert_eq!(command_lines(&shared), vec!["ls", "make", "make test"]);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_errors_through_the_channel() -> Result<()> {
+        let (errors, received) = mpsc::channel();
+        let mut history =
+            BackgroundHistory::new(Box::<FileBackedHistory>::default())?.with_error_sender(errors);
+        let saved = history.save(HistoryItem::from_command_line("cargo build"))?;
+        // not supported by the wrapped history
+        history.update(saved.id.unwrap(), &|item| HistoryItem {
+            exit_status: Some(0),
+            ..item
+        })?;
+        assert_eq!(history.load(saved.id.unwrap())?.exit_status, Some(0));
+
+        history.flush();
+        let errors: Vec<_> = received.try_iter().collect();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors[0],
+            ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_ids_of_entries_whose_ids_shifted() -> Result<()> {
+        let shared = SharedHistory::default();
+        for command_line in ["ls", "cd src", "make"] {
+            shared
+                .clone()
+                .save(HistoryItem::from_command_line(command_line))?;
+        }
+        let (errors, received) = mpsc::channel();
+        let mut history =
+            BackgroundHistory::new(Box::new(shared.clone()))?.with_error_sender(errors);
+        history.delete(HistoryItemId::new(1))?;
+        // `make` moved to index 1 of the wrapped history
+        history.delete(HistoryItemId::new(2))?;
+        let saved = history.save(HistoryItem::from_command_line("make test"))?;
+        history.delete(HistoryItemId::new(0))?;
+        assert_eq!(command_lines(&history), vec!["make test"]);
+        history.save(HistoryItem::from_command_line("make check"))?;
+        history.delete(saved.id.unwrap())?;
+
+        history.flush();
+        assert_eq!(command_lines(&shared), vec!["make check"]);
+        assert!(received.try_iter().next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_ids_of_entries_dropped_for_the_capacity() -> Result<()> {
+        let shared = SharedHistory(Arc::new(Mutex::new(FileBackedHistory::new(2)?)));
+        let mut history = BackgroundHistory::new(Box::new(shared.clone()))?;
+        let first = history.save(HistoryItem::from_command_line("ls"))?;
+        let second = history.save(HistoryItem::from_command_line("cd src"))?;
+        // drops `ls` from the wrapped history, `cd src` moves to index 0
+        history.save(HistoryItem::from_command_line("make"))?;
+        history.delete(second.id.unwrap())?;
+        history.delete(first.id.unwrap())?;
+
+        history.flush();
+        assert_eq!(command_lines(&shared), vec!["make"]);
+        Ok(())
+    }
+
+    #[test]
+    fn skips_the_entries_the_wrapped_history_skips() -> Result<()> {
+        let (errors, received) = mpsc::channel();
+        let shared = SharedHistory::default();
+        let mut history =
+            BackgroundHistory::new(Box::new(shared.clone()))?.with_error_sender(errors);
+        let saved = history.save(HistoryItem::from_command_line("ls"))?;
+        let repeated = history.save(HistoryItem::from_command_line("ls"))?;
+        assert_eq!(repeated.id, None);
+        assert_eq!(history.save(HistoryItem::from_command_line(""))?.id, None);
+        assert_eq!(command_lines(&history), vec!["ls"]);
+
+        // reaches the entry in the wrapped history, which can't update entries
+        history.update(saved.id.unwrap(), &|item| HistoryItem {
+            exit_status: Some(0),
+            ..item
+        })?;
+        history.flush();
+        assert!(matches!(
+            received.try_iter().collect::<Vec<_>>()[..],
+            [ReedlineError(
+                ReedlineErrorVariants::HistoryFeatureUnsupported { .. }
+            )]
+        ));
+        assert_eq!(command_lines(&shared), vec!["ls"]);
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_more_info_are_unsupported() -> Result<()> {
+        let history = BackgroundHistory::new(Box::<FileBackedHistory>::default())?;
+        let mut query = SearchQuery::everything(SearchDirection::Backward, None);
+        query
+            .filter
+            .more_info
+            .push(crate::MoreInfoFilter::equals("branch", "main"));
+        assert!(matches!(
+            history.search(query.clone()),
+            Err(ReedlineError(
+                ReedlineErrorVariants::HistoryFeatureUnsupported { .. }
+            ))
+        ));
+        assert!(history.count(query).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "jsonl")]
+    #[test]
+    fn filters_for_the_session_of_the_wrapped_history() -> Result<()> {
+        use crate::{JsonlBackedHistory, SearchFilter};
+        use chrono::{Duration, TimeZone};
+
+        let session_start = Utc.timestamp_opt(1_000_000, 0).unwrap();
+        let mut wrapped = JsonlBackedHistory::new(10)?
+            .with_session(Some(HistorySessionId::new(1)), Some(session_start));
+        for (command_line, session, offset) in [("old", 2, -10), ("other", 2, 10), ("own", 1, 20)] {
+            wrapped.save(HistoryItem {
+                session_id: Some(HistorySessionId::new(session)),
+                start_timestamp: Some(session_start + Duration::seconds(offset)),
+                ..HistoryItem::from_command_line(command_line)
+            })?;
+        }
+        let history = BackgroundHistory::new(Box::new(wrapped))?;
+
+        let query = SearchQuery {
+            filter: SearchFilter::anything(Some(HistorySessionId::new(1))),
+            ..SearchQuery::everything(SearchDirection::Forward, None)
+        };
+        let found: Vec<_> = history
+            .search(query.clone())?
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        assert_eq!(found, vec!["old", "own"]);
+        assert_eq!(history.count(query)?, 2);
+        Ok(())
+    }
+}
@@ -1,6 +1,16 @@
-use super::HistoryItemId;
-use crate::{core_editor::LineBuffer, HistoryItem, HistorySessionId, Result};
+use super::{
+    retention::{apply_retention_by_deletion, RetentionPolicy, RetentionReport},
+    stats::HistoryStats,
+    HistoryItemId,
+};
+use crate::{
+    core_editor::LineBuffer,
+    result::{ReedlineError, ReedlineErrorVariants},
+    utils::fuzzy::fuzzy_match,
+    HistoryItem, HistorySessionId, Result,
+};
 use chrono::Utc;
+use std::{collections::HashMap, time::Duration};
 
 /// Browsing modes for a [`History`]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -11,12 +21,15 @@ pub enum HistoryNavigationQuery {
     PrefixSearch(String),
     /// Full exact search for all entries containing a string.
     SubstringSearch(String),
+    /// Search for entries containing the characters of a string in order (e.g. `gpfl` for
+    /// `git push --force-with-lease`). Best matches are visited first.
+    Fuzzy(String),
     // Suffix Search
-    // Fuzzy Search
 }
 
 /// Ways to search for a particular command line in the [`History`]
 // todo: merge with [HistoryNavigationQuery]
+#[derive(Clone)]
 pub enum CommandLineSearch {
     /// Command line starts with the same string
     Prefix(String),
@@ -26,6 +39,26 @@ pub enum CommandLineSearch {
     ///
     /// Useful to gather statistics
     Exact(String),
+    /// Command line contains the characters of the string in order, not necessarily adjacent.
+    ///
+    /// Matching is case-insensitive unless the string contains an uppercase character.
+    /// Results are ranked by match quality first and by the [`SearchDirection`] second,
+    /// so `limit` applies to the best matches.
+    Fuzzy(String),
+}
+
+impl CommandLineSearch {
+    /// Score of a matching command line, `None` if it doesn't match.
+    ///
+    /// Only [`CommandLineSearch::Fuzzy`] ranks its matches, all others score zero.
+    pub(crate) fn match_score(&self, command_line: &str) -> Option<i64> {
+        match self {
+            CommandLineSearch::Prefix(p) => command_line.starts_with(p).then_some(0),
+            CommandLineSearch::Substring(p) => command_line.contains(p).then_some(0),
+            CommandLineSearch::Exact(p) => (command_line == p).then_some(0),
+            CommandLineSearch::Fuzzy(p) => fuzzy_match(p, command_line).map(|m| m.score),
+        }
+    }
 }
 
 /// Defines how to traverse the history when executing a [`SearchQuery`]
@@ -37,7 +70,152 @@ pub enum SearchDirection {
     Forward,
 }
 
+/// Defines the order of the results of a [`SearchQuery`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchOrder {
+    /// Ordered by time in the [`SearchDirection`]
+    /// (after the match quality for [`CommandLineSearch::Fuzzy`])
+    Chronological,
+    /// Most frecent command lines first: each entry of a command line adds to its score,
+    /// recent entries more than old ones, so both frequently and recently used commands
+    /// come first.
+    ///
+    /// Only the most recent entry of every command line is returned, ties are broken by recency.
+    /// The match quality of [`CommandLineSearch::Fuzzy`] is ignored.
+    Frecency,
+    /// Longest running entries first, those without a duration last.
+    ///
+    /// Ties are ordered by time in the [`SearchDirection`],
+    /// the match quality of [`CommandLineSearch::Fuzzy`] is ignored.
+    Duration,
+    /// Failed entries first, then the successful ones and those without an exit status last.
+    ///
+    /// Ties are ordered by time in the [`SearchDirection`],
+    /// the match quality of [`CommandLineSearch::Fuzzy`] is ignored.
+    ExitStatus,
+}
+
+/// Weight of an entry for [`SearchOrder::Frecency`] depending on its age:
+/// `(maximum age in milliseconds, weight)`, older entries and those without a timestamp weigh 1.
+pub(crate) const FRECENCY_WEIGHTS: [(i64, i64); 4] = [
+    (60 * 60 * 1000, 16),
+    (24 * 60 * 60 * 1000, 8),
+    (7 * 24 * 60 * 60 * 1000, 4),
+    (30 * 24 * 60 * 60 * 1000, 2),
+];
+
+fn frecency_weight(timestamp: Option<chrono::DateTime<Utc>>, now: chrono::DateTime<Utc>) -> i64 {
+    timestamp
+        .and_then(|timestamp| {
+            let age = (now - timestamp).num_milliseconds();
+            FRECENCY_WEIGHTS
+                .iter()
+                .find(|(max_age, _)| age < *max_age)
+                .map(|(_, weight)| *weight)
+        })
+        .unwrap_or(1)
+}
+
+/// Orders matching items by [`SearchOrder::Frecency`] and keeps the best `limit` items.
+pub(crate) fn rank_by_frecency<E, T>(items: Vec<T>, limit: usize) -> Vec<T>
+where
+    E: crate::HistoryItemExtraInfo,
+    T: std::borrow::Borrow<HistoryItem<E>>,
+{
+    let now = Utc::now();
+    // (score, id of the most recent entry, most recent entry) per command line
+    let mut ranked: Vec<(i64, Option<HistoryItemId>, T)> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for item in items {
+        let (weight, id) = {
+            let item = item.borrow();
+            (frecency_weight(item.start_timestamp, now), item.id)
+        };
+        let command_line = &item.borrow().command_line;
+        if let Some(&position) = positions.get(command_line) {
+            let entry = &mut ranked[position];
+            entry.0 += weight;
+            if id > entry.1 {
+                entry.1 = id;
+                entry.2 = item;
+            }
+        } else {
+            positions.insert(command_line.clone(), ranked.len());
+            ranked.push((weight, id, item));
+        }
+    }
+    ranked.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    ranked
+        .into_iter()
+        .take(limit)
+        .map(|(_, _, item)| item)
+        .collect()
+}
+
+/// How a [`MoreInfoFilter`] compares the string at its path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoreInfoMatch {
+    /// The string equals the given one
+    Equals(String),
+    /// The string starts with the given one
+    Prefix(String),
+}
+
+/// Condition on a string in the JSON of [`HistoryItem::more_info`], e.g. the git branch
+/// stored by a custom [`HistoryItemExtraInfo`](crate::HistoryItemExtraInfo)
+///
+/// Other values than strings, like numbers, never match.
+///
+/// ```rust
+/// use reedline::{MoreInfoFilter, SearchDirection, SearchQuery};
+///
+/// let mut query = SearchQuery::everything(SearchDirection::Backward, None);
+/// query.filter.more_info.push(MoreInfoFilter::equals("git.branch", "main"));
+/// query.filter.more_info.push(MoreInfoFilter::prefix("container", "postgres"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoreInfoFilter {
+    /// Keys of the nested objects leading to the string
+    pub path: Vec<String>,
+    /// The comparison of the string
+    pub matches: MoreInfoMatch,
+}
+
+impl MoreInfoFilter {
+    /// Strings at the path of keys separated by `.` equal to `value`
+    pub fn equals(path: &str, value: impl Into<String>) -> Self {
+        MoreInfoFilter {
+            path: path.split('.').map(str::to_string).collect(),
+            matches: MoreInfoMatch::Equals(value.into()),
+        }
+    }
+
+    /// Strings at the path of keys separated by `.` starting with `prefix`
+    pub fn prefix(path: &str, prefix: impl Into<String>) -> Self {
+        MoreInfoFilter {
+            path: path.split('.').map(str::to_string).collect(),
+            matches: MoreInfoMatch::Prefix(prefix.into()),
+        }
+    }
+
+    /// Whether the string at the path of the JSON value matches
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn is_match(&self, value: &serde_json::Value) -> bool {
+        let string = self
+            .path
+            .iter()
+            .try_fold(value, |value, key| value.get(key))
+            .and_then(serde_json::Value::as_str);
+        match (&self.matches, string) {
+            (MoreInfoMatch::Equals(expected), Some(string)) => string == expected,
+            (MoreInfoMatch::Prefix(prefix), Some(string)) => string.starts_with(prefix),
+            (_, None) => false,
+        }
+    }
+}
+
 /// Defines additional filters for querying the [`History`]
+#[derive(Clone)]
 pub struct SearchFilter {
     /// Query for the command line content
     pub command_line: Option<CommandLineSearch>,
@@ -51,8 +229,16 @@ pub struct SearchFilter {
     pub cwd_prefix: Option<String>,
     /// Filter whether the command completed
     pub exit_successful: Option<bool>,
+    /// Only entries that ran at least this long
+    pub min_duration: Option<Duration>,
+    /// Only entries that ran at most this long
+    pub max_duration: Option<Duration>,
     /// Filter on the session id
     pub session: Option<HistorySessionId>,
+    /// Only entries with all of these tags, e.g. [`HistoryItem::PINNED_TAG`]
+    pub tags: Vec<String>,
+    /// Only entries whose [`HistoryItem::more_info`] matches all of these filters
+    pub more_info: Vec<MoreInfoFilter>,
 }
 
 impl SearchFilter {
@@ -87,15 +273,22 @@ impl SearchFilter {
             cwd_exact: None,
             cwd_prefix: None,
             exit_successful: None,
+            min_duration: None,
+            max_duration: None,
             session,
+            tags: vec![],
+            more_info: vec![],
         }
     }
 }
 
 /// Query for search in the potentially rich [`History`]
+#[derive(Clone)]
 pub struct SearchQuery {
     /// Direction to search in
     pub direction: SearchDirection,
+    /// Order of the results
+    pub order: SearchOrder,
     /// if given, only get results after/before this time (depending on direction)
     pub start_time: Option<chrono::DateTime<Utc>>,
     /// if given, only get results after/before this time (depending on direction)
@@ -116,6 +309,7 @@ impl SearchQuery {
     pub fn all_that_contain_rev(contains: String) -> SearchQuery {
         SearchQuery {
             direction: SearchDirection::Backward,
+            order: SearchOrder::Chronological,
             start_time: None,
             end_time: None,
             start_id: None,
@@ -129,6 +323,7 @@ impl SearchQuery {
     pub const fn last_with_search(filter: SearchFilter) -> SearchQuery {
         SearchQuery {
             direction: SearchDirection::Backward,
+            order: SearchOrder::Chronological,
             start_time: None,
             end_time: None,
             start_id: None,
@@ -156,6 +351,13 @@ impl SearchQuery {
         SearchQuery::last_with_search(SearchFilter::from_text_search_cwd(cwd, prefix, session))
     }
 
+    /// A builder that sets the [`SearchOrder`] of the results
+    #[must_use]
+    pub fn with_order(mut self, order: SearchOrder) -> SearchQuery {
+        self.order = order;
+        self
+    }
+
     /// Query to get all entries in the given [`SearchDirection`]
     pub fn everything(
         direction: SearchDirection,
@@ -163,6 +365,7 @@ impl SearchQuery {
     ) -> SearchQuery {
         SearchQuery {
             direction,
+            order: SearchOrder::Chronological,
             start_time: None,
             end_time: None,
             start_id: None,
@@ -173,6 +376,208 @@ impl SearchQuery {
     }
 }
 
+/// Evaluation of queries for histories that keep all their items in memory
+impl SearchQuery {
+    /// Score of an item matching all criteria of the query, `None` if it doesn't match.
+    ///
+    /// `session_timestamp` is the start of the current session, see [`SearchFilter::session`].
+    pub(crate) fn match_score<E: crate::HistoryItemExtraInfo>(
+        &self,
+        item: &HistoryItem<E>,
+        session_timestamp: Option<chrono::DateTime<Utc>>,
+    ) -> Option<i64> {
+        let is_asc = self.direction == SearchDirection::Forward;
+        // Mirror SQL semantics: comparisons with missing values never match
+        let before = |a: Option<i64>, b: i64| a.map_or(false, |a| a < b);
+        let timestamp = item.start_timestamp.map(|t| t.timestamp_millis());
+        let id = item.id.map(|id| id.0);
+        if let Some(start) = self.start_time {
+            let start = start.timestamp_millis();
+            if is_asc && !timestamp.map_or(false, |t| t > start)
+                || !is_asc && !before(timestamp, start)
+            {
+                return None;
+            }
+        }
+        if let Some(end) = self.end_time {
+            let end = end.timestamp_millis();
+            if is_asc && !timestamp.map_or(false, |t| t <= end)
+                || !is_asc && !timestamp.map_or(false, |t| t >= end)
+            {
+                return None;
+            }
+        }
+        if let Some(start) = self.start_id {
+            if is_asc && !id.map_or(false, |id| id > start.0) || !is_asc && !before(id, start.0) {
+                return None;
+            }
+        }
+        if let Some(end) = self.end_id {
+            if is_asc && !id.map_or(false, |id| id <= end.0)
+                || !is_asc && !id.map_or(false, |id| id >= end.0)
+            {
+                return None;
+            }
+        }
+
+        let filter = &self.filter;
+        let score = match &filter.command_line {
+            Some(search) => search.match_score(&item.command_line)?,
+            None => 0,
+        };
+        if filter.not_command_line.as_ref() == Some(&item.command_line) {
+            return None;
+        }
+        if filter.hostname.is_some() && filter.hostname != item.hostname {
+            return None;
+        }
+        if filter.cwd_exact.is_some() && filter.cwd_exact != item.cwd {
+            return None;
+        }
+        if let Some(cwd_prefix) = &filter.cwd_prefix {
+            if !item
+                .cwd
+                .as_ref()
+                .map_or(false, |cwd| cwd.starts_with(cwd_prefix))
+            {
+                return None;
+            }
+        }
+        if let Some(exit_successful) = filter.exit_successful {
+            if item.exit_status.map(|s| s == 0) != Some(exit_successful) {
+                return None;
+            }
+        }
+        if let Some(min_duration) = filter.min_duration {
+            if !item.duration.map_or(false, |d| d >= min_duration) {
+                return None;
+            }
+        }
+        if let Some(max_duration) = filter.max_duration {
+            if !item.duration.map_or(false, |d| d <= max_duration) {
+                return None;
+            }
+        }
+        if !filter.tags.iter().all(|tag| item.has_tag(tag)) {
+            return None;
+        }
+        if !filter.more_info.is_empty() {
+            #[cfg(feature = "serde_json")]
+            {
+                let more_info = item.more_info.as_ref().map(serde_json::to_value)?.ok()?;
+                if !filter.more_info.iter().all(|f| f.is_match(&more_info)) {
+                    return None;
+                }
+            }
+            // without JSON support no extra info can be inspected
+            #[cfg(not(feature = "serde_json"))]
+            return None;
+        }
+        if let (Some(session), Some(session_timestamp)) = (filter.session, session_timestamp) {
+            // Items of this session or from before this session started
+            if item.session_id != Some(session)
+                && !before(timestamp, session_timestamp.timestamp_millis())
+            {
+                return None;
+            }
+        }
+        Some(score)
+    }
+
+    /// Fails if the query filters by [`SearchFilter::more_info`] but the extra info of the
+    /// items can't be inspected, instead of finding nothing
+    pub(crate) fn check_more_info<E: crate::HistoryItemExtraInfo>(
+        &self,
+        history: &'static str,
+    ) -> Result<()> {
+        if self.filter.more_info.is_empty() || E::INSPECTABLE && cfg!(feature = "serde_json") {
+            Ok(())
+        } else {
+            Err(ReedlineError(
+                ReedlineErrorVariants::HistoryFeatureUnsupported {
+                    history,
+                    feature: "filtering by more_info",
+                },
+            ))
+        }
+    }
+
+    /// Runs the query over items ordered from the oldest to the most recent one
+    pub(crate) fn run_in_memory<'a, E: crate::HistoryItemExtraInfo>(
+        &self,
+        items: impl Iterator<Item = &'a HistoryItem<E>>,
+        session_timestamp: Option<chrono::DateTime<Utc>>,
+    ) -> Vec<&'a HistoryItem<E>> {
+        let mut matches: Vec<_> = items
+            .filter_map(|item| {
+                self.match_score(item, session_timestamp)
+                    .map(|score| (score, item))
+            })
+            .collect();
+        if self.direction == SearchDirection::Backward {
+            matches.reverse();
+        }
+        let limit = self.limit.map_or(usize::MAX, |l| l.max(0) as usize);
+        if self.order == SearchOrder::Frecency {
+            rank_by_frecency(matches.into_iter().map(|(_, item)| item).collect(), limit)
+        } else if self.order != SearchOrder::Chronological {
+            let items = matches.into_iter().map(|(_, item)| item).collect();
+            sort_by_outcome(items, self.order, limit)
+        } else if let Some(CommandLineSearch::Fuzzy(_)) = self.filter.command_line {
+            rank_fuzzy_matches(matches, limit)
+        } else {
+            matches
+                .into_iter()
+                .take(limit)
+                .map(|(_, item)| item)
+                .collect()
+        }
+    }
+}
+
+/// Orders the items of a [`SearchOrder::Duration`] or [`SearchOrder::ExitStatus`] query
+/// and keeps the first `limit` items.
+///
+/// Expects the items in the order of the queried [`SearchDirection`], which breaks ties.
+pub(crate) fn sort_by_outcome<E, T>(mut items: Vec<T>, order: SearchOrder, limit: usize) -> Vec<T>
+where
+    E: crate::HistoryItemExtraInfo,
+    T: std::borrow::Borrow<HistoryItem<E>>,
+{
+    // stable sorts to keep the order of the search direction for ties
+    match order {
+        SearchOrder::Duration => {
+            items.sort_by_key(|item| std::cmp::Reverse(item.borrow().duration));
+        }
+        SearchOrder::ExitStatus => items.sort_by_key(|item| match item.borrow().exit_status {
+            Some(0) => 1,
+            Some(_) => 0,
+            None => 2,
+        }),
+        SearchOrder::Chronological | SearchOrder::Frecency => {}
+    }
+    items.truncate(limit);
+    items
+}
+
+/// Orders the `(score, item)` pairs of a [`CommandLineSearch::Fuzzy`] query by descending score
+/// and keeps the best `limit` items.
+///
+/// Expects the items in the order of the queried [`SearchDirection`], which breaks ties.
+pub(crate) fn rank_fuzzy_matches<T>(mut matches: Vec<(i64, T)>, limit: usize) -> Vec<T> {
+    // stable sort to keep the order of the search direction for equal scores
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+/// Whether saving the item adds it to a [`History`] whose most recent item is the second
+/// one, see [`History::new_item_check()`]
+pub type NewItemCheck = Box<dyn Fn(&HistoryItem, Option<&HistoryItem>) -> bool + Send>;
+
 /// Represents a history file or database
 /// Data could be stored e.g. in a plain text file, in a `JSONL` file, in a `SQLite` database
 pub trait History: Send {
@@ -206,6 +611,26 @@ pub trait History: Send {
     fn sync(&mut self) -> std::io::Result<()>;
     /// get the history session id
     fn session(&self) -> Option<HistorySessionId>;
+    /// get the start of the history session, `None` if queries filtering for the session
+    /// aren't restricted to its items and the items from before it started
+    fn session_timestamp(&self) -> Option<chrono::DateTime<Utc>> {
+        None
+    }
+    /// the check whether saving a new item adds it after the most recent item, for wrappers
+    /// keeping their own copy of the items, like [`BackgroundHistory`](crate::BackgroundHistory);
+    /// by default every item is added
+    fn new_item_check(&self) -> NewItemCheck {
+        Box::new(|_, _| true)
+    }
+    /// remove the items the retention policy doesn't keep and report what was removed
+    fn apply_retention(&mut self, policy: &RetentionPolicy) -> Result<RetentionReport> {
+        apply_retention_by_deletion(self, policy)
+    }
+    /// aggregate statistics over all history items, with rankings of at most `limit` entries
+    fn stats(&self, limit: usize) -> Result<HistoryStats> {
+        let items = self.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+        Ok(HistoryStats::from_items(&items, limit))
+    }
 }
 
 #[cfg(test)]
@@ -227,6 +652,7 @@ mod test {
             cwd: Some(cwd.to_string()),
             duration: Some(Duration::from_millis(1000)),
             exit_status: Some(exit_status),
+            tags: vec![],
             more_info: None,
         }
     }
@@ -302,6 +728,93 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn search_frecency_prefers_frequent_commands() -> Result<()> {
+        let mut history = create_filled_example_history()?;
+        history.save(create_item(1, "/home/me/Downloads", "cd foo", 0))?; // 13
+        history.save(create_item(1, "/home/me/Downloads", "pwd", 0))?; // 14
+        history.save(create_item(1, "/home/me/Downloads", "cd foo", 0))?; // 15
+        let res = history.search(
+            SearchQuery::all_that_contain_rev("cd ".to_string()).with_order(SearchOrder::Frecency),
+        )?;
+        // one result per command line, most recent entry; ties broken by recency
+        search_returned(&*history, res, vec![15, 8, 1])?;
+        Ok(())
+    }
+
+    #[test]
+    fn search_frecency_respects_limit() -> Result<()> {
+        let mut history = create_filled_example_history()?;
+        history.save(create_item(1, "/etc/nginx", "vim htpasswd", 0))?; // 13
+        let res = history.search(
+            SearchQuery::last_with_prefix("vim".to_string(), None)
+                .with_order(SearchOrder::Frecency),
+        )?;
+        search_returned(&*history, res, vec![13])?;
+        Ok(())
+    }
+
+    #[test]
+    fn frecency_weighs_recent_entries_more() {
+        let now = Utc::now();
+        let item = |id: i64, command_line: &str, age: chrono::Duration| HistoryItem {
+            id: Some(HistoryItemId::new(id)),
+            start_timestamp: Some(now - age),
+            ..HistoryItem::from_command_line(command_line)
+        };
+        let items = vec![
+            item(1, "make", chrono::Duration::days(60)),
+            item(2, "make", chrono::Duration::days(45)),
+            item(3, "make", chrono::Duration::days(40)),
+            item(4, "cargo build", chrono::Duration::minutes(5)),
+            item(5, "ls", chrono::Duration::days(2)),
+            item(6, "ls", chrono::Duration::days(3)),
+        ];
+        let ranked: Vec<_> = rank_by_frecency(items, usize::MAX)
+            .into_iter()
+            .map(|item| (item.id.unwrap().0, item.command_line))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                (4, "cargo build".to_string()),
+                (6, "ls".to_string()),
+                (3, "make".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn sorts_by_duration_and_exit_status() {
+        let item = |id: i64, duration_ms: Option<u64>, exit_status: Option<i64>| HistoryItem {
+            id: Some(HistoryItemId::new(id)),
+            duration: duration_ms.map(Duration::from_millis),
+            exit_status,
+            ..HistoryItem::from_command_line("make")
+        };
+        let items = vec![
+            item(4, Some(20), Some(0)),
+            item(3, None, None),
+            item(2, Some(500), Some(2)),
+            item(1, Some(20), Some(1)),
+        ];
+        let ids = |items: Vec<HistoryItem>| -> Vec<i64> {
+            items.into_iter().map(|item| item.id.unwrap().0).collect()
+        };
+        assert_eq!(
+            ids(sort_by_outcome(
+                items.clone(),
+                SearchOrder::Duration,
+                usize::MAX
+            )),
+            vec![2, 4, 1, 3]
+        );
+        assert_eq!(
+            ids(sort_by_outcome(items, SearchOrder::ExitStatus, 3)),
+            vec![2, 1, 4]
+        );
+    }
+
     #[test]
     fn get_latest() -> Result<()> {
         let history = create_filled_example_history()?;
@@ -369,6 +882,70 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn search_fuzzy_ranks_best_match_first() -> Result<()> {
+        let history = create_filled_example_history()?;
+        let res = history.search(SearchQuery {
+            filter: SearchFilter::from_text_search(
+                CommandLineSearch::Fuzzy("vnc".to_string()),
+                None,
+            ),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        })?;
+        // "vim nginx.conf" matches at word starts, "vim htpasswd" does not match at all
+        search_returned(&*history, res, vec![10])?;
+
+        let res = history.search(SearchQuery {
+            filter: SearchFilter::from_text_search(
+                CommandLineSearch::Fuzzy("uzp".to_string()),
+                None,
+            ),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        })?;
+        // the closer match wins over the more recent one
+        search_returned(&*history, res, vec![2, 3])?;
+        Ok(())
+    }
+
+    #[test]
+    fn search_fuzzy_ignores_the_case_of_non_ascii_letters() -> Result<()> {
+        let mut history = create_filled_example_history()?;
+        let saved = history.save(create_item(1, "/", "echo ÉTÉ", 0))?;
+        let res = history.search(SearchQuery {
+            filter: SearchFilter::from_text_search(
+                CommandLineSearch::Fuzzy("été".to_string()),
+                None,
+            ),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        })?;
+        search_returned(&*history, res, vec![saved.id.unwrap().0])?;
+        Ok(())
+    }
+
+    #[test]
+    fn search_fuzzy_limit_applies_after_ranking() -> Result<()> {
+        let history = create_filled_example_history()?;
+        let res = history.search(SearchQuery {
+            filter: SearchFilter::from_text_search(
+                CommandLineSearch::Fuzzy("LS".to_string()),
+                None,
+            ),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        })?;
+        search_returned(&*history, res, vec![])?;
+
+        let res = history.search(SearchQuery {
+            filter: SearchFilter::from_text_search(
+                CommandLineSearch::Fuzzy("ls".to_string()),
+                None,
+            ),
+            limit: Some(1),
+            ..SearchQuery::everything(SearchDirection::Forward, None)
+        })?;
+        search_returned(&*history, res, vec![5])?;
+        Ok(())
+    }
+
     #[test]
     fn search_includes_limit() -> Result<()> {
         let history = create_filled_example_history()?;
@@ -1,8 +1,10 @@
 use crate::{History, HistoryNavigationQuery, HistorySessionId};
+use std::collections::HashSet;
 
 use super::base::CommandLineSearch;
 use super::base::SearchDirection;
 use super::base::SearchFilter;
+use super::base::SearchOrder;
 use super::HistoryItem;
 use super::SearchQuery;
 use crate::Result;
@@ -14,6 +16,8 @@ pub struct HistoryCursor {
     current: Option<HistoryItem>,
     skip_dupes: bool,
     session: Option<HistorySessionId>,
+    // the fuzzy matches of the pattern, ranked and without duplicates
+    ranked: Option<(String, Vec<HistoryItem>)>,
 }
 
 impl HistoryCursor {
@@ -23,6 +27,7 @@ impl HistoryCursor {
             current: None,
             skip_dupes: true,
             session,
+            ranked: None,
         }
     }
 
@@ -48,6 +53,9 @@ impl HistoryCursor {
                 CommandLineSearch::Substring(substring),
                 self.session,
             ),
+            HistoryNavigationQuery::Fuzzy(pattern) => {
+                SearchFilter::from_text_search(CommandLineSearch::Fuzzy(pattern), self.session)
+            }
         };
         if let (true, Some(current)) = (self.skip_dupes, &self.current) {
             SearchFilter {
@@ -67,6 +75,9 @@ impl HistoryCursor {
             // if searching forward but we don't have a starting point, assume we are at the end
             return Ok(());
         }
+        if let HistoryNavigationQuery::Fuzzy(pattern) = &self.query {
+            return self.navigate_ranked(history, direction, pattern.clone());
+        }
         let start_id = self.current.as_ref().and_then(|e| e.id);
         let mut next = history.search(SearchQuery {
             start_id,
@@ -74,6 +85,7 @@ impl HistoryCursor {
             start_time: None,
             end_time: None,
             direction,
+            order: SearchOrder::Chronological,
             limit: Some(1),
             filter: self.get_search_filter(),
         })?;
@@ -86,6 +98,58 @@ impl HistoryCursor {
         Ok(())
     }
 
+    /// Fuzzy results are ordered by match quality instead of by id, so step through the
+    /// ranked list: going back visits worse matches, going forward better ones.
+    ///
+    /// The list is searched once per pattern, a new cursor is created when the buffer changes.
+    fn navigate_ranked(
+        &mut self,
+        history: &dyn History,
+        direction: SearchDirection,
+        pattern: String,
+    ) -> Result<()> {
+        if self.ranked.as_ref().map(|(cached, _)| cached) != Some(&pattern) {
+            let found = history.search(SearchQuery {
+                start_id: None,
+                end_id: None,
+                start_time: None,
+                end_time: None,
+                direction: SearchDirection::Backward,
+                order: SearchOrder::Chronological,
+                limit: None,
+                filter: SearchFilter::from_text_search(
+                    CommandLineSearch::Fuzzy(pattern.clone()),
+                    self.session,
+                ),
+            })?;
+            let mut seen = HashSet::new();
+            let found = found
+                .into_iter()
+                .filter(|e| !self.skip_dupes || seen.insert(e.command_line.clone()))
+                .collect();
+            self.ranked = Some((pattern, found));
+        }
+        let ranked = self.ranked.as_ref().map_or(&[][..], |(_, ranked)| ranked);
+        let position = self.current.as_ref().and_then(|current| {
+            ranked.iter().position(|e| {
+                e.id == current.id || (self.skip_dupes && e.command_line == current.command_line)
+            })
+        });
+        let next = match (direction, position) {
+            (SearchDirection::Backward, None) => Some(0),
+            (SearchDirection::Backward, Some(p)) => Some(p + 1),
+            (SearchDirection::Forward, Some(p)) => p.checked_sub(1),
+            (SearchDirection::Forward, None) => None,
+        };
+        match next {
+            Some(n) if n < ranked.len() => self.current = Some(ranked[n].clone()),
+            // bottomed out: stay on the worst match
+            Some(_) if position.is_some() => {}
+            _ => self.current = None,
+        }
+        Ok(())
+    }
+
     /// Returns the string (if present) at the cursor
     pub fn string_at_cursor(&self) -> Option<String> {
         self.current.as_ref().map(|e| e.command_line.to_string())
@@ -374,6 +438,104 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn fuzzy_search_visits_best_match_first() -> Result<()> {
+        let (mut hist, _) = create_history();
+        hist.save(HistoryItem::from_command_line(
+            "git push --force-with-lease",
+        ))?;
+        hist.save(HistoryItem::from_command_line("git pull --ff-only"))?;
+        hist.save(HistoryItem::from_command_line(
+            "git push --force-with-lease",
+        ))?;
+        hist.save(HistoryItem::from_command_line("ls"))?;
+
+        let mut cursor =
+            HistoryCursor::new(HistoryNavigationQuery::Fuzzy("gpfl".to_string()), None);
+        cursor.back(&*hist)?;
+        assert_eq!(
+            cursor.string_at_cursor(),
+            Some("git push --force-with-lease".to_string())
+        );
+        cursor.back(&*hist)?;
+        assert_eq!(
+            cursor.string_at_cursor(),
+            Some("git pull --ff-only".to_string())
+        );
+        cursor.back(&*hist)?;
+        assert_eq!(
+            cursor.string_at_cursor(),
+            Some("git pull --ff-only".to_string())
+        );
+        cursor.forward(&*hist)?;
+        assert_eq!(
+            cursor.string_at_cursor(),
+            Some("git push --force-with-lease".to_string())
+        );
+        cursor.forward(&*hist)?;
+        assert_eq!(cursor.string_at_cursor(), None);
+        Ok(())
+    }
+
+    /// Counts the searches of the wrapped history
+    struct CountingHistory {
+        history: Box<dyn History>,
+        searches: std::cell::Cell<usize>,
+    }
+
+    impl History for CountingHistory {
+        fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
+            self.history.save(h)
+        }
+        fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+            self.history.load(id)
+        }
+        fn count(&self, query: SearchQuery) -> Result<i64> {
+            self.history.count(query)
+        }
+        fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+            self.searches.set(self.searches.get() + 1);
+            self.history.search(query)
+        }
+        fn update(
+            &mut self,
+            id: HistoryItemId,
+            updater: &dyn Fn(HistoryItem) -> HistoryItem,
+        ) -> Result<()> {
+            self.history.update(id, updater)
+        }
+        fn clear(&mut self) -> Result<()> {
+            self.history.clear()
+        }
+        fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+            self.history.delete(h)
+        }
+        fn sync(&mut self) -> std::io::Result<()> {
+            self.history.sync()
+        }
+        fn session(&self) -> Option<HistorySessionId> {
+            None
+        }
+    }
+
+    #[test]
+    fn fuzzy_search_ranks_the_matches_once() -> Result<()> {
+        let (mut history, _) = create_history();
+        add_text_entries(history.as_mut(), &["git push", "git pull", "ls"]);
+        let hist = CountingHistory {
+            history,
+            searches: std::cell::Cell::new(0),
+        };
+
+        let mut cursor = HistoryCursor::new(HistoryNavigationQuery::Fuzzy("gp".to_string()), None);
+        cursor.back(&hist)?;
+        cursor.back(&hist)?;
+        cursor.forward(&hist)?;
+        assert_eq!(cursor.string_at_cursor(), Some("git pull".to_string()));
+        assert_eq!(hist.searches.get(), 1);
+        Ok(())
+    }
+
     #[test]
     fn writes_to_new_file() -> Result<()> {
         use tempfile::tempdir;
@@ -474,6 +636,233 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn deletes_unsynced_entry() -> Result<()> {
+        let mut hist = FileBackedHistory::default();
+        add_text_entries(&mut hist, &["first", "secret", "last"]);
+
+        hist.delete(HistoryItemId::new(1))?;
+        assert_eq!(get_all_entry_texts(&hist), vec!["first", "last"]);
+        assert!(hist.delete(HistoryItemId::new(2)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn deleted_entries_are_removed_from_file() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+
+        {
+            let (mut writing_hist, _) = create_history_at(10, &histfile);
+            add_text_entries(
+                writing_hist.as_mut(),
+                &["ls", "secret", "cd", "secret", "pwd"],
+            );
+        }
+
+        {
+            let (mut deleting_hist, _) = create_history_at(10, &histfile);
+            add_text_entries(deleting_hist.as_mut(), &["unsynced"]);
+            deleting_hist.delete(HistoryItemId::new(3))?;
+            deleting_hist.delete(HistoryItemId::new(1))?;
+            assert_eq!(
+                get_all_entry_texts(deleting_hist.as_ref()),
+                vec!["ls", "cd", "pwd", "unsynced"]
+            );
+            deleting_hist.sync()?;
+            assert_eq!(
+                get_all_entry_texts(deleting_hist.as_ref()),
+                vec!["ls", "cd", "pwd", "unsynced"]
+            );
+        }
+
+        let (reading_hist, _) = create_history_at(10, &histfile);
+        assert_eq!(
+            get_all_entry_texts(reading_hist.as_ref()),
+            vec!["ls", "cd", "pwd", "unsynced"]
+        );
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_histories_do_not_resurrect_deleted_entries() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+
+        {
+            let (mut writing_hist, _) = create_history_at(10, &histfile);
+            add_text_entries(writing_hist.as_mut(), &["ls", "secret", "cd"]);
+        }
+
+        {
+            let (mut hist_a, _) = create_history_at(10, &histfile);
+            let (mut hist_b, _) = create_history_at(10, &histfile);
+
+            add_text_entries(hist_b.as_mut(), &["B1"]);
+            hist_a.delete(HistoryItemId::new(1))?;
+            hist_b.sync()?;
+            add_text_entries(hist_a.as_mut(), &["A1"]);
+            hist_a.sync()?;
+            add_text_entries(hist_b.as_mut(), &["B2"]);
+            // As `hist` goes out of scope and get's dropped, its contents are flushed to disk
+        }
+
+        let (reading_hist, _) = create_history_at(10, &histfile);
+        assert_eq!(
+            get_all_entry_texts(reading_hist.as_ref()),
+            vec!["ls", "cd", "B1", "A1", "B2"]
+        );
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
+    fn shared_history_at(path: &Path) -> FileBackedHistory {
+        FileBackedHistory::with_file(10, path.to_owned())
+            .unwrap()
+            .with_shared_history(true)
+    }
+
+    fn browse_back(hist: &dyn History) -> Vec<String> {
+        let mut cursor =
+            HistoryCursor::new(HistoryNavigationQuery::Normal(LineBuffer::default()), None);
+        let mut seen = vec![];
+        cursor.back(hist).unwrap();
+        while let Some(entry) = cursor.string_at_cursor() {
+            seen.push(entry);
+            cursor.back(hist).unwrap();
+            if cursor.string_at_cursor().as_ref() == seen.last() {
+                break;
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn shared_histories_pick_up_entries_of_other_sessions() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+        {
+            let (mut writing_hist, _) = create_history_at(10, &histfile);
+            add_text_entries(writing_hist.as_mut(), &["ls", "cd"]);
+        }
+
+        let mut hist_a = shared_history_at(&histfile);
+        let mut hist_b = shared_history_at(&histfile);
+        add_text_entries(&mut hist_a, &["A1"]);
+        hist_a.sync()?;
+        add_text_entries(&mut hist_b, &["B1"]);
+        hist_b.sync()?;
+        add_text_entries(&mut hist_a, &["A2"]);
+        hist_a.sync()?;
+
+        // the own entries come first when browsing back
+        assert_eq!(browse_back(&hist_a), vec!["A2", "A1", "B1", "cd", "ls"]);
+        hist_b.sync()?;
+        assert_eq!(browse_back(&hist_b), vec!["B1", "A2", "A1", "cd", "ls"]);
+
+        // a rewrite of the file by another session is picked up as well
+        hist_b.delete(HistoryItemId::new(0))?;
+        hist_b.sync()?;
+        hist_a.sync()?;
+        assert_eq!(browse_back(&hist_a), vec!["A2", "A1", "B1", "cd"]);
+
+        drop(hist_a);
+        drop(hist_b);
+        let (reading_hist, _) = create_history_at(10, &histfile);
+        assert_eq!(
+            get_all_entry_texts(reading_hist.as_ref()),
+            vec!["cd", "A1", "B1", "A2"]
+        );
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn shared_histories_pick_up_several_entries_in_one_sync() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+        let mut hist_a = shared_history_at(&histfile);
+        let mut hist_b = shared_history_at(&histfile);
+        add_text_entries(&mut hist_b, &["B1", "B2"]);
+        hist_b.sync()?;
+        add_text_entries(&mut hist_a, &["A1", "A2", "A3"]);
+        hist_a.sync()?;
+        add_text_entries(&mut hist_b, &["B3"]);
+        hist_b.sync()?;
+
+        assert_eq!(
+            get_all_entry_texts(&hist_b),
+            vec!["A1", "A2", "A3", "B1", "B2", "B3"]
+        );
+        add_text_entries(&mut hist_a, &["A4"]);
+        hist_a.sync()?;
+        assert_eq!(
+            get_all_entry_texts(&hist_a),
+            vec!["B1", "B2", "B3", "A1", "A2", "A3", "A4"]
+        );
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn shared_histories_reread_a_large_file_rewritten_at_its_end() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+        let mut hist =
+            FileBackedHistory::with_file(1000, histfile.clone())?.with_shared_history(true);
+        let entries: Vec<String> = (0..500).map(|i| format!("entry {i:03}")).collect();
+        let entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        add_text_entries(&mut hist, &entries);
+        hist.sync()?;
+
+        // another program replaces the last entry and appends one
+        let mut rewritten = entries[..499].join("\n");
+        rewritten.push_str("\nls\npwd\n");
+        std::fs::write(&histfile, rewritten)?;
+        hist.sync()?;
+        // the new entries count as the ones of other sessions
+        let texts = get_all_entry_texts(&hist);
+        assert_eq!(texts[..3], ["ls", "pwd", "entry 000"]);
+        assert_eq!(texts[498..], ["entry 496", "entry 497", "entry 498"]);
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn shared_histories_reread_a_file_rewritten_to_a_larger_size() -> Result<()> {
+        use tempfile::tempdir;
+
+        let tmp = tempdir().unwrap();
+        let histfile = tmp.path().join(".history");
+        let mut hist = shared_history_at(&histfile);
+        add_text_entries(&mut hist, &["ls"]);
+        hist.sync()?;
+
+        // another program replaces the file
+        std::fs::write(&histfile, "pwd\ncd src\n")?;
+        hist.sync()?;
+        assert_eq!(get_all_entry_texts(&hist), vec!["pwd", "cd src"]);
+
+        tmp.close().unwrap();
+        Ok(())
+    }
+
     #[test]
     fn truncates_too_large_file() -> Result<()> {
         use tempfile::tempdir;
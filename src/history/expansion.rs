@@ -0,0 +1,331 @@
+//! Word designators, modifiers and quick substitution of the bash history expansion
+//!
+//! The event designators (`!!`, `!n`, `!-n`, `!prefix`) are parsed by
+//! [`parse_selection_char`](crate::menu_functions::parse_selection_char), this module handles
+//! what may follow them, e.g. `!!:2`, `!$:h` or `!-2:s/foo/bar/`.
+
+/// A word index, `None` standing for `$`, the last word
+type WordIndex = Option<usize>;
+
+/// Range of words selected by a word designator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Words {
+    start: WordIndex,
+    end: WordIndex,
+    // `x-` leaves out the last word
+    skip_last: bool,
+    // `*` and `x*` may select no words at all
+    allow_empty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Modifier {
+    /// `:h`, removes the trailing pathname component
+    Head,
+    /// `:t`, removes all leading pathname components
+    Tail,
+    /// `:s/old/new/` and `:gs/old/new/`
+    Substitute {
+        old: String,
+        new: String,
+        global: bool,
+    },
+}
+
+/// The word designator and modifiers following an event designator
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Designators {
+    words: Option<Words>,
+    modifiers: Vec<Modifier>,
+}
+
+/// Finds the first `!^`, `!*` or `!:`, the short forms of `!!:^`, `!!:*` and `!!:`
+///
+/// `!$` is found by [`parse_selection_char`](crate::menu_functions::parse_selection_char).
+pub(crate) fn find_last_command_shorthand(buffer: &str) -> Option<usize> {
+    buffer
+        .match_indices('!')
+        .map(|(position, _)| position)
+        .find(|&position| matches!(buffer[position + 1..].chars().next(), Some('^' | '*' | ':')))
+}
+
+/// Parses the designators at the start of `text`, returning them with the number of bytes they take
+///
+/// The colon before the word designator may be left out if it starts with `^`, `$` or `*`.
+/// Parsing stops at the first part that is no designator.
+pub(crate) fn parse_designators(text: &str) -> (Designators, usize) {
+    let mut designators = Designators::default();
+    let mut consumed = 0;
+
+    let word_text = text.strip_prefix(':').unwrap_or(text);
+    let colon = text.len() - word_text.len();
+    if colon == 1 || word_text.starts_with(['^', '$', '*']) {
+        if let Some((words, len)) = parse_words(word_text) {
+            designators.words = Some(words);
+            consumed = colon + len;
+        }
+    }
+
+    while let Some(modifier_text) = text[consumed..].strip_prefix(':') {
+        match parse_modifier(modifier_text) {
+            Some((modifier, len)) => {
+                designators.modifiers.push(modifier);
+                consumed += 1 + len;
+            }
+            None => break,
+        }
+    }
+    (designators, consumed)
+}
+
+/// Parses `n`, `^`, `$`, `x-y`, `x-`, `-y`, `x*` and `*`
+fn parse_words(text: &str) -> Option<(Words, usize)> {
+    let words = |start, end, skip_last, allow_empty| Words {
+        start,
+        end,
+        skip_last,
+        allow_empty,
+    };
+    if text.starts_with('*') {
+        return Some((words(Some(1), None, false, true), 1));
+    }
+    let Some((start, len)) = parse_word_index(text) else {
+        // `-y` is short for `0-y`
+        let (end, len) = parse_word_index(text.strip_prefix('-')?)?;
+        return Some((words(Some(0), end, false, false), len + 1));
+    };
+
+    let rest = &text[len..];
+    if rest.starts_with('*') {
+        Some((words(start, None, false, true), len + 1))
+    } else if let Some(range_end) = rest.strip_prefix('-') {
+        match parse_word_index(range_end) {
+            Some((end, end_len)) => Some((words(start, end, false, false), len + 1 + end_len)),
+            None => Some((words(start, None, true, false), len + 1)),
+        }
+    } else {
+        Some((words(start, start, false, false), len))
+    }
+}
+
+/// Parses `n`, `^` or `$`
+fn parse_word_index(text: &str) -> Option<(WordIndex, usize)> {
+    match text.chars().next()? {
+        '^' => Some((Some(1), 1)),
+        '$' => Some((None, 1)),
+        c if c.is_ascii_digit() => {
+            let len = text
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(text.len());
+            Some((Some(text[..len].parse().ok()?), len))
+        }
+        _ => None,
+    }
+}
+
+fn parse_modifier(text: &str) -> Option<(Modifier, usize)> {
+    match text.chars().next()? {
+        'h' => Some((Modifier::Head, 1)),
+        't' => Some((Modifier::Tail, 1)),
+        's' => parse_substitution(&text[1..], false).map(|(modifier, len)| (modifier, len + 1)),
+        'g' => {
+            let substitution = text[1..].strip_prefix('s')?;
+            parse_substitution(substitution, true).map(|(modifier, len)| (modifier, len + 2))
+        }
+        _ => None,
+    }
+}
+
+/// Parses `/old/new/` with any delimiter, the last one may be left out at the end of the line
+fn parse_substitution(text: &str, global: bool) -> Option<(Modifier, usize)> {
+    let delimiter = text.chars().next()?;
+    let mut len = delimiter.len_utf8();
+    let (old, old_len) = parse_delimited(&text[len..], delimiter);
+    len += old_len;
+    let (new, new_len) = parse_delimited(&text[len..], delimiter);
+    len += new_len;
+    (!old.is_empty()).then_some((Modifier::Substitute { old, new, global }, len))
+}
+
+/// Reads up to and including the delimiter, or to the end, resolving `\` escapes of the delimiter
+fn parse_delimited(text: &str, delimiter: char) -> (String, usize) {
+    let mut part = String::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        if c == delimiter {
+            return (part, position + c.len_utf8());
+        }
+        match chars.peek() {
+            Some(&(_, escaped)) if c == '\\' && escaped == delimiter => {
+                part.push(escaped);
+                chars.next();
+            }
+            _ => part.push(c),
+        }
+    }
+    (part, text.len())
+}
+
+/// Parses the quick substitution `^old^new^` at the start of the line,
+/// returning the equivalent designators of `!!:s/old/new/` with the number of bytes it takes
+pub(crate) fn parse_quick_substitution(buffer: &str) -> Option<(Designators, usize)> {
+    let text = buffer.strip_prefix('^')?;
+    // unlike `:s` the line needs the second `^`
+    text.find('^')?;
+    let (modifier, len) = parse_substitution(buffer, false)?;
+    Some((
+        Designators {
+            words: None,
+            modifiers: vec![modifier],
+        },
+        len,
+    ))
+}
+
+/// Splits a command line into words like bash, keeping quoted whitespace and the quotes
+fn split_words(command_line: &str) -> Vec<&str> {
+    let mut words = vec![];
+    let mut start = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (position, c) in command_line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (c, quote) {
+            ('\\', q) if q != Some('\'') => escaped = true,
+            ('\'' | '"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if let Some(word_start) = start.take() {
+                    words.push(&command_line[word_start..position]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(position);
+    }
+    if let Some(word_start) = start {
+        words.push(&command_line[word_start..]);
+    }
+    words
+}
+
+impl Designators {
+    /// Applies the designators to the command line of a history entry,
+    /// `None` if the selected words don't exist or a substitution finds nothing to replace
+    pub(crate) fn expand(&self, command_line: &str) -> Option<String> {
+        let mut expansion = match self.words {
+            None => command_line.to_string(),
+            Some(words) => {
+                let split = split_words(command_line);
+                let last = split.len().checked_sub(1)?;
+                let start = words.start.unwrap_or(last);
+                let end = words.end.unwrap_or(last);
+                let end = if words.skip_last {
+                    end.checked_sub(1)?
+                } else {
+                    end
+                };
+                let empty = start == end + 1;
+                if end > last || start > end + 1 || (empty && !words.allow_empty) {
+                    return None;
+                }
+                split[start..=end].join(" ")
+            }
+        };
+        for modifier in &self.modifiers {
+            expansion = match modifier {
+                Modifier::Head => match expansion.rfind('/') {
+                    Some(0) => "/".to_string(),
+                    Some(position) => expansion[..position].to_string(),
+                    None => expansion,
+                },
+                Modifier::Tail => match expansion.rfind('/') {
+                    Some(position) => expansion[position + 1..].to_string(),
+                    None => expansion,
+                },
+                Modifier::Substitute { old, new, global } => {
+                    if !expansion.contains(old.as_str()) {
+                        return None;
+                    }
+                    // `&` in the replacement stands for the replaced text
+                    let new = new.replace('&', old);
+                    if *global {
+                        expansion.replace(old.as_str(), &new)
+                    } else {
+                        expansion.replacen(old.as_str(), &new, 1)
+                    }
+                }
+            };
+        }
+        Some(expansion)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    const COMMAND_LINE: &str = "cp -r \"my dir\" /tmp/backup/old.tar";
+
+    #[rstest]
+    #[case(":0", "cp", 2)]
+    #[case(":2", "\"my dir\"", 2)]
+    #[case("^", "-r", 1)]
+    #[case("$", "/tmp/backup/old.tar", 1)]
+    #[case("*", "-r \"my dir\" /tmp/backup/old.tar", 1)]
+    #[case(":1-2", "-r \"my dir\"", 4)]
+    #[case(":-1", "cp -r", 3)]
+    #[case(":2*", "\"my dir\" /tmp/backup/old.tar", 3)]
+    #[case(":1-", "-r \"my dir\"", 3)]
+    #[case("$:h", "/tmp/backup", 3)]
+    #[case("$:t", "old.tar", 3)]
+    #[case("$:h:t", "backup", 5)]
+    #[case(":s/old/new/ rest", "cp -r \"my dir\" /tmp/backup/new.tar", 11)]
+    #[case(":gs/o/0", "cp -r \"my dir\" /tmp/backup/0ld.tar", 7)]
+    #[case(":s|my dir|&s|", "cp -r \"my dirs\" /tmp/backup/old.tar", 13)]
+    #[case(" :2", COMMAND_LINE, 0)]
+    #[case(":x", COMMAND_LINE, 0)]
+    fn expands_designators(#[case] text: &str, #[case] expansion: &str, #[case] consumed: usize) {
+        let (designators, len) = parse_designators(text);
+        assert_eq!(len, consumed);
+        assert_eq!(designators.expand(COMMAND_LINE).unwrap(), expansion);
+    }
+
+    #[rstest]
+    #[case(":5")]
+    #[case(":3-1")]
+    #[case(":s/missing/found/")]
+    fn fails_like_bash(#[case] text: &str) {
+        let (designators, _) = parse_designators(text);
+        assert_eq!(designators.expand(COMMAND_LINE), None);
+    }
+
+    #[test]
+    fn star_of_a_single_word_is_empty() {
+        let (designators, _) = parse_designators("*");
+        assert_eq!(designators.expand("ls").unwrap(), "");
+    }
+
+    #[test]
+    fn quick_substitution_replaces_the_first_match() {
+        let (designators, len) = parse_quick_substitution("^old^new^ -v").unwrap();
+        assert_eq!(len, 9);
+        assert_eq!(
+            designators.expand("mv old old.bak").unwrap(),
+            "mv new old.bak"
+        );
+        assert_eq!(parse_quick_substitution("^old"), None);
+    }
+
+    #[test]
+    fn finds_the_shorthands_of_the_last_command() {
+        assert_eq!(find_last_command_shorthand("echo !^ !*"), Some(5));
+        assert_eq!(find_last_command_shorthand("echo !!"), None);
+    }
+}
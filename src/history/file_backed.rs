@@ -1,5 +1,6 @@
 use super::{
-    base::CommandLineSearch, History, HistoryItem, HistoryItemId, SearchDirection, SearchQuery,
+    base::{rank_by_frecency, rank_fuzzy_matches, sort_by_outcome, CommandLineSearch},
+    History, HistoryItem, HistoryItemId, NewItemCheck, SearchDirection, SearchOrder, SearchQuery,
 };
 use crate::{
     result::{ReedlineError, ReedlineErrorVariants},
@@ -7,11 +8,13 @@ use crate::{
 };
 
 use std::{
-    collections::VecDeque,
-    fs::OpenOptions,
-    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    collections::{hash_map::DefaultHasher, VecDeque},
+    fs::{File, OpenOptions},
+    hash::Hasher,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     ops::{Deref, DerefMut},
     path::PathBuf,
+    time::SystemTime,
 };
 
 /// Default size of the [`FileBackedHistory`] used when calling [`FileBackedHistory::default()`]
@@ -24,13 +27,87 @@ pub const NEWLINE_ESCAPE: &str = "<\\n>";
 /// Similar to bash's behavior without HISTTIMEFORMAT.
 /// (See <https://www.gnu.org/software/bash/manual/html_node/Bash-History-Facilities.html>)
 /// If the history is associated to a file all new changes within a given history capacity will be written to disk when History is dropped.
+/// Entries deleted after they were written to disk are removed from the file on the next sync.
+///
+/// In the shared mode (see [`FileBackedHistory::with_shared_history()`]) every sync also picks
+/// up the entries other sessions appended to the file since the last one.
 #[derive(Debug)]
 pub struct FileBackedHistory {
     capacity: usize,
     entries: VecDeque<String>,
     file: Option<PathBuf>,
-    len_on_disk: usize, // Keep track what was previously written to disk
+    len_on_disk: usize,         // Keep track what was previously written to disk
+    dropped_from_disk: usize,   // Entries written to disk but dropped from memory due to capacity
+    tombstones: Vec<Tombstone>, // Entries deleted from memory that still have to be removed from disk
     session: Option<HistorySessionId>,
+    shared: bool,
+    session_start: usize, // Index of the first entry saved in this session, in the shared mode
+    synced_file: Option<FileState>, // The file as left by the last sync
+}
+
+/// How many bytes before the end of the history file as of the last sync are compared to
+/// detect a rewrite by another session
+const SYNCED_TAIL_LEN: u64 = 4096;
+
+/// Size, modification time and hash of the end of the history file, to detect changes by
+/// other sessions without reading all of it.
+#[derive(Debug, Clone, Copy)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+    tail_hash: u64,
+}
+
+impl FileState {
+    fn of(file: &mut File) -> std::io::Result<Self> {
+        let metadata = file.metadata()?;
+        Ok(FileState {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            tail_hash: hash_tail(file, metadata.len())?,
+        })
+    }
+
+    /// Whether the file only grew by appended entries since it was in this state
+    fn appended_to(&self, file: &mut File) -> std::io::Result<bool> {
+        let metadata = file.metadata()?;
+        if metadata.len() < self.len {
+            return Ok(false);
+        }
+        if metadata.len() == self.len
+            && self.modified.is_some()
+            && metadata.modified().ok() == self.modified
+        {
+            return Ok(true);
+        }
+        Ok(hash_tail(file, self.len)? == self.tail_hash)
+    }
+}
+
+/// Hashes the last [`SYNCED_TAIL_LEN`] bytes of the first `len` bytes of the file
+fn hash_tail(file: &mut File, len: u64) -> std::io::Result<u64> {
+    let start = len.saturating_sub(SYNCED_TAIL_LEN);
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::new(file.take(len - start));
+    let mut hasher = DefaultHasher::new();
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        hasher.write(buffer);
+        let read = buffer.len();
+        reader.consume(read);
+    }
+    Ok(hasher.finish())
+}
+
+/// An entry deleted after it was written to disk.
+#[derive(Debug)]
+struct Tombstone {
+    /// Line of the entry in the file as of the last sync
+    line: usize,
+    command_line: String,
 }
 
 impl Default for FileBackedHistory {
@@ -49,14 +126,52 @@ impl Default for FileBackedHistory {
     }
 }
 
-fn encode_entry(s: &str) -> String {
+pub(super) fn encode_entry(s: &str) -> String {
     s.replace('\n', NEWLINE_ESCAPE)
 }
 
-fn decode_entry(s: &str) -> String {
+pub(super) fn decode_entry(s: &str) -> String {
     s.replace(NEWLINE_ESCAPE, "\n")
 }
 
+/// Removes the entries of this session from the lines read from the file and returns those
+/// found, the most recent occurrences are taken to be the ones of this session.
+fn take_own_entries(lines: &mut VecDeque<String>, own_entries: Vec<String>) -> Vec<String> {
+    let mut end = lines.len();
+    let mut found = Vec::with_capacity(own_entries.len());
+    for entry in own_entries.into_iter().rev() {
+        if let Some(idx) = lines.range(..end).rposition(|line| *line == entry) {
+            lines.remove(idx);
+            end = idx;
+            found.push(entry);
+        }
+    }
+    found.reverse();
+    found
+}
+
+/// Removes the lines of deleted entries, returns whether anything was removed.
+///
+/// Other sessions might have truncated the file since the last sync, so the matching line
+/// closest to the recorded position is removed.
+fn remove_tombstoned(lines: &mut VecDeque<String>, tombstones: &mut Vec<Tombstone>) -> bool {
+    tombstones.sort_unstable_by_key(|t| std::cmp::Reverse(t.line));
+    let mut removed = false;
+    for tombstone in tombstones.drain(..) {
+        let closest = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| **line == tombstone.command_line)
+            .min_by_key(|(idx, _)| idx.abs_diff(tombstone.line))
+            .map(|(idx, _)| idx);
+        if let Some(idx) = closest {
+            lines.remove(idx);
+            removed = true;
+        }
+    }
+    removed
+}
+
 impl History for FileBackedHistory {
     /// only saves a value if it's different than the last value
     fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
@@ -68,7 +183,11 @@ impl History for FileBackedHistory {
                     // History is "full", so we delete the oldest entry first,
                     // before adding a new one.
                     self.entries.pop_front();
-                    self.len_on_disk = self.len_on_disk.saturating_sub(1);
+                    self.session_start = self.session_start.saturating_sub(1);
+                    if self.len_on_disk > 0 {
+                        self.len_on_disk -= 1;
+                        self.dropped_from_disk += 1;
+                    }
                 }
                 self.entries.push_back(entry.to_string());
                 Some(HistoryItemId::new((self.entries.len() - 1) as i64))
@@ -109,6 +228,10 @@ impl History for FileBackedHistory {
             || query.filter.cwd_exact.is_some()
             || query.filter.cwd_prefix.is_some()
             || query.filter.exit_successful.is_some()
+            || query.filter.min_duration.is_some()
+            || query.filter.max_duration.is_some()
+            || !query.filter.tags.is_empty()
+            || !query.filter.more_info.is_empty()
         {
             return Err(ReedlineError(
                 ReedlineErrorVariants::HistoryFeatureUnsupported {
@@ -142,22 +265,21 @@ impl History for FileBackedHistory {
             intrinsic_limit as usize
         };
         let filter = |(idx, cmd): (usize, &String)| {
-            if !match &query.filter.command_line {
-                Some(CommandLineSearch::Prefix(p)) => cmd.starts_with(p),
-                Some(CommandLineSearch::Substring(p)) => cmd.contains(p),
-                Some(CommandLineSearch::Exact(p)) => cmd == p,
-                None => true,
-            } {
-                return None;
-            }
+            let score = match &query.filter.command_line {
+                Some(search) => search.match_score(cmd)?,
+                None => 0,
+            };
             if let Some(str) = &query.filter.not_command_line {
                 if cmd == str {
                     return None;
                 }
             }
-            Some(FileBackedHistory::construct_entry(
-                Some(HistoryItemId::new(idx as i64)),
-                cmd.to_string(), // todo: this copy might be a perf bottleneck
+            Some((
+                score,
+                FileBackedHistory::construct_entry(
+                    Some(HistoryItemId::new(idx as i64)),
+                    cmd.to_string(), // todo: this copy might be a perf bottleneck
+                ),
             ))
         };
 
@@ -167,10 +289,25 @@ impl History for FileBackedHistory {
             .enumerate()
             .skip(min_id as usize)
             .take(intrinsic_limit as usize);
-        if let SearchDirection::Backward = query.direction {
-            Ok(iter.rev().filter_map(filter).take(limit).collect())
+        let iter: Box<dyn Iterator<Item = _>> = if let SearchDirection::Backward = query.direction {
+            Box::new(iter.rev())
+        } else {
+            Box::new(iter)
+        };
+        let matches = iter.filter_map(filter);
+        if query.order == SearchOrder::Frecency {
+            let matches = matches.map(|(_, item)| item).collect();
+            Ok(rank_by_frecency(
+                matches,
+                query.limit.map_or(usize::MAX, |l| l as usize),
+            ))
+        } else if query.order != SearchOrder::Chronological {
+            let matches = matches.map(|(_, item)| item).collect();
+            Ok(sort_by_outcome(matches, query.order, limit))
+        } else if let Some(CommandLineSearch::Fuzzy(_)) = &query.filter.command_line {
+            Ok(rank_fuzzy_matches(matches.collect(), limit))
         } else {
-            Ok(iter.filter_map(filter).take(limit).collect())
+            Ok(matches.take(limit).map(|(_, item)| item).collect())
         }
     }
 
@@ -190,6 +327,10 @@ impl History for FileBackedHistory {
     fn clear(&mut self) -> Result<()> {
         self.entries.clear();
         self.len_on_disk = 0;
+        self.dropped_from_disk = 0;
+        self.tombstones.clear();
+        self.session_start = 0;
+        self.synced_file = None;
 
         if let Some(file) = &self.file {
             if let Err(err) = std::fs::remove_file(file) {
@@ -200,18 +341,47 @@ impl History for FileBackedHistory {
         Ok(())
     }
 
-    fn delete(&mut self, _h: super::HistoryItemId) -> Result<()> {
-        Err(ReedlineError(
-            ReedlineErrorVariants::HistoryFeatureUnsupported {
-                history: "FileBackedHistory",
-                feature: "removing entries",
-            },
-        ))
+    /// Removes the entry from memory right away.
+    ///
+    /// If the entry was already written to disk, it is removed from the file on the next
+    /// [`FileBackedHistory::sync()`], so that it doesn't come back when the file is read again.
+    fn delete(&mut self, h: super::HistoryItemId) -> Result<()> {
+        let idx = usize::try_from(h.0)
+            .ok()
+            .filter(|idx| *idx < self.entries.len())
+            .ok_or(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                "Item does not exist",
+            )))?;
+        let command_line = self
+            .entries
+            .remove(idx)
+            .expect("index was checked to be in bounds");
+        if idx < self.session_start {
+            self.session_start -= 1;
+        }
+        if idx < self.len_on_disk {
+            self.len_on_disk -= 1;
+            // Map the in-memory position back to the line in the file
+            let mut line = idx + self.dropped_from_disk;
+            let mut tombstone_lines: Vec<_> = self.tombstones.iter().map(|t| t.line).collect();
+            tombstone_lines.sort_unstable();
+            for tombstone_line in tombstone_lines {
+                if tombstone_line <= line {
+                    line += 1;
+                }
+            }
+            self.tombstones.push(Tombstone { line, command_line });
+        }
+        Ok(())
     }
 
     /// Writes unwritten history contents to disk.
     ///
     /// If file would exceed `capacity` truncates the oldest entries.
+    /// Entries deleted since the last sync are removed from the file.
+    ///
+    /// In the shared mode, entries other sessions appended to the file are inserted before
+    /// the entries of this session. If the file didn't change since the last sync, it isn't read.
     fn sync(&mut self) -> std::io::Result<()> {
         if let Some(fname) = &self.file {
             // The unwritten entries
@@ -230,12 +400,56 @@ impl History for FileBackedHistory {
                     .open(fname)?,
             );
             let mut writer_guard = f_lock.write()?;
-            let (mut foreign_entries, truncate) = {
+            if self.shared && self.tombstones.is_empty() {
+                if let Some(synced) = self.synced_file {
+                    // Unless another session rewrote the file, it only grew by appended entries
+                    if synced.appended_to(writer_guard.deref_mut())? {
+                        let mut reader = BufReader::new(writer_guard.deref_mut());
+                        reader.seek(SeekFrom::Start(synced.len))?;
+                        let appended = reader
+                            .lines()
+                            .map(|o| o.map(|i| decode_entry(&i)))
+                            .collect::<std::io::Result<Vec<_>>>()?;
+                        if self.entries.len() + appended.len() <= self.capacity {
+                            let own_entries = self.entries.range(self.len_on_disk..);
+                            let mut writer = BufWriter::new(writer_guard.deref_mut());
+                            writer.seek(SeekFrom::End(0))?;
+                            for line in own_entries {
+                                writer.write_all(encode_entry(line).as_bytes())?;
+                                writer.write_all("\n".as_bytes())?;
+                            }
+                            writer.flush()?;
+                            drop(writer);
+
+                            for line in appended {
+                                self.entries.insert(self.session_start, line);
+                                self.session_start += 1;
+                            }
+                            self.len_on_disk = self.entries.len();
+                            self.synced_file = Some(FileState::of(writer_guard.deref_mut())?);
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+            // This session's entries that are already in the file
+            let own_on_disk: Vec<String> = if self.shared {
+                self.entries
+                    .range(self.session_start.min(self.len_on_disk)..self.len_on_disk)
+                    .cloned()
+                    .collect()
+            } else {
+                vec![]
+            };
+            // the checks of the shared mode may have moved the position
+            writer_guard.rewind()?;
+            let (mut foreign_entries, rewrite) = {
                 let reader = BufReader::new(writer_guard.deref());
                 let mut from_file = reader
                     .lines()
                     .map(|o| o.map(|i| decode_entry(&i)))
                     .collect::<std::io::Result<VecDeque<_>>>()?;
+                let removed = remove_tombstoned(&mut from_file, &mut self.tombstones);
                 if from_file.len() + own_entries.len() > self.capacity {
                     (
                         from_file.split_off(
@@ -244,13 +458,13 @@ impl History for FileBackedHistory {
                         true,
                     )
                 } else {
-                    (from_file, false)
+                    (from_file, removed)
                 }
             };
 
             {
                 let mut writer = BufWriter::new(writer_guard.deref_mut());
-                if truncate {
+                if rewrite {
                     writer.rewind()?;
 
                     for line in &foreign_entries {
@@ -266,17 +480,26 @@ impl History for FileBackedHistory {
                 }
                 writer.flush()?;
             }
-            if truncate {
+            if rewrite {
                 let file = writer_guard.deref_mut();
                 let file_len = file.stream_position()?;
                 file.set_len(file_len)?;
             }
 
-            let own_entries = self.entries.drain(self.len_on_disk..);
+            let own_entries: Vec<_> = self.entries.drain(self.len_on_disk..).collect();
+            self.session_start = foreign_entries.len();
+            if self.shared {
+                // Keep the entries of this session after those of other sessions
+                let own_on_disk = take_own_entries(&mut foreign_entries, own_on_disk);
+                self.session_start = foreign_entries.len();
+                foreign_entries.extend(own_on_disk);
+            }
             foreign_entries.extend(own_entries);
             self.entries = foreign_entries;
 
             self.len_on_disk = self.entries.len();
+            self.dropped_from_disk = 0;
+            self.synced_file = Some(FileState::of(writer_guard.deref_mut())?);
         }
         Ok(())
     }
@@ -284,6 +507,16 @@ impl History for FileBackedHistory {
     fn session(&self) -> Option<HistorySessionId> {
         self.session
     }
+
+    /// Skips empty command lines and repetitions of the most recent one
+    fn new_item_check(&self) -> NewItemCheck {
+        let capacity = self.capacity;
+        Box::new(move |item, last| {
+            capacity > 0
+                && !item.command_line.is_empty()
+                && last.map_or(true, |last| last.command_line != item.command_line)
+        })
+    }
 }
 
 impl FileBackedHistory {
@@ -301,7 +534,12 @@ impl FileBackedHistory {
             entries: VecDeque::new(),
             file: None,
             len_on_disk: 0,
+            dropped_from_disk: 0,
+            tombstones: Vec::new(),
             session: None,
+            shared: false,
+            session_start: 0,
+            synced_file: None,
         })
     }
 
@@ -323,6 +561,19 @@ impl FileBackedHistory {
         Ok(hist)
     }
 
+    /// A builder that enables the shared mode, similar to zsh's `share_history`
+    ///
+    /// Every [`FileBackedHistory::sync()`] then picks up the entries other sessions appended
+    /// to the file, which are placed before the entries of this session so that those are
+    /// still found first when browsing the history.
+    /// Use it together with [`Reedline::with_shared_history()`](crate::Reedline::with_shared_history),
+    /// which syncs the history before every prompt.
+    #[must_use]
+    pub fn with_shared_history(mut self, enable: bool) -> Self {
+        self.shared = enable;
+        self
+    }
+
     // this history doesn't store any info except command line
     fn construct_entry(id: Option<HistoryItemId>, command_line: String) -> HistoryItem {
         HistoryItem {
@@ -334,6 +585,7 @@ impl FileBackedHistory {
             cwd: None,
             duration: None,
             exit_status: None,
+            tags: vec![],
             more_info: None,
         }
     }
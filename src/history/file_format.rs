@@ -0,0 +1,394 @@
+use super::{
+    file_backed::{decode_entry, encode_entry},
+    History, HistoryItem, SearchDirection, SearchQuery,
+};
+use crate::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+/// zsh escapes special bytes in its history file with this byte followed by the byte xor 32
+const ZSH_META: u8 = 0x83;
+
+/// History file formats of other shells (and reedline's own plaintext format)
+/// that can be converted from and to [`HistoryItem`]s.
+///
+/// All formats list the entries from oldest to newest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFileFormat {
+    /// bash `HISTFILE`, optionally with `#<unix timestamp>` lines before the entries
+    /// (written by bash when `HISTTIMEFORMAT` is set).
+    ///
+    /// Multiline entries can only be represented in files with timestamps.
+    Bash,
+    /// zsh history, in the `EXTENDED_HISTORY` format (`: <start>:<duration>;<command>`)
+    /// or as plain lines. Durations are in whole seconds.
+    Zsh,
+    /// fish's YAML-like `fish_history`
+    Fish,
+    /// The format of [`FileBackedHistory`](crate::FileBackedHistory):
+    /// one entry per line, only containing the command line.
+    Reedline,
+}
+
+impl HistoryFileFormat {
+    /// Parse the contents of a history file, oldest entry first.
+    ///
+    /// Lines that don't fit the format are read as plain command lines,
+    /// invalid UTF-8 is replaced.
+    pub fn parse(self, contents: &[u8]) -> Vec<HistoryItem> {
+        match self {
+            HistoryFileFormat::Bash => parse_bash(&String::from_utf8_lossy(contents)),
+            HistoryFileFormat::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(contents))),
+            HistoryFileFormat::Fish => parse_fish(&String::from_utf8_lossy(contents)),
+            HistoryFileFormat::Reedline => String::from_utf8_lossy(contents)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| HistoryItem::from_command_line(decode_entry(line)))
+                .collect(),
+        }
+    }
+
+    /// Write `items` (oldest first) in this format.
+    ///
+    /// Information the format has no place for is dropped.
+    pub fn write(self, items: &[HistoryItem], writer: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            HistoryFileFormat::Bash => write_bash(items, writer),
+            HistoryFileFormat::Zsh => {
+                for item in items {
+                    let line = format!(
+                        ": {}:{};{}\n",
+                        item.start_timestamp.map_or(0, |t| t.timestamp()),
+                        item.duration.map_or(0, |d| d.as_secs()),
+                        item.command_line.replace('\n', "\\\n")
+                    );
+                    writer.write_all(&metafy(line.as_bytes()))?;
+                }
+                Ok(())
+            }
+            HistoryFileFormat::Fish => {
+                for item in items {
+                    writeln!(writer, "- cmd: {}", escape_fish(&item.command_line))?;
+                    if let Some(timestamp) = item.start_timestamp {
+                        writeln!(writer, "  when: {}", timestamp.timestamp())?;
+                    }
+                }
+                Ok(())
+            }
+            HistoryFileFormat::Reedline => {
+                for item in items {
+                    writeln!(writer, "{}", encode_entry(&item.command_line))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Read a history file in the given format and save its entries to `history`.
+///
+/// Returns the number of imported entries.
+pub fn import_history(
+    history: &mut dyn History,
+    format: HistoryFileFormat,
+    mut reader: impl Read,
+) -> Result<usize> {
+    let mut contents = vec![];
+    reader.read_to_end(&mut contents)?;
+    let items = format.parse(&contents);
+    let count = items.len();
+    for item in items {
+        history.save(item)?;
+    }
+    history.sync()?;
+    Ok(count)
+}
+
+/// Write all entries of `history` in the given format, oldest first.
+///
+/// Returns the number of exported entries.
+pub fn export_history(
+    history: &dyn History,
+    format: HistoryFileFormat,
+    mut writer: impl Write,
+) -> Result<usize> {
+    let items = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+    format.write(&items, &mut writer)?;
+    writer.flush()?;
+    Ok(items.len())
+}
+
+fn from_unix_seconds(seconds: &str) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(seconds.trim().parse().ok()?, 0).single()
+}
+
+fn bash_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    line.strip_prefix('#')
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(from_unix_seconds)
+}
+
+fn parse_bash(contents: &str) -> Vec<HistoryItem> {
+    let mut items: Vec<HistoryItem> = vec![];
+    // like bash, the lines following a timestamp belong to the same entry
+    let mut after_timestamp = false;
+    for line in contents.lines() {
+        if let Some(timestamp) = bash_timestamp(line) {
+            let mut item = HistoryItem::from_command_line("");
+            item.start_timestamp = Some(timestamp);
+            items.push(item);
+            after_timestamp = true;
+        } else if after_timestamp {
+            let item = items.last_mut().expect("a timestamp started an entry");
+            if !item.command_line.is_empty() {
+                item.command_line.push('\n');
+            }
+            item.command_line.push_str(line);
+        } else {
+            items.push(HistoryItem::from_command_line(line));
+        }
+    }
+    items.retain(|item| !item.command_line.trim().is_empty());
+    items
+}
+
+fn write_bash(items: &[HistoryItem], writer: &mut impl Write) -> std::io::Result<()> {
+    // Without a timestamp line an entry would be appended to the previous timestamped one
+    // on reading, so once there is one, every entry gets one.
+    let mut last_timestamp = None;
+    for item in items {
+        if let Some(timestamp) = item.start_timestamp.or(last_timestamp) {
+            writeln!(writer, "#{}", timestamp.timestamp())?;
+            last_timestamp = Some(timestamp);
+        }
+        writeln!(writer, "{}", item.command_line)?;
+    }
+    Ok(())
+}
+
+fn unmetafy(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut bytes = bytes.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == ZSH_META {
+            if let Some(&escaped) = bytes.next() {
+                result.push(escaped ^ 32);
+            }
+        } else {
+            result.push(byte);
+        }
+    }
+    result
+}
+
+fn metafy(bytes: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    for &byte in bytes {
+        // NUL and the bytes zsh uses as internal tokens
+        if byte == 0 || (ZSH_META..=0xa2).contains(&byte) {
+            result.push(ZSH_META);
+            result.push(byte ^ 32);
+        } else {
+            result.push(byte);
+        }
+    }
+    result
+}
+
+fn parse_zsh_extended(line: &str) -> Option<HistoryItem> {
+    let (metadata, command_line) = line.strip_prefix(": ")?.split_once(';')?;
+    let (start, duration) = metadata.split_once(':')?;
+    let mut item = HistoryItem::from_command_line(command_line);
+    item.start_timestamp = Some(from_unix_seconds(start)?);
+    item.duration = Some(Duration::from_secs(duration.trim().parse().ok()?));
+    Some(item)
+}
+
+fn parse_zsh(contents: &str) -> Vec<HistoryItem> {
+    let mut items = vec![];
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        // newlines within an entry are escaped with a backslash
+        let mut entry = line.to_string();
+        while entry.ends_with('\\') {
+            match lines.next() {
+                Some(next) => {
+                    entry.pop();
+                    entry.push('\n');
+                    entry.push_str(next);
+                }
+                None => break,
+            }
+        }
+        if entry.trim().is_empty() {
+            continue;
+        }
+        items.push(
+            parse_zsh_extended(&entry).unwrap_or_else(|| HistoryItem::from_command_line(entry)),
+        );
+    }
+    items
+}
+
+fn escape_fish(command_line: &str) -> String {
+    command_line.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape_fish(escaped: &str) -> String {
+    let mut result = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => result.push('\\'),
+            Some('n') => result.push('\n'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+fn parse_fish(contents: &str) -> Vec<HistoryItem> {
+    let mut items: Vec<HistoryItem> = vec![];
+    for line in contents.lines() {
+        if let Some(command_line) = line.strip_prefix("- cmd: ") {
+            items.push(HistoryItem::from_command_line(unescape_fish(command_line)));
+        } else if let Some(when) = line.strip_prefix("  when: ") {
+            if let Some(item) = items.last_mut() {
+                item.start_timestamp = from_unix_seconds(when);
+            }
+        }
+        // other keys (like the `paths` of an entry) have no equivalent
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileBackedHistory;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    fn item(command_line: &str, start: Option<i64>, duration: Option<u64>) -> HistoryItem {
+        let mut item = HistoryItem::from_command_line(command_line);
+        item.start_timestamp = start.map(|s| Utc.timestamp_opt(s, 0).unwrap());
+        item.duration = duration.map(Duration::from_secs);
+        item
+    }
+
+    #[test]
+    fn parses_bash_with_timestamps() {
+        let contents = b"ls\n#1700000000\ngit status\n#1700000005\nfor i in 1 2; do\n  echo $i\ndone\n#comment\n";
+        assert_eq!(
+            HistoryFileFormat::Bash.parse(contents),
+            vec![
+                item("ls", None, None),
+                item("git status", Some(1700000000), None),
+                item(
+                    "for i in 1 2; do\n  echo $i\ndone\n#comment",
+                    Some(1700000005),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_zsh_extended_history() {
+        let mut contents =
+            b": 1700000000:3;cargo build\\\n--release\nls\n: 1700000010:0;echo ".to_vec();
+        // "é" metafied
+        contents.extend([0xc3, ZSH_META, 0xa9 ^ 32, b'\n']);
+        assert_eq!(
+            HistoryFileFormat::Zsh.parse(&contents),
+            vec![
+                item("cargo build\n--release", Some(1700000000), Some(3)),
+                item("ls", None, None),
+                item("echo é", Some(1700000010), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_fish_history() {
+        let contents =
+            b"- cmd: echo a\\\\b\\nc\n  when: 1700000000\n  paths:\n    - a\n- cmd: ls\n";
+        assert_eq!(
+            HistoryFileFormat::Fish.parse(contents),
+            vec![
+                item("echo a\\b\nc", Some(1700000000), None),
+                item("ls", None, None),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(HistoryFileFormat::Bash)]
+    #[case(HistoryFileFormat::Zsh)]
+    #[case(HistoryFileFormat::Fish)]
+    fn round_trips_timestamps(#[case] format: HistoryFileFormat) {
+        let items = vec![
+            item("cargo test", Some(1700000000), Some(12)),
+            item("echo 'multi\nline' é \\ x", Some(1700000020), Some(0)),
+        ];
+        let mut written = vec![];
+        format.write(&items, &mut written).unwrap();
+        let parsed = format.parse(&written);
+        assert_eq!(
+            parsed
+                .iter()
+                .map(|i| (i.command_line.as_str(), i.start_timestamp))
+                .collect::<Vec<_>>(),
+            items
+                .iter()
+                .map(|i| (i.command_line.as_str(), i.start_timestamp))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn writes_zsh_extended_history() {
+        let mut written = vec![];
+        HistoryFileFormat::Zsh
+            .write(
+                &[item("cargo test", Some(1700000000), Some(12))],
+                &mut written,
+            )
+            .unwrap();
+        assert_eq!(written, b": 1700000000:12;cargo test\n");
+    }
+
+    #[test]
+    fn imports_and_exports_through_history() -> Result<()> {
+        let mut history = FileBackedHistory::default();
+        let imported = import_history(
+            &mut history,
+            HistoryFileFormat::Bash,
+            &b"#1700000000\nls\n#1700000001\necho 'a\nb'\n"[..],
+        )?;
+        assert_eq!(imported, 2);
+
+        let mut exported = vec![];
+        assert_eq!(
+            export_history(&history, HistoryFileFormat::Reedline, &mut exported)?,
+            2
+        );
+        assert_eq!(exported, b"ls\necho 'a<\\n>b'\n");
+        assert_eq!(
+            HistoryFileFormat::Reedline.parse(&exported),
+            vec![item("ls", None, None), item("echo 'a\nb'", None, None)]
+        );
+        Ok(())
+    }
+}
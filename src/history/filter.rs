@@ -0,0 +1,371 @@
+use super::{History, HistoryItem, HistoryItemId, HistorySessionId, SearchQuery};
+use crate::{
+    result::{ReedlineError, ReedlineErrorVariants},
+    Result,
+};
+
+/// Decides whether and how a submitted command line ends up in the [`History`].
+///
+/// Used by [`Reedline`](crate::Reedline) before saving a submitted line and when showing
+/// already saved entries in hints and menus, so entries saved before the filter was set up
+/// don't leak either.
+///
+/// Any `Fn(HistoryItem) -> Option<HistoryItem>` closure is a `HistoryFilter`, e.g. to refuse
+/// command lines matching your own patterns:
+///
+/// ```rust
+/// use reedline::{HistoryItem, Reedline};
+///
+/// let line_editor = Reedline::create().with_history_filter(Box::new(|item: HistoryItem| {
+///     (!item.command_line.contains("vault read")).then_some(item)
+/// }));
+/// ```
+pub trait HistoryFilter: Send {
+    /// Returns the item to keep, possibly redacted or rewritten,
+    /// or `None` to keep it out of the history.
+    fn filter(&self, item: HistoryItem) -> Option<HistoryItem>;
+}
+
+impl<F> HistoryFilter for F
+where
+    F: Fn(HistoryItem) -> Option<HistoryItem> + Send,
+{
+    fn filter(&self, item: HistoryItem) -> Option<HistoryItem> {
+        self(item)
+    }
+}
+
+/// A [`HistoryFilter`] that masks secrets in command lines
+///
+/// Masks the values of assignments (`AWS_SECRET_ACCESS_KEY=...`, `--password=...`) and the
+/// arguments of flags (`--password xyz`) whose name contains one of the secret names.
+/// Names are matched case-insensitively.
+pub struct DefaultHistoryFilter {
+    secret_names: Vec<String>,
+    mask: String,
+}
+
+impl Default for DefaultHistoryFilter {
+    fn default() -> Self {
+        DefaultHistoryFilter {
+            secret_names: ["password", "passwd", "secret", "token", "api_key", "apikey"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            mask: "***".to_string(),
+        }
+    }
+}
+
+impl HistoryFilter for DefaultHistoryFilter {
+    fn filter(&self, mut item: HistoryItem) -> Option<HistoryItem> {
+        if let Some(redacted) = self.redact(&item.command_line) {
+            item.command_line = redacted;
+        }
+        Some(item)
+    }
+}
+
+impl DefaultHistoryFilter {
+    /// A builder that sets the names identifying secret variables and flags
+    #[must_use]
+    pub fn with_secret_names(mut self, secret_names: Vec<String>) -> Self {
+        self.secret_names = secret_names
+            .into_iter()
+            .map(|name| name.to_lowercase())
+            .collect();
+        self
+    }
+
+    /// A builder that sets the text replacing the secrets
+    #[must_use]
+    pub fn with_mask(mut self, mask: String) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    fn is_secret(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.secret_names
+            .iter()
+            .any(|secret| name.contains(secret.as_str()))
+    }
+
+    /// The redacted command line, `None` if there is nothing to redact
+    fn redact(&self, command_line: &str) -> Option<String> {
+        let mut secrets = vec![];
+        let mut flag_value_is_secret = false;
+        for (start, word) in words(command_line) {
+            if std::mem::take(&mut flag_value_is_secret) && !word.starts_with('-') {
+                secrets.push(start..start + word.len());
+            } else if let Some((name, value)) = word.split_once('=') {
+                if !value.is_empty() && self.is_secret(name) {
+                    secrets.push(start + name.len() + 1..start + word.len());
+                }
+            } else if word.starts_with('-') {
+                flag_value_is_secret = self.is_secret(word);
+            }
+        }
+        if secrets.is_empty() {
+            return None;
+        }
+        let mut redacted = String::with_capacity(command_line.len());
+        let mut copied = 0;
+        for secret in secrets {
+            redacted.push_str(&command_line[copied..secret.start]);
+            redacted.push_str(&self.mask);
+            copied = secret.end;
+        }
+        redacted.push_str(&command_line[copied..]);
+        Some(redacted)
+    }
+}
+
+/// Whitespace separated words with their byte offset, quoted whitespace doesn't separate words
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = vec![];
+    let mut start = None;
+    let mut quote = None;
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, c) if c.is_whitespace() => {
+                if let Some(start) = start.take() {
+                    words.push((start, &line[start..idx]));
+                }
+            }
+            (None, c) => {
+                if matches!(c, '"' | '\'') {
+                    quote = Some(c);
+                }
+                start.get_or_insert(idx);
+            }
+        }
+    }
+    if let Some(start) = start {
+        words.push((start, &line[start..]));
+    }
+    words
+}
+
+/// Read-only view of a [`History`] with a [`HistoryFilter`] applied to every item read,
+/// as shown in hints and menus
+///
+/// Holds mutable references only because those are `Send` for `Send` types, as required by [`History`].
+pub(crate) struct FilteredHistory<'a> {
+    history: &'a mut (dyn History + 'static),
+    filter: Option<&'a mut (dyn HistoryFilter + 'static)>,
+}
+
+impl<'a> FilteredHistory<'a> {
+    pub(crate) fn new(
+        history: &'a mut (dyn History + 'static),
+        filter: Option<&'a mut (dyn HistoryFilter + 'static)>,
+    ) -> Self {
+        FilteredHistory { history, filter }
+    }
+}
+
+fn read_only() -> ReedlineError {
+    ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported {
+        history: "FilteredHistory",
+        feature: "modifying entries",
+    })
+}
+
+/// Applies the filter to an item found by the query, dropping it if the filter rewrote its
+/// command line so that it no longer matches the query
+fn filter_matching(
+    filter: &dyn HistoryFilter,
+    query: &SearchQuery,
+    item: HistoryItem,
+) -> Option<HistoryItem> {
+    let original = item.command_line.clone();
+    let filtered = filter.filter(item)?;
+    if filtered.command_line == original {
+        return Some(filtered);
+    }
+    let filter = &query.filter;
+    let matches = filter.command_line.as_ref().map_or(true, |search| {
+        search.match_score(&filtered.command_line).is_some()
+    }) && filter.not_command_line.as_ref() != Some(&filtered.command_line);
+    matches.then_some(filtered)
+}
+
+impl History for FilteredHistory<'_> {
+    fn save(&mut self, _h: HistoryItem) -> Result<HistoryItem> {
+        Err(read_only())
+    }
+
+    fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+        let item = self.history.load(id)?;
+        match &self.filter {
+            Some(filter) => {
+                filter
+                    .filter(item)
+                    .ok_or(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                        "Item does not exist",
+                    )))
+            }
+            None => Ok(item),
+        }
+    }
+
+    fn count(&self, query: SearchQuery) -> Result<i64> {
+        match &self.filter {
+            Some(_) => Ok(self.search(query)?.len() as i64),
+            None => self.history.count(query),
+        }
+    }
+
+    fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+        let filter = match &self.filter {
+            Some(filter) => filter,
+            None => return self.history.search(query),
+        };
+        let limit = query.limit;
+        let results = self.history.search(query.clone())?;
+        let found = results.len();
+        let mut filtered: Vec<_> = results
+            .into_iter()
+            .filter_map(|item| filter_matching(&**filter, &query, item))
+            .collect();
+        if let Some(limit) = limit {
+            // fill up the places of dropped items
+            if filtered.len() < found {
+                let unlimited = SearchQuery {
+                    limit: None,
+                    ..query.clone()
+                };
+                filtered = self
+                    .history
+                    .search(unlimited)?
+                    .into_iter()
+                    .filter_map(|item| filter_matching(&**filter, &query, item))
+                    .take(limit.max(0) as usize)
+                    .collect();
+            }
+        }
+        Ok(filtered)
+    }
+
+    fn update(
+        &mut self,
+        _id: HistoryItemId,
+        _updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> Result<()> {
+        Err(read_only())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        Err(read_only())
+    }
+
+    fn delete(&mut self, _h: HistoryItemId) -> Result<()> {
+        Err(read_only())
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        self.history.session()
+    }
+
+    fn session_timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.history.session_timestamp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileBackedHistory, SearchDirection};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("ls -l", "ls -l")]
+    #[case(
+        "AWS_SECRET_ACCESS_KEY=abc aws s3 ls",
+        "AWS_SECRET_ACCESS_KEY=*** aws s3 ls"
+    )]
+    #[case("export GITHUB_TOKEN='a b'", "export GITHUB_TOKEN=***")]
+    #[case(
+        "mysql -u root --password hunter2 db",
+        "mysql -u root --password *** db"
+    )]
+    #[case("mysql --password=hunter2", "mysql --password=***")]
+    #[case(
+        "docker login --password-stdin -u me",
+        "docker login --password-stdin -u me"
+    )]
+    #[case("echo PASSWORD=", "echo PASSWORD=")]
+    fn redacts_secrets(#[case] command_line: &str, #[case] expected: &str) {
+        let filtered = DefaultHistoryFilter::default()
+            .filter(HistoryItem::from_command_line(command_line))
+            .unwrap();
+        assert_eq!(filtered.command_line, expected);
+    }
+
+    #[test]
+    fn custom_secret_names_and_mask() {
+        let filter = DefaultHistoryFilter::default()
+            .with_secret_names(vec!["PIN".to_string()])
+            .with_mask("<redacted>".to_string());
+        let filtered = filter
+            .filter(HistoryItem::from_command_line(
+                "unlock --pin 1234 --token x",
+            ))
+            .unwrap();
+        assert_eq!(filtered.command_line, "unlock --pin <redacted> --token x");
+    }
+
+    #[test]
+    fn view_applies_filter_and_fills_limit() -> Result<()> {
+        let mut history = FileBackedHistory::default();
+        for command_line in ["ls", "echo secret", "cat PASSWORD=x", "echo secret2"] {
+            history.save(HistoryItem::from_command_line(command_line))?;
+        }
+        let mut filter = |item: HistoryItem| {
+            let item = (!item.command_line.starts_with("echo secret")).then_some(item)?;
+            DefaultHistoryFilter::default().filter(item)
+        };
+        let view = FilteredHistory::new(&mut history, Some(&mut filter));
+
+        let query = SearchQuery {
+            limit: Some(2),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        };
+        let found: Vec<_> = view
+            .search(query)?
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        assert_eq!(found, vec!["cat PASSWORD=***", "ls"]);
+        assert!(view.load(HistoryItemId::new(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn view_drops_items_rewritten_so_they_no_longer_match() -> Result<()> {
+        let mut history = FileBackedHistory::default();
+        for command_line in ["mysql --password hunter2", "mysql --password hx"] {
+            history.save(HistoryItem::from_command_line(command_line))?;
+        }
+        let mut filter = DefaultHistoryFilter::default();
+        let view = FilteredHistory::new(&mut history, Some(&mut filter));
+
+        let hint = view.search(SearchQuery::last_with_prefix(
+            "mysql --password h".to_string(),
+            None,
+        ))?;
+        assert!(hint.is_empty());
+        // still found by what the filter left
+        let redacted = view.search(SearchQuery::last_with_prefix("mysql".to_string(), None))?;
+        assert_eq!(redacted[0].command_line, "mysql --password ***");
+        Ok(())
+    }
+}
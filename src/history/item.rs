@@ -51,7 +51,12 @@ impl From<HistorySessionId> for i64 {
 }
 
 /// This trait represents additional arbitrary context to be added to a history (optional, see [`HistoryItem`])
-pub trait HistoryItemExtraInfo: Serialize + DeserializeOwned + Default + Send {}
+pub trait HistoryItemExtraInfo: Serialize + DeserializeOwned + Default + Send {
+    /// Whether the fields of the extra info can be inspected by the
+    /// [`SearchFilter::more_info`](crate::SearchFilter::more_info) filters of histories
+    /// searching in memory
+    const INSPECTABLE: bool = true;
+}
 
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 /// something that is serialized as null and deserialized by ignoring everything
@@ -75,7 +80,9 @@ impl<'de> Deserialize<'de> for IgnoreAllExtraInfo {
     }
 }
 
-impl HistoryItemExtraInfo for IgnoreAllExtraInfo {}
+impl HistoryItemExtraInfo for IgnoreAllExtraInfo {
+    const INSPECTABLE: bool = false;
+}
 
 /// Represents one run command with some optional additional context
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -97,6 +104,9 @@ pub struct HistoryItem<ExtraInfo: HistoryItemExtraInfo = IgnoreAllExtraInfo> {
     pub duration: Option<Duration>,
     /// the exit status of the command
     pub exit_status: Option<i64>,
+    /// tags given by the user, e.g. `deploy`, including [`HistoryItem::PINNED_TAG`] for pinned entries
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
     /// arbitrary additional information that might be interesting
     /// NOTE: this attribute is required because of
     /// <https://github.com/rust-lang/rust/issues/41617>
@@ -106,6 +116,10 @@ pub struct HistoryItem<ExtraInfo: HistoryItemExtraInfo = IgnoreAllExtraInfo> {
 }
 
 impl HistoryItem {
+    /// Tag of the pinned entries, which neither truncation nor a
+    /// [`RetentionPolicy`](crate::RetentionPolicy) ever remove
+    pub const PINNED_TAG: &'static str = "pinned";
+
     /// create a history item purely from the command line with everything else set to None
     pub fn from_command_line(cmd: impl Into<String>) -> HistoryItem {
         HistoryItem {
@@ -117,11 +131,24 @@ impl HistoryItem {
             cwd: None,
             duration: None,
             exit_status: None,
+            tags: vec![],
             more_info: None,
         }
     }
 }
 
+impl<ExtraInfo: HistoryItemExtraInfo> HistoryItem<ExtraInfo> {
+    /// Whether the item has the given tag
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    /// Whether the item is pinned, having the [`HistoryItem::PINNED_TAG`]
+    pub fn is_pinned(&self) -> bool {
+        self.has_tag(HistoryItem::PINNED_TAG)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,6 +181,7 @@ mod tests {
             cwd: None,
             duration: None,
             exit_status: None,
+            tags: vec![],
             more_info: Some(CustomExtraInfo {
                 mode: "shell".to_string(),
                 tags: vec!["test".to_string()],
@@ -177,6 +205,7 @@ mod tests {
             cwd: Some("/home/user".to_string()),
             duration: None,
             exit_status: Some(0),
+            tags: vec![],
             more_info: Some(CustomExtraInfo {
                 mode: "r".to_string(),
                 tags: vec!["data".to_string(), "analysis".to_string()],
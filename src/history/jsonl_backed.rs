@@ -0,0 +1,664 @@
+use super::{
+    History, HistoryItem, HistoryItemExtraInfo, HistoryItemId, IgnoreAllExtraInfo, NewItemCheck,
+    SearchQuery,
+};
+use crate::{
+    result::{ReedlineError, ReedlineErrorVariants},
+    HistorySessionId, Result,
+};
+use chrono::Utc;
+use serde_json::Value;
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::OpenOptions,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::{Deref, DerefMut},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+/// Default size of the [`JsonlBackedHistory`] used when calling [`JsonlBackedHistory::default()`]
+pub const JSONL_HISTORY_SIZE: usize = 10000;
+
+impl HistoryItemExtraInfo for Value {}
+
+/// Items as stored in the file, keeping whatever `more_info` other applications attached
+type StoredItem = HistoryItem<Value>;
+
+/// History that stores every [`HistoryItem`] with all its context as one JSON object per line.
+///
+/// Unlike [`crate::FileBackedHistory`] it keeps the `cwd`, `exit_status`, `duration`, `hostname`,
+/// `session_id`, `tags` and `more_info` of each command and can filter by them, without depending
+/// on SQLite.
+/// It uses the same file locking and capacity truncation as [`crate::FileBackedHistory`], so
+/// several sessions can share one file. Changes are written to disk on [`History::sync()`] and when
+/// the history is dropped.
+///
+/// Ids are derived from the time an item is saved, so they are unique across sessions sharing a file.
+///
+/// ## Required feature:
+/// `jsonl`
+#[derive(Debug)]
+pub struct JsonlBackedHistory {
+    capacity: usize,
+    // ordered by id
+    entries: VecDeque<StoredItem>,
+    file: Option<PathBuf>,
+    // Changes that still have to be written to disk
+    unsynced: HashSet<HistoryItemId>,
+    updated: HashSet<HistoryItemId>,
+    deleted: HashSet<HistoryItemId>,
+    session: Option<HistorySessionId>,
+    session_timestamp: Option<chrono::DateTime<Utc>>,
+}
+
+impl Default for JsonlBackedHistory {
+    /// Creates an in-memory [`History`] with a maximal capacity of [`JSONL_HISTORY_SIZE`].
+    ///
+    /// To create a [`History`] that is synchronized with a file use [`JsonlBackedHistory::with_file()`]
+    fn default() -> Self {
+        match Self::new(JSONL_HISTORY_SIZE) {
+            Ok(history) => history,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+fn to_public(item: &StoredItem) -> HistoryItem {
+    HistoryItem {
+        id: item.id,
+        start_timestamp: item.start_timestamp,
+        command_line: item.command_line.clone(),
+        session_id: item.session_id,
+        hostname: item.hostname.clone(),
+        cwd: item.cwd.clone(),
+        duration: item.duration,
+        exit_status: item.exit_status,
+        tags: item.tags.clone(),
+        more_info: item.more_info.as_ref().map(|_| IgnoreAllExtraInfo),
+    }
+}
+
+fn to_stored(item: HistoryItem, more_info: Option<Value>) -> StoredItem {
+    HistoryItem {
+        id: item.id,
+        start_timestamp: item.start_timestamp,
+        command_line: item.command_line,
+        session_id: item.session_id,
+        hostname: item.hostname,
+        cwd: item.cwd,
+        duration: item.duration,
+        exit_status: item.exit_status,
+        tags: item.tags,
+        more_info,
+    }
+}
+
+fn item_does_not_exist() -> ReedlineError {
+    ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+        "Item does not exist",
+    ))
+}
+
+fn encode_item(item: &StoredItem) -> std::io::Result<String> {
+    serde_json::to_string(item).map_err(std::io::Error::from)
+}
+
+fn decode_item(line: &[u8]) -> serde_json::Result<StoredItem> {
+    serde_json::from_slice(line)
+}
+
+impl History for JsonlBackedHistory {
+    /// Saves a new item, or replaces the item with the same id if it already exists
+    fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
+        if h.command_line.is_empty() || self.capacity == 0 {
+            return Ok(h);
+        }
+        if let Some(id) = h.id {
+            if let Some(pos) = self.position(id) {
+                let more_info = self.entries[pos].more_info.take();
+                self.entries[pos] = to_stored(h.clone(), more_info);
+                if !self.unsynced.contains(&id) {
+                    self.updated.insert(id);
+                }
+                return Ok(h);
+            }
+        }
+
+        let mut h = h;
+        let id = match h.id {
+            Some(id) => id,
+            None => self.next_id(),
+        };
+        h.id = Some(id);
+        if self.entries.len() >= self.capacity {
+            // History is "full", so we delete the oldest entry that isn't pinned first,
+            // before adding a new one.
+            let oldest = self.entries.iter().position(|e| !e.is_pinned());
+            if let Some(oldest) = oldest.and_then(|pos| self.entries.remove(pos)) {
+                // never written entries don't need to be written anymore,
+                // entries on disk are truncated on the next sync
+                self.unsynced
+                    .remove(&oldest.id.expect("stored items have an id"));
+            }
+        }
+        let pos = self.entries.partition_point(|e| e.id < Some(id));
+        self.entries.insert(pos, to_stored(h.clone(), None));
+        self.unsynced.insert(id);
+        Ok(h)
+    }
+
+    fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+        self.position(id)
+            .map(|pos| to_public(&self.entries[pos]))
+            .ok_or_else(item_does_not_exist)
+    }
+
+    fn count(&self, query: SearchQuery) -> Result<i64> {
+        Ok(query
+            .run_in_memory(self.entries.iter(), self.session_timestamp)
+            .len() as i64)
+    }
+
+    fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+        Ok(query
+            .run_in_memory(self.entries.iter(), self.session_timestamp)
+            .into_iter()
+            .map(to_public)
+            .collect())
+    }
+
+    fn update(
+        &mut self,
+        id: HistoryItemId,
+        updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> Result<()> {
+        let item = self.load(id)?;
+        let mut updated = updater(item);
+        updated.id = Some(id);
+        self.save(updated)?;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.entries.clear();
+        self.unsynced.clear();
+        self.updated.clear();
+        self.deleted.clear();
+
+        if let Some(file) = &self.file {
+            if let Err(err) = std::fs::remove_file(file) {
+                return Err(ReedlineError(ReedlineErrorVariants::IOError(err)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the entry from memory right away and from the file on the next sync
+    fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+        let pos = self.position(h).ok_or_else(item_does_not_exist)?;
+        self.entries.remove(pos);
+        self.updated.remove(&h);
+        if !self.unsynced.remove(&h) {
+            self.deleted.insert(h);
+        }
+        Ok(())
+    }
+
+    /// Writes unwritten history contents to disk and reads the entries of other sessions.
+    ///
+    /// If file would exceed `capacity` truncates the oldest entries that aren't pinned.
+    /// Lines that can't be read as an item are skipped, but kept in the file.
+    fn sync(&mut self) -> std::io::Result<()> {
+        let fname = match &self.file {
+            Some(fname) => fname,
+            None => return Ok(()),
+        };
+        let own_entries: Vec<StoredItem> = self
+            .entries
+            .iter()
+            .filter(|e| e.id.map_or(false, |id| self.unsynced.contains(&id)))
+            .cloned()
+            .collect();
+
+        if let Some(base_dir) = fname.parent() {
+            std::fs::create_dir_all(base_dir)?;
+        }
+
+        let mut f_lock = fd_lock::RwLock::new(
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .read(true)
+                .truncate(false)
+                .open(fname)?,
+        );
+        let mut writer_guard = f_lock.write()?;
+        let mut content = vec![];
+        BufReader::new(writer_guard.deref()).read_to_end(&mut content)?;
+        // e.g. the last line was cut off by a crash
+        let missing_newline = content.last().map_or(false, |last| *last != b'\n');
+        // Lines that aren't items, like cut off or edited ones, are kept as they are
+        let mut unreadable = vec![];
+        let (mut foreign_entries, rewrite) = {
+            let mut from_file = vec![];
+            for line in content.split(|b| *b == b'\n') {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                match decode_item(line) {
+                    Ok(item) => from_file.push(item),
+                    Err(_) => unreadable.push(line),
+                }
+            }
+
+            let mut rewrite = false;
+            from_file.retain(|e| {
+                let deleted = e.id.map_or(false, |id| self.deleted.contains(&id));
+                rewrite |= deleted;
+                !deleted
+            });
+            for e in from_file.iter_mut() {
+                let updated = e.id.filter(|id| self.updated.contains(id));
+                if let Some(pos) = updated.and_then(|id| self.position(id)) {
+                    *e = self.entries[pos].clone();
+                    rewrite = true;
+                }
+            }
+            if from_file.len() + own_entries.len() > self.capacity {
+                // the file may be out of order if sessions wrote concurrently
+                from_file.sort_by_key(|e| e.id);
+                let keep = self.capacity.saturating_sub(own_entries.len());
+                let mut excess = from_file.len().saturating_sub(keep);
+                from_file.retain(|e| {
+                    let drop = excess > 0 && !e.is_pinned();
+                    excess -= usize::from(drop);
+                    !drop
+                });
+                rewrite = true;
+            }
+            (from_file, rewrite)
+        };
+
+        {
+            let mut writer = BufWriter::new(writer_guard.deref_mut());
+            if rewrite {
+                writer.rewind()?;
+
+                for line in &unreadable {
+                    writer.write_all(line)?;
+                    writer.write_all("\n".as_bytes())?;
+                }
+                for item in &foreign_entries {
+                    writer.write_all(encode_item(item)?.as_bytes())?;
+                    writer.write_all("\n".as_bytes())?;
+                }
+            } else {
+                writer.seek(SeekFrom::End(0))?;
+                if missing_newline && !own_entries.is_empty() {
+                    writer.write_all("\n".as_bytes())?;
+                }
+            }
+            for item in &own_entries {
+                writer.write_all(encode_item(item)?.as_bytes())?;
+                writer.write_all("\n".as_bytes())?;
+            }
+            writer.flush()?;
+        }
+        if rewrite {
+            let file = writer_guard.deref_mut();
+            let file_len = file.stream_position()?;
+            file.set_len(file_len)?;
+        }
+
+        foreign_entries.extend(own_entries);
+        foreign_entries.sort_by_key(|e| e.id);
+        foreign_entries.dedup_by_key(|e| e.id);
+        self.entries = foreign_entries.into();
+        self.unsynced.clear();
+        self.updated.clear();
+        self.deleted.clear();
+        Ok(())
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        self.session
+    }
+
+    fn session_timestamp(&self) -> Option<chrono::DateTime<Utc>> {
+        self.session_timestamp
+    }
+
+    /// Skips empty command lines
+    fn new_item_check(&self) -> NewItemCheck {
+        let capacity = self.capacity;
+        Box::new(move |item, _| capacity > 0 && !item.command_line.is_empty())
+    }
+}
+
+impl JsonlBackedHistory {
+    /// Creates a new in-memory history that remembers `n <= capacity` elements
+    pub fn new(capacity: usize) -> Result<Self> {
+        if capacity == usize::MAX {
+            return Err(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                "History capacity too large to be addressed safely",
+            )));
+        }
+
+        Ok(JsonlBackedHistory {
+            capacity,
+            entries: VecDeque::new(),
+            file: None,
+            unsynced: HashSet::new(),
+            updated: HashSet::new(),
+            deleted: HashSet::new(),
+            session: None,
+            session_timestamp: None,
+        })
+    }
+
+    /// Creates a new history with an associated history file.
+    ///
+    /// History file format: one JSON serialized [`HistoryItem`] per line.
+    /// If file exists file will be read otherwise empty file will be created.
+    ///
+    ///
+    /// **Side effects:** creates all nested directories to the file
+    ///
+    pub fn with_file(capacity: usize, file: PathBuf) -> Result<Self> {
+        let mut hist = Self::new(capacity)?;
+        if let Some(base_dir) = file.parent() {
+            std::fs::create_dir_all(base_dir)?;
+        }
+        hist.file = Some(file);
+        hist.sync()?;
+        Ok(hist)
+    }
+
+    /// A builder that sets the session of this history.
+    ///
+    /// Queries filtering for the session return its items and the items from before
+    /// `session_timestamp`, like [`crate::SqliteBackedHistory`] does.
+    #[must_use]
+    pub fn with_session(
+        mut self,
+        session: Option<HistorySessionId>,
+        session_timestamp: Option<chrono::DateTime<Utc>>,
+    ) -> Self {
+        self.session = session;
+        self.session_timestamp = session_timestamp;
+        self
+    }
+
+    fn position(&self, id: HistoryItemId) -> Option<usize> {
+        let pos = self.entries.partition_point(|e| e.id < Some(id));
+        (self.entries.get(pos).and_then(|e| e.id) == Some(id)).then_some(pos)
+    }
+
+    /// Time based, so that ids of concurrent sessions don't collide and increase over time
+    fn next_id(&self) -> HistoryItemId {
+        let now = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(n) => n.as_nanos() as i64,
+            Err(_) => 0,
+        };
+        let after_last = self
+            .entries
+            .back()
+            .and_then(|e| e.id)
+            .map_or(0, |id| id.0 + 1);
+        HistoryItemId::new(now.max(after_last))
+    }
+}
+
+impl Drop for JsonlBackedHistory {
+    /// On drop the content of the [`History`] will be written to the file if specified via [`JsonlBackedHistory::with_file()`].
+    fn drop(&mut self) {
+        let _res = self.sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoreInfoFilter, SearchDirection, SearchFilter};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn create_item(cwd: &str, cmd: &str, exit_status: i64) -> HistoryItem {
+        HistoryItem {
+            id: None,
+            start_timestamp: None,
+            command_line: cmd.to_string(),
+            session_id: Some(HistorySessionId::new(1)),
+            hostname: Some("foohost".to_string()),
+            cwd: Some(cwd.to_string()),
+            duration: Some(Duration::from_millis(1000)),
+            exit_status: Some(exit_status),
+            tags: vec![],
+            more_info: None,
+        }
+    }
+
+    fn command_lines(items: Vec<HistoryItem>) -> Vec<String> {
+        items.into_iter().map(|e| e.command_line).collect()
+    }
+
+    fn all_command_lines(history: &dyn History) -> Vec<String> {
+        command_lines(
+            history
+                .search(SearchQuery::everything(SearchDirection::Forward, None))
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn persists_all_fields() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("nested").join("history.jsonl");
+
+        let saved = {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            history.save(create_item("/home/me", "ls", 0))?;
+            history.save(create_item("/etc", "cat\nmultiline", 1))?
+        };
+
+        let history = JsonlBackedHistory::with_file(10, histfile)?;
+        assert_eq!(history.load(saved.id.unwrap())?, saved);
+        assert_eq!(all_command_lines(&history), vec!["ls", "cat\nmultiline"]);
+        Ok(())
+    }
+
+    #[test]
+    fn lines_that_are_no_items_are_skipped_and_kept() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+        let saved = {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            history.save(create_item("/", "ls", 0))?
+        };
+        // edited by hand, then cut off by a crash
+        let mut content = std::fs::read_to_string(&histfile)?;
+        content.push_str("not json\n{\"id\":1,\"command_li");
+        std::fs::write(&histfile, &content)?;
+
+        {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            assert_eq!(all_command_lines(&history), vec!["ls"]);
+            history.save(create_item("/", "pwd", 0))?;
+        }
+        {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            assert_eq!(all_command_lines(&history), vec!["ls", "pwd"]);
+            // rewrites the file
+            history.delete(saved.id.unwrap())?;
+        }
+
+        let history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+        assert_eq!(all_command_lines(&history), vec!["pwd"]);
+        let content = std::fs::read_to_string(&histfile)?;
+        assert!(content.contains("not json\n{\"id\":1,\"command_li\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_extra_info() -> Result<()> {
+        let mut history = JsonlBackedHistory::default();
+        history.save(create_item("/home/me", "cd Downloads", 0))?;
+        history.save(create_item("/home/me/Downloads", "unzp foo.zip", 1))?;
+        history.save(create_item("/home/me/Downloads", "unzip foo.zip", 0))?;
+        history.save(create_item("/etc", "ls", 0))?;
+
+        let mut filter = SearchFilter::anything(None);
+        filter.cwd_prefix = Some("/home/me".to_string());
+        filter.exit_successful = Some(true);
+        let res = history.search(SearchQuery {
+            filter,
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        })?;
+        assert_eq!(command_lines(res), vec!["unzip foo.zip", "cd Downloads"]);
+
+        let res = history.search(SearchQuery::last_with_prefix_and_cwd(
+            "unz".to_string(),
+            "/home/me/Downloads".to_string(),
+            None,
+        ))?;
+        assert_eq!(command_lines(res), vec!["unzip foo.zip"]);
+        Ok(())
+    }
+
+    #[test]
+    fn update_and_delete_are_persisted() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+
+        let (first, second) = {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            let first = history.save(create_item("/", "first", 0))?;
+            let second = history.save(create_item("/", "secret", 0))?;
+            history.save(create_item("/", "third", 0))?;
+            (first.id.unwrap(), second.id.unwrap())
+        };
+
+        {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            history.update(first, &|mut e| {
+                e.exit_status = Some(127);
+                e
+            })?;
+            history.delete(second)?;
+            assert!(history.load(second).is_err());
+        }
+
+        let history = JsonlBackedHistory::with_file(10, histfile)?;
+        assert_eq!(all_command_lines(&history), vec!["first", "third"]);
+        assert_eq!(history.load(first)?.exit_status, Some(127));
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_histories_merge_and_truncate() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+
+        {
+            let mut history = JsonlBackedHistory::with_file(4, histfile.clone())?;
+            history.save(create_item("/", "initial", 0))?;
+        }
+        {
+            let mut hist_a = JsonlBackedHistory::with_file(4, histfile.clone())?;
+            let mut hist_b = JsonlBackedHistory::with_file(4, histfile.clone())?;
+            hist_a.save(create_item("/", "A1", 0))?;
+            hist_b.save(create_item("/", "B1", 0))?;
+            hist_a.save(create_item("/", "A2", 0))?;
+            hist_b.save(create_item("/", "B2", 0))?;
+            hist_a.sync()?;
+            hist_b.sync()?;
+            assert_eq!(all_command_lines(&hist_b), vec!["A1", "B1", "A2", "B2"]);
+        }
+
+        let history = JsonlBackedHistory::with_file(4, histfile)?;
+        assert_eq!(all_command_lines(&history), vec!["A1", "B1", "A2", "B2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn pinned_entries_survive_truncation() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+
+        {
+            let mut history = JsonlBackedHistory::with_file(3, histfile.clone())?;
+            history.save(HistoryItem {
+                tags: vec![HistoryItem::PINNED_TAG.to_string(), "deploy".to_string()],
+                ..create_item("/", "kubectl apply", 0)
+            })?;
+            for command_line in ["a", "b", "c"] {
+                history.save(create_item("/", command_line, 0))?;
+            }
+            assert_eq!(all_command_lines(&history), vec!["kubectl apply", "b", "c"]);
+        }
+        {
+            let mut history = JsonlBackedHistory::with_file(3, histfile.clone())?;
+            history.save(create_item("/", "d", 0))?;
+        }
+
+        let history = JsonlBackedHistory::with_file(3, histfile)?;
+        assert_eq!(all_command_lines(&history), vec!["kubectl apply", "c", "d"]);
+        let mut query = SearchQuery::everything(SearchDirection::Backward, None);
+        query.filter.tags = vec!["deploy".to_string()];
+        let tagged = history.search(query)?;
+        assert_eq!(tagged.len(), 1);
+        assert!(tagged[0].is_pinned());
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_more_info_of_other_applications() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+        let line = |id, command_line, branch| {
+            format!(
+                r#"{{"id":{id},"start_timestamp":null,"command_line":"{command_line}","session_id":null,"hostname":null,"cwd":null,"duration":null,"exit_status":null,"more_info":{{"git":{{"branch":"{branch}"}}}}}}"#
+            )
+        };
+        std::fs::write(
+            &histfile,
+            format!(
+                "{}\n{}\n",
+                line(1, "git push", "main"),
+                line(2, "git rebase", "feature/search")
+            ),
+        )?;
+
+        let history = JsonlBackedHistory::with_file(10, histfile)?;
+        let mut query = SearchQuery::everything(SearchDirection::Forward, None);
+        query.filter.more_info = vec![MoreInfoFilter::prefix("git.branch", "feature/")];
+        let found = history.search(query)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].command_line, "git rebase");
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_more_info_of_other_applications() -> Result<()> {
+        let tmp = tempfile::tempdir().unwrap();
+        let histfile = tmp.path().join("history.jsonl");
+        let line = r#"{"id":1,"start_timestamp":null,"command_line":"git status","session_id":null,"hostname":null,"cwd":null,"duration":null,"exit_status":null,"more_info":{"branch":"main"}}"#;
+        std::fs::write(&histfile, format!("{line}\n"))?;
+
+        {
+            let mut history = JsonlBackedHistory::with_file(10, histfile.clone())?;
+            history.update(HistoryItemId::new(1), &|mut e| {
+                e.exit_status = Some(0);
+                e
+            })?;
+        }
+
+        let content = std::fs::read_to_string(&histfile)?;
+        assert!(content.contains(r#""more_info":{"branch":"main"}"#));
+        assert!(content.contains(r#""exit_status":0"#));
+        Ok(())
+    }
+}
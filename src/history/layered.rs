@@ -0,0 +1,729 @@
+use super::{
+    base::{rank_by_frecency, rank_fuzzy_matches, sort_by_outcome, CommandLineSearch},
+    History, HistoryItem, HistoryItemId, HistorySessionId, NewItemCheck, SearchDirection,
+    SearchOrder, SearchQuery,
+};
+use crate::{
+    result::{ReedlineError, ReedlineErrorVariants},
+    Result,
+};
+use chrono::Utc;
+use std::{iter::Peekable, ops::Range};
+
+/// Entries of the layers, with their layer
+type LayerEntries = Vec<(usize, HistoryItem)>;
+
+/// How many more entries than the limit each layer finds, as merging might drop some
+const MERGE_HEADROOM: i64 = 16;
+
+/// A [`History`] combining several histories, e.g. a project-local history on top of the
+/// global history of the user
+///
+/// Searches merge the entries of all layers by their `start_timestamp`. Entries without one are
+/// ordered after those of lower layers, so the entries of a plain text history on top are found
+/// first when browsing back. When the same command line follows itself from another layer, e.g.
+/// because it was saved to both, only the more recent entry is kept.
+///
+/// New entries are saved to the target layer, other changes go to the layer of the entry.
+/// The ids of the entries are mapped to ids unique across the layers, so they are only valid
+/// for the same set of layers. Ids too large for that, like the timestamps of a
+/// `JsonlBackedHistory` among more than five layers, are an error.
+///
+/// Chronological searches with a limit, like the steps of the history cursor, only merge the
+/// entries of the layers around their start, which are found by id and by timestamp. They
+/// expect the layers to search their entries in the order of their ids, which a shared
+/// `SqliteBackedHistory` doesn't do for the entries of the session.
+///
+/// Layers that don't support a filter of a search are left out of it, as long as any layer
+/// supports it.
+///
+/// ```rust,no_run
+/// use reedline::{FileBackedHistory, LayeredHistory, Reedline};
+///
+/// let global = FileBackedHistory::with_file(10_000, "history.txt".into()).unwrap();
+/// let project = FileBackedHistory::with_file(1000, ".shell_history".into()).unwrap();
+/// // save new entries to the project history
+/// let history = LayeredHistory::new(vec![Box::new(global), Box::new(project)], 1).unwrap();
+/// let line_editor = Reedline::create().with_history(Box::new(history));
+/// ```
+pub struct LayeredHistory {
+    layers: Vec<Box<dyn History>>,
+    target: usize,
+}
+
+impl LayeredHistory {
+    /// Combines the layers, from the lowest to the highest one, and saves new entries to the
+    /// layer at index `target`
+    ///
+    /// Returns an error if there is no layer at index `target`.
+    pub fn new(layers: Vec<Box<dyn History>>, target: usize) -> Result<Self> {
+        if target >= layers.len() {
+            return Err(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                "Target layer does not exist",
+            )));
+        }
+        Ok(LayeredHistory { layers, target })
+    }
+
+    fn to_outer(&self, layer: usize, id: HistoryItemId) -> Result<HistoryItemId> {
+        id.0.checked_mul(self.layers.len() as i64)
+            .and_then(|id| id.checked_add(layer as i64))
+            .map(HistoryItemId::new)
+            .ok_or(ReedlineError(ReedlineErrorVariants::OtherHistoryError(
+                "Item id too large to be combined with the ids of the other layers",
+            )))
+    }
+
+    fn to_inner(&self, id: HistoryItemId) -> (usize, HistoryItemId) {
+        let layers = self.layers.len() as i64;
+        (
+            id.0.rem_euclid(layers) as usize,
+            HistoryItemId::new(id.0.div_euclid(layers)),
+        )
+    }
+
+    fn outer_item(&self, layer: usize, mut item: HistoryItem) -> Result<HistoryItem> {
+        item.id = item.id.map(|id| self.to_outer(layer, id)).transpose()?;
+        Ok(item)
+    }
+
+    /// All matching entries of all layers in chronological order, with their layer, and the
+    /// range of them that is the same as in the merge of all entries
+    ///
+    /// With `fetch`, each layer finds at most that many entries: the layer of the `start_id`
+    /// from there on, the other layers from the start of a forward search or else from their
+    /// most recent entry. More entries of a layer that found `fetch` entries might belong
+    /// between the merged ones, so the range ends at its entries.
+    fn merged_entries(
+        &self,
+        query: &SearchQuery,
+        fetch: Option<i64>,
+    ) -> Result<(LayerEntries, Range<usize>)> {
+        let start = query.start_id.map(|id| self.to_inner(id));
+        let start_timestamp = match (fetch, start) {
+            (Some(_), Some((layer, id))) => self.layers[layer].load(id)?.start_timestamp,
+            _ => None,
+        };
+        let mut unsupported = None;
+        let mut layers = vec![];
+        for (layer, history) in self.layers.iter().enumerate() {
+            // the entries of the other layers next to the start are found by its timestamp
+            let found_pivot = match start_timestamp {
+                Some(timestamp) if start.map_or(false, |(l, _)| l != layer) => {
+                    pivot(history.as_ref(), query, timestamp)?
+                }
+                _ => None,
+            };
+            let pivot = found_pivot.flatten();
+            let (direction, start_id) = match (start, found_pivot) {
+                (Some((start_layer, id)), _) if start_layer == layer => (query.direction, Some(id)),
+                (_, Some(pivot)) => (query.direction, pivot),
+                (None, _) if query.direction == SearchDirection::Forward => {
+                    (SearchDirection::Forward, None)
+                }
+                // browsing the history starts at the most recent entries
+                _ => (SearchDirection::Backward, None),
+            };
+            let layer_query = match fetch {
+                Some(fetch) => SearchQuery {
+                    direction,
+                    order: SearchOrder::Chronological,
+                    start_id,
+                    end_id: None,
+                    limit: Some(fetch),
+                    ..query.clone()
+                },
+                None => SearchQuery {
+                    direction: SearchDirection::Forward,
+                    order: SearchOrder::Chronological,
+                    start_id: None,
+                    end_id: None,
+                    limit: None,
+                    ..query.clone()
+                },
+            };
+            match history.search(layer_query) {
+                Ok(mut items) => {
+                    let cut = fetch.map_or(false, |fetch| items.len() as i64 >= fetch);
+                    // the entries from the pivot on are missing, those from the start on
+                    // come after it anyway
+                    let (cut_before, cut_after) = match direction {
+                        SearchDirection::Backward => (cut, pivot.is_some()),
+                        SearchDirection::Forward => (pivot.is_some(), cut),
+                    };
+                    // fuzzy searches rank by match quality, backward searches are reversed
+                    items.sort_by_key(|item| item.id);
+                    layers.push(Run {
+                        layer,
+                        items: items.into_iter().peekable(),
+                        started: false,
+                        cut_before,
+                        cut_after,
+                    });
+                }
+                Err(ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported {
+                    history,
+                    feature,
+                })) => {
+                    unsupported =
+                        Some(ReedlineErrorVariants::HistoryFeatureUnsupported { history, feature });
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if let (true, Some(unsupported)) = (layers.is_empty(), unsupported) {
+            return Err(ReedlineError(unsupported));
+        }
+
+        let mut merged: LayerEntries = vec![];
+        let mut complete = 0..usize::MAX;
+        loop {
+            let mut next: Option<(usize, usize, &HistoryItem)> = None;
+            for (idx, run) in layers.iter_mut().enumerate() {
+                if let Some(item) = run.items.peek() {
+                    match next {
+                        Some((_, next_layer, next_item))
+                            if !is_before((run.layer, item), (next_layer, next_item)) => {}
+                        _ => next = Some((idx, run.layer, item)),
+                    }
+                }
+            }
+            let Some((idx, layer, _)) = next else {
+                break;
+            };
+            let run = &mut layers[idx];
+            let item = run.items.next().expect("peeked before");
+            match merged.last_mut() {
+                Some((last_layer, last))
+                    if *last_layer != layer && last.command_line == item.command_line =>
+                {
+                    *last_layer = layer;
+                    *last = item;
+                }
+                _ => merged.push((layer, item)),
+            }
+            // the entry might be merged with one that wasn't found, so it's left out as well
+            let position = merged.len() - 1;
+            if run.cut_before && !run.started {
+                complete.start = complete.start.max(position + 1);
+            }
+            if run.cut_after && run.items.peek().is_none() {
+                complete.end = complete.end.min(position);
+            }
+            run.started = true;
+        }
+        complete.end = complete.end.min(merged.len());
+        Ok((merged, complete))
+    }
+
+    /// The merged entries the query finds, in chronological order
+    ///
+    /// Chronological searches with a limit merge the entries of the layers around the start of
+    /// the search, and only search further if the limit isn't reached with the complete ones.
+    fn found_entries(&self, query: &SearchQuery) -> Result<LayerEntries> {
+        let limit = query.limit.map_or(usize::MAX, |l| l.max(0) as usize);
+        let mut fetch = match &query.filter.command_line {
+            // fuzzy searches rank all matches
+            Some(CommandLineSearch::Fuzzy(_)) => None,
+            _ if query.order != SearchOrder::Chronological => None,
+            _ => query.limit.map(|l| l.max(0).saturating_add(MERGE_HEADROOM)),
+        };
+        loop {
+            let (merged, complete) = self.merged_entries(query, fetch)?;
+            let start = match query.start_id {
+                Some(id) => Some(self.bounds(&merged, id)?),
+                None => None,
+            };
+            let end = match query.end_id {
+                Some(id) => Some(self.bounds(&merged, id)?),
+                None => None,
+            };
+            // the ids are exclusive bounds
+            let (first, last) = match query.direction {
+                SearchDirection::Forward => {
+                    (start.map(|(_, after)| after), end.map(|(before, _)| before))
+                }
+                SearchDirection::Backward => {
+                    (end.map(|(_, after)| after), start.map(|(before, _)| before))
+                }
+            };
+            let first = first.unwrap_or(0);
+            let last = last.unwrap_or(merged.len()).max(first);
+            // the entries within the limit
+            let (from, to) = match query.direction {
+                SearchDirection::Forward => (first, last.min(first.saturating_add(limit))),
+                SearchDirection::Backward => (first.max(last.saturating_sub(limit)), last),
+            };
+            match fetch {
+                Some(limit) if from < complete.start || to > complete.end => {
+                    fetch = Some(limit.saturating_mul(2));
+                }
+                _ => return Ok(merged.into_iter().skip(first).take(last - first).collect()),
+            }
+        }
+    }
+
+    /// Range of the merged entries before and after an entry, which might not be among them:
+    /// `(end of the entries before, start of the entries after)`
+    fn bounds(&self, merged: &[(usize, HistoryItem)], id: HistoryItemId) -> Result<(usize, usize)> {
+        let (layer, inner_id) = self.to_inner(id);
+        if let Some(position) = merged
+            .iter()
+            .position(|(l, item)| *l == layer && item.id == Some(inner_id))
+        {
+            return Ok((position, position + 1));
+        }
+        let item = self.layers[layer].load(inner_id)?;
+        let position = merged
+            .iter()
+            .position(|(l, other)| is_before((layer, &item), (*l, other)))
+            .unwrap_or(merged.len());
+        Ok((position, position))
+    }
+}
+
+/// The id to search a layer from for the entries following `timestamp` in the direction of
+/// the query, the one beyond the closest entry in the other direction
+///
+/// `Some(None)` if there are no two such entries, so the layer is searched from its end, and
+/// `None` if the layer can't search by time.
+fn pivot(
+    history: &dyn History,
+    query: &SearchQuery,
+    timestamp: chrono::DateTime<Utc>,
+) -> Result<Option<Option<HistoryItemId>>> {
+    let beyond = history.search(SearchQuery {
+        direction: match query.direction {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        },
+        order: SearchOrder::Chronological,
+        start_time: Some(timestamp),
+        end_time: None,
+        start_id: None,
+        end_id: None,
+        limit: Some(2),
+        ..query.clone()
+    });
+    match beyond {
+        Ok(items) => Ok(Some(items.get(1).and_then(|item| item.id))),
+        Err(ReedlineError(ReedlineErrorVariants::HistoryFeatureUnsupported { .. })) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// The entries a layer found for a merge, see [`LayeredHistory::merged_entries`]
+struct Run {
+    layer: usize,
+    items: Peekable<std::vec::IntoIter<HistoryItem>>,
+    started: bool,
+    /// Whether the layer might have more entries before the found ones
+    cut_before: bool,
+    /// Whether the layer might have more entries after the found ones
+    cut_after: bool,
+}
+
+/// Whether entry `a` comes before `b` in the chronological order of the merged layers
+fn is_before((a_layer, a): (usize, &HistoryItem), (b_layer, b): (usize, &HistoryItem)) -> bool {
+    match (a.start_timestamp, b.start_timestamp) {
+        (Some(a_start), Some(b_start)) if a_start != b_start => a_start < b_start,
+        _ if a_layer == b_layer => a.id < b.id,
+        _ => a_layer < b_layer,
+    }
+}
+
+impl History for LayeredHistory {
+    /// Saves new entries to the target layer and updates existing ones in their layer
+    fn save(&mut self, mut h: HistoryItem) -> Result<HistoryItem> {
+        let layer = match h.id {
+            Some(id) => {
+                let (layer, inner_id) = self.to_inner(id);
+                h.id = Some(inner_id);
+                layer
+            }
+            None => self.target,
+        };
+        let saved = self.layers[layer].save(h)?;
+        self.outer_item(layer, saved)
+    }
+
+    fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+        let (layer, inner_id) = self.to_inner(id);
+        let item = self.layers[layer].load(inner_id)?;
+        self.outer_item(layer, item)
+    }
+
+    fn count(&self, query: SearchQuery) -> Result<i64> {
+        let query = SearchQuery {
+            limit: None,
+            ..query
+        };
+        Ok(self.search(query)?.len() as i64)
+    }
+
+    fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+        let mut items: Vec<_> = self
+            .found_entries(&query)?
+            .into_iter()
+            .map(|(layer, item)| self.outer_item(layer, item))
+            .collect::<Result<_>>()?;
+        if query.direction == SearchDirection::Backward {
+            items.reverse();
+        }
+
+        let limit = query.limit.map_or(usize::MAX, |l| l.max(0) as usize);
+        if query.order == SearchOrder::Frecency {
+            Ok(rank_by_frecency(items, limit))
+        } else if query.order != SearchOrder::Chronological {
+            Ok(sort_by_outcome(items, query.order, limit))
+        } else if let Some(search @ CommandLineSearch::Fuzzy(_)) = &query.filter.command_line {
+            let matches = items
+                .into_iter()
+                .filter_map(|item| Some((search.match_score(&item.command_line)?, item)))
+                .collect();
+            Ok(rank_fuzzy_matches(matches, limit))
+        } else {
+            items.truncate(limit);
+            Ok(items)
+        }
+    }
+
+    fn update(
+        &mut self,
+        id: HistoryItemId,
+        updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> Result<()> {
+        let (layer, inner_id) = self.to_inner(id);
+        let inner_updater = |item: HistoryItem| {
+            let mut updated = updater(HistoryItem {
+                id: Some(id),
+                ..item
+            });
+            updated.id = Some(inner_id);
+            updated
+        };
+        self.layers[layer].update(inner_id, &inner_updater)
+    }
+
+    /// Clears all layers
+    fn clear(&mut self) -> Result<()> {
+        for layer in &mut self.layers {
+            layer.clear()?;
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+        let (layer, inner_id) = self.to_inner(h);
+        self.layers[layer].delete(inner_id)
+    }
+
+    /// Syncs all layers, even if one of them fails
+    fn sync(&mut self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for layer in &mut self.layers {
+            let synced = layer.sync();
+            if result.is_ok() {
+                result = synced;
+            }
+        }
+        result
+    }
+
+    /// The session of the target layer
+    fn session(&self) -> Option<HistorySessionId> {
+        self.layers[self.target].session()
+    }
+
+    /// The session start of the target layer
+    fn session_timestamp(&self) -> Option<chrono::DateTime<Utc>> {
+        self.layers[self.target].session_timestamp()
+    }
+
+    /// The check of the target layer
+    fn new_item_check(&self) -> NewItemCheck {
+        self.layers[self.target].new_item_check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{history::HistoryCursor, FileBackedHistory, HistoryNavigationQuery, LineBuffer};
+    use pretty_assertions::assert_eq;
+
+    fn file_backed(command_lines: &[&str]) -> Box<dyn History> {
+        let mut history = FileBackedHistory::default();
+        for command_line in command_lines {
+            history
+                .save(HistoryItem::from_command_line(*command_line))
+                .unwrap();
+        }
+        Box::new(history)
+    }
+
+    fn command_lines(items: Vec<HistoryItem>) -> Vec<String> {
+        items.into_iter().map(|item| item.command_line).collect()
+    }
+
+    #[test]
+    fn target_layer_must_exist() {
+        assert!(LayeredHistory::new(vec![file_backed(&[])], 1).is_err());
+    }
+
+    #[test]
+    fn higher_layers_without_timestamps_are_more_recent() -> Result<()> {
+        let mut history = LayeredHistory::new(
+            vec![file_backed(&["ls", "make"]), file_backed(&["make", "cd"])],
+            0,
+        )?;
+        history.save(HistoryItem::from_command_line("pwd"))?;
+
+        let everything = SearchQuery::everything(SearchDirection::Backward, None);
+        assert_eq!(
+            command_lines(history.search(everything)?),
+            vec!["cd", "make", "pwd", "make", "ls"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ids_reach_the_layer_of_the_entry() -> Result<()> {
+        let mut history = LayeredHistory::new(
+            vec![
+                file_backed(&["ls", "git log"]),
+                file_backed(&["cd", "git status"]),
+            ],
+            1,
+        )?;
+        let query = SearchQuery {
+            filter: crate::SearchFilter::from_text_search(
+                CommandLineSearch::Prefix("git".to_string()),
+                None,
+            ),
+            ..SearchQuery::everything(SearchDirection::Forward, None)
+        };
+        let found = history.search(query.clone())?;
+        assert_eq!(command_lines(found.clone()), vec!["git log", "git status"]);
+        for item in &found {
+            assert_eq!(
+                history.load(item.id.unwrap())?.command_line,
+                item.command_line
+            );
+        }
+
+        history.delete(found[0].id.unwrap())?;
+        assert_eq!(command_lines(history.search(query)?), vec!["git status"]);
+        assert_eq!(history.count_all()?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_browses_across_layers() -> Result<()> {
+        let history =
+            LayeredHistory::new(vec![file_backed(&["a", "b"]), file_backed(&["c", "d"])], 1)?;
+        let mut cursor = HistoryCursor::new(
+            HistoryNavigationQuery::Normal(LineBuffer::default()),
+            history.session(),
+        );
+        let mut seen = vec![];
+        for _ in 0..5 {
+            cursor.back(&history)?;
+            seen.extend(cursor.string_at_cursor());
+        }
+        assert_eq!(seen, vec!["d", "c", "b", "a", "a"]);
+        cursor.forward(&history)?;
+        assert_eq!(cursor.string_at_cursor(), Some("b".to_string()));
+        Ok(())
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
+    #[test]
+    fn merges_by_timestamp_and_drops_duplicates() -> Result<()> {
+        use crate::SqliteBackedHistory;
+        use chrono::{Duration, Utc};
+
+        let mut global = SqliteBackedHistory::in_memory()?;
+        let mut project = SqliteBackedHistory::in_memory()?;
+        let now = Utc::now();
+        let at = |command_line: &str, minutes_ago| HistoryItem {
+            start_timestamp: Some(now - Duration::minutes(minutes_ago)),
+            ..HistoryItem::from_command_line(command_line)
+        };
+        global.save(at("ls", 50))?;
+        project.save(at("cargo build", 40))?;
+        global.save(at("vim notes", 30))?;
+        global.save(at("cargo test", 20))?;
+        project.save(at("cargo test", 20))?;
+        project.save(at("git push", 10))?;
+        let history = LayeredHistory::new(vec![Box::new(global), Box::new(project)], 1)?;
+
+        let everything = SearchQuery::everything(SearchDirection::Forward, None);
+        assert_eq!(
+            command_lines(history.search(everything)?),
+            vec!["ls", "cargo build", "vim notes", "cargo test", "git push"]
+        );
+        let latest = SearchQuery {
+            limit: Some(1),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        };
+        assert_eq!(command_lines(history.search(latest)?), vec!["git push"]);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
+    #[test]
+    fn limited_searches_find_enough_entries_after_merging() -> Result<()> {
+        use crate::SqliteBackedHistory;
+        use chrono::{Duration, Utc};
+
+        let mut global = SqliteBackedHistory::in_memory()?;
+        let mut project = SqliteBackedHistory::in_memory()?;
+        let now = Utc::now();
+        let at = |command_line: &str, minutes_ago| HistoryItem {
+            start_timestamp: Some(now - Duration::minutes(minutes_ago)),
+            ..HistoryItem::from_command_line(command_line)
+        };
+        global.save(at("ls", 50))?;
+        global.save(at("make", 30))?;
+        project.save(at("make", 20))?;
+        global.save(at("make", 10))?;
+        let history = LayeredHistory::new(vec![Box::new(global), Box::new(project)], 1)?;
+
+        // the two most recent entries of each layer merge into one
+        let latest = SearchQuery {
+            limit: Some(2),
+            ..SearchQuery::everything(SearchDirection::Backward, None)
+        };
+        assert_eq!(command_lines(history.search(latest)?), vec!["make", "ls"]);
+        Ok(())
+    }
+
+    #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
+    #[test]
+    fn cursor_steps_only_search_the_entries_around_the_cursor() -> Result<()> {
+        use crate::SqliteBackedHistory;
+        use chrono::{Duration, Utc};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        /// Records the most entries a search of the wrapped history found
+        struct Counting {
+            history: SqliteBackedHistory,
+            most_found: Arc<AtomicUsize>,
+        }
+
+        impl History for Counting {
+            fn save(&mut self, h: HistoryItem) -> Result<HistoryItem> {
+                self.history.save(h)
+            }
+            fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
+                self.history.load(id)
+            }
+            fn count(&self, query: SearchQuery) -> Result<i64> {
+                self.history.count(query)
+            }
+            fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
+                let found = self.history.search(query)?;
+                self.most_found.fetch_max(found.len(), Ordering::Relaxed);
+                Ok(found)
+            }
+            fn update(
+                &mut self,
+                id: HistoryItemId,
+                updater: &dyn Fn(HistoryItem) -> HistoryItem,
+            ) -> Result<()> {
+                self.history.update(id, updater)
+            }
+            fn clear(&mut self) -> Result<()> {
+                self.history.clear()
+            }
+            fn delete(&mut self, h: HistoryItemId) -> Result<()> {
+                self.history.delete(h)
+            }
+            fn sync(&mut self) -> std::io::Result<()> {
+                self.history.sync()
+            }
+            fn session(&self) -> Option<HistorySessionId> {
+                None
+            }
+        }
+
+        let most_found = Arc::new(AtomicUsize::new(0));
+        let mut layers = [
+            SqliteBackedHistory::in_memory()?,
+            SqliteBackedHistory::in_memory()?,
+        ];
+        let now = Utc::now();
+        for i in 0..200 {
+            // runs of entries in the same layer, some repeated in the other one
+            let layer = (i / 3) % 2;
+            let command_line = format!("cmd {}", i - i % 5);
+            layers[layer].save(HistoryItem {
+                start_timestamp: Some(now - Duration::minutes(200 - i as i64)),
+                ..HistoryItem::from_command_line(command_line)
+            })?;
+        }
+        let [global, project] = layers;
+        let history = LayeredHistory::new(
+            vec![
+                Box::new(Counting {
+                    history: global,
+                    most_found: most_found.clone(),
+                }),
+                Box::new(Counting {
+                    history: project,
+                    most_found: most_found.clone(),
+                }),
+            ],
+            1,
+        )?;
+        let mut everything = command_lines(
+            history.search(SearchQuery::everything(SearchDirection::Backward, None))?,
+        );
+        // the cursor skips repeated entries
+        everything.dedup();
+        most_found.store(0, Ordering::Relaxed);
+
+        let mut cursor = HistoryCursor::new(
+            HistoryNavigationQuery::Normal(LineBuffer::default()),
+            history.session(),
+        );
+        let mut seen = vec![];
+        for _ in 0..everything.len() {
+            cursor.back(&history)?;
+            seen.extend(cursor.string_at_cursor());
+        }
+        assert_eq!(seen, everything);
+        let mut seen = vec![];
+        for _ in 1..everything.len() {
+            cursor.forward(&history)?;
+            seen.extend(cursor.string_at_cursor());
+        }
+        let mut forward = everything;
+        forward.reverse();
+        assert_eq!(seen, forward[1..]);
+        assert!(most_found.load(Ordering::Relaxed) <= 1 + MERGE_HEADROOM as usize);
+        Ok(())
+    }
+
+    #[cfg(feature = "jsonl")]
+    #[test]
+    fn ids_too_large_for_the_layers_are_an_error() -> Result<()> {
+        use crate::JsonlBackedHistory;
+
+        // the ids are the nanoseconds since the epoch
+        let mut jsonl = JsonlBackedHistory::new(10)?;
+        jsonl.save(HistoryItem::from_command_line("ls"))?;
+        let mut layers: Vec<Box<dyn History>> = (0..5).map(|_| file_backed(&[])).collect();
+        layers.push(Box::new(jsonl));
+        let history = LayeredHistory::new(layers, 5)?;
+
+        assert!(history
+            .search(SearchQuery::everything(SearchDirection::Backward, None))
+            .is_err());
+        Ok(())
+    }
+}
@@ -1,14 +1,28 @@
+mod background;
 mod base;
 mod cursor;
+#[cfg(feature = "bashisms")]
+pub(crate) mod expansion;
 mod file_backed;
+mod file_format;
+mod filter;
 mod item;
+#[cfg(feature = "jsonl")]
+mod jsonl_backed;
+mod layered;
+mod retention;
 #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
 mod sqlite_backed;
+mod stats;
+#[cfg(feature = "jsonl")]
+pub use jsonl_backed::{JsonlBackedHistory, JSONL_HISTORY_SIZE};
 #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
 pub use sqlite_backed::SqliteBackedHistory;
 
+pub use background::BackgroundHistory;
 pub use base::{
-    CommandLineSearch, History, HistoryNavigationQuery, SearchDirection, SearchFilter, SearchQuery,
+    CommandLineSearch, History, HistoryNavigationQuery, MoreInfoFilter, MoreInfoMatch,
+    NewItemCheck, SearchDirection, SearchFilter, SearchOrder, SearchQuery,
 };
 pub use cursor::HistoryCursor;
 pub use item::{
@@ -16,3 +30,9 @@ pub use item::{
 };
 
 pub use file_backed::{FileBackedHistory, HISTORY_SIZE};
+pub use file_format::{export_history, import_history, HistoryFileFormat};
+pub(crate) use filter::FilteredHistory;
+pub use filter::{DefaultHistoryFilter, HistoryFilter};
+pub use layered::LayeredHistory;
+pub use retention::{DuplicateRetention, RetentionPolicy, RetentionReport};
+pub use stats::{CommandStats, HistoryStats};
@@ -0,0 +1,307 @@
+use super::{History, HistoryItem, HistoryItemId, SearchDirection, SearchQuery};
+use crate::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// Which duplicate command lines a [`RetentionPolicy`] removes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateRetention {
+    /// Keep every entry
+    KeepAll,
+    /// Remove entries directly followed by the same command line
+    DropConsecutive,
+    /// Remove every entry but the newest one of each command line
+    DropAll,
+}
+
+/// Rules deciding which entries a [`History`] keeps, applied by [`History::apply_retention()`]
+///
+/// Rules based on the time of entries skip those without a `start_timestamp`, rules based
+/// on the exit status skip those without one. Pinned entries, see [`HistoryItem::is_pinned()`],
+/// are never removed and don't count towards the maximal number of entries.
+///
+/// ```rust
+/// use chrono::Duration;
+/// use reedline::{DuplicateRetention, RetentionPolicy};
+///
+/// let policy = RetentionPolicy::default()
+///     .with_max_entries(100_000)
+///     .with_max_age(Duration::days(365))
+///     .with_duplicates(DuplicateRetention::DropAll)
+///     .with_max_age_of_failed(Duration::days(30));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub(crate) max_entries: Option<usize>,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) duplicates: DuplicateRetention,
+    pub(crate) max_age_of_failed: Option<Duration>,
+}
+
+impl Default for RetentionPolicy {
+    /// A policy keeping every entry
+    fn default() -> Self {
+        RetentionPolicy {
+            max_entries: None,
+            max_age: None,
+            duplicates: DuplicateRetention::KeepAll,
+            max_age_of_failed: None,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// A builder that keeps only the given number of the most recent entries
+    #[must_use]
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// A builder that removes entries started longer ago than `max_age`
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// A builder that sets which duplicate command lines are removed
+    #[must_use]
+    pub fn with_duplicates(mut self, duplicates: DuplicateRetention) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+
+    /// A builder that removes failed commands, with a non-zero exit status,
+    /// started longer ago than `max_age`
+    #[must_use]
+    pub fn with_max_age_of_failed(mut self, max_age: Duration) -> Self {
+        self.max_age_of_failed = Some(max_age);
+        self
+    }
+}
+
+/// What [`History::apply_retention()`] removed
+///
+/// Every entry is counted once, by the first of the rules removing it, in the order of the fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    /// Number of failed commands removed for their age
+    pub failed: usize,
+    /// Number of entries removed for their age
+    pub expired: usize,
+    /// Number of duplicate entries removed
+    pub duplicates: usize,
+    /// Number of entries removed beyond the maximal number of entries
+    pub over_limit: usize,
+    /// Number of removed duplicates by the command line of the entry kept in their place
+    pub merged_duplicates: HashMap<String, usize>,
+}
+
+impl RetentionReport {
+    /// Total number of removed entries
+    pub fn removed(&self) -> usize {
+        self.failed + self.expired + self.duplicates + self.over_limit
+    }
+}
+
+/// The entries the policy removes from the given entries, in chronological order
+pub(crate) fn plan_retention(
+    items: &[HistoryItem],
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> (Vec<HistoryItemId>, RetentionReport) {
+    let mut report = RetentionReport::default();
+    let mut remove = vec![false; items.len()];
+    let started_before = |item: &HistoryItem, max_age: Duration| {
+        !item.is_pinned()
+            && item
+                .start_timestamp
+                .map_or(false, |start| start < now - max_age)
+    };
+
+    if let Some(max_age) = policy.max_age_of_failed {
+        for (item, remove) in items.iter().zip(remove.iter_mut()) {
+            if item.exit_status.map_or(false, |status| status != 0) && started_before(item, max_age)
+            {
+                *remove = true;
+                report.failed += 1;
+            }
+        }
+    }
+    if let Some(max_age) = policy.max_age {
+        for (item, remove) in items.iter().zip(remove.iter_mut()) {
+            if !*remove && started_before(item, max_age) {
+                *remove = true;
+                report.expired += 1;
+            }
+        }
+    }
+    if policy.duplicates != DuplicateRetention::KeepAll {
+        // walk from the newest entry, so the newest one of each command line is kept
+        let mut kept: Option<&str> = None;
+        let mut seen = HashSet::new();
+        for (item, remove) in items.iter().zip(remove.iter_mut()).rev() {
+            if *remove {
+                continue;
+            }
+            let duplicate = match policy.duplicates {
+                DuplicateRetention::DropConsecutive => kept == Some(item.command_line.as_str()),
+                _ => !seen.insert(item.command_line.as_str()),
+            };
+            if duplicate && !item.is_pinned() {
+                *remove = true;
+                report.duplicates += 1;
+                *report
+                    .merged_duplicates
+                    .entry(item.command_line.clone())
+                    .or_default() += 1;
+            }
+            kept = Some(&item.command_line);
+        }
+    }
+    if let Some(max_entries) = policy.max_entries {
+        for (_, remove) in items
+            .iter()
+            .zip(remove.iter_mut())
+            .rev()
+            .filter(|(item, remove)| !**remove && !item.is_pinned())
+            .skip(max_entries)
+        {
+            *remove = true;
+            report.over_limit += 1;
+        }
+    }
+
+    let ids = items
+        .iter()
+        .zip(remove)
+        .filter(|(_, remove)| *remove)
+        .filter_map(|(item, _)| item.id)
+        .collect();
+    (ids, report)
+}
+
+/// Applies the policy through [`History::search()`] and [`History::delete()`],
+/// deleting the newest entries first, so that the ids of the others stay valid.
+pub(crate) fn apply_retention_by_deletion(
+    history: &mut (impl History + ?Sized),
+    policy: &RetentionPolicy,
+) -> Result<RetentionReport> {
+    let items = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+    let (ids, report) = plan_retention(&items, policy, Utc::now());
+    for id in ids.into_iter().rev() {
+        history.delete(id)?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileBackedHistory;
+    use pretty_assertions::assert_eq;
+
+    fn item(id: i64, command_line: &str, days_ago: i64, exit_status: i64) -> HistoryItem {
+        HistoryItem {
+            id: Some(HistoryItemId::new(id)),
+            start_timestamp: Some(Utc::now() - Duration::days(days_ago)),
+            exit_status: Some(exit_status),
+            ..HistoryItem::from_command_line(command_line)
+        }
+    }
+
+    fn example_items() -> Vec<HistoryItem> {
+        vec![
+            item(0, "make", 40, 2),
+            item(1, "ls", 20, 0),
+            item(2, "cd src", 10, 0),
+            item(3, "make", 9, 1),
+            item(4, "make", 8, 0),
+            item(5, "ls", 3, 0),
+            item(6, "git push", 1, 1),
+        ]
+    }
+
+    fn ids(ids: &[i64]) -> Vec<HistoryItemId> {
+        ids.iter().copied().map(HistoryItemId::new).collect()
+    }
+
+    #[test]
+    fn default_policy_keeps_everything() {
+        let (removed, report) =
+            plan_retention(&example_items(), &RetentionPolicy::default(), Utc::now());
+        assert_eq!(removed, vec![]);
+        assert_eq!(report, RetentionReport::default());
+    }
+
+    #[test]
+    fn rules_count_each_removed_entry_once() {
+        let policy = RetentionPolicy::default()
+            .with_max_age_of_failed(Duration::days(5))
+            .with_max_age(Duration::days(30))
+            .with_duplicates(DuplicateRetention::DropAll)
+            .with_max_entries(3);
+        let (removed, report) = plan_retention(&example_items(), &policy, Utc::now());
+        assert_eq!(removed, ids(&[0, 1, 2, 3]));
+        assert_eq!(
+            report,
+            RetentionReport {
+                failed: 2,
+                expired: 0,
+                duplicates: 1,
+                over_limit: 1,
+                merged_duplicates: [("ls".to_string(), 1)].into_iter().collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn consecutive_duplicates_keep_the_newest() {
+        let policy =
+            RetentionPolicy::default().with_duplicates(DuplicateRetention::DropConsecutive);
+        let (removed, report) = plan_retention(&example_items(), &policy, Utc::now());
+        assert_eq!(removed, ids(&[3]));
+        assert_eq!(report.merged_duplicates["make"], 1);
+    }
+
+    #[test]
+    fn never_removes_pinned_entries() {
+        let mut items = example_items();
+        for id in [0, 3] {
+            items[id].tags = vec![HistoryItem::PINNED_TAG.to_string()];
+        }
+        let policy = RetentionPolicy::default()
+            .with_max_age_of_failed(Duration::days(0))
+            .with_max_age(Duration::days(30))
+            .with_duplicates(DuplicateRetention::DropAll)
+            .with_max_entries(2);
+        let (removed, report) = plan_retention(&items, &policy, Utc::now());
+        assert_eq!(removed, ids(&[1, 2, 6]));
+        assert_eq!(report.failed, 1);
+        assert_eq!(report.duplicates, 1);
+        assert_eq!(report.over_limit, 1);
+    }
+
+    #[test]
+    fn applies_to_file_backed_history() -> Result<()> {
+        let mut history = FileBackedHistory::default();
+        for command_line in ["ls", "make", "ls", "cd", "make", "ls"] {
+            history.save(HistoryItem::from_command_line(command_line))?;
+        }
+        let policy = RetentionPolicy::default()
+            .with_duplicates(DuplicateRetention::DropAll)
+            .with_max_age(Duration::days(1));
+        let report = history.apply_retention(&policy)?;
+        assert_eq!(report.removed(), 3);
+        assert_eq!(report.expired, 0);
+
+        let left: Vec<_> = history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))?
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect();
+        assert_eq!(left, vec!["cd", "make", "ls"]);
+        Ok(())
+    }
+}
@@ -1,16 +1,117 @@
 use super::{
-    base::{CommandLineSearch, SearchDirection, SearchQuery},
-    History, HistoryItem, HistoryItemId, HistorySessionId,
+    base::{
+        rank_fuzzy_matches, CommandLineSearch, MoreInfoMatch, SearchDirection, SearchOrder,
+        SearchQuery, FRECENCY_WEIGHTS,
+    },
+    stats::{first_token, top_counts},
+    CommandStats, DuplicateRetention, History, HistoryItem, HistoryItemId, HistorySessionId,
+    HistoryStats, RetentionPolicy, RetentionReport,
 };
 use crate::{
     result::{ReedlineError, ReedlineErrorVariants},
+    utils::fuzzy::{fuzzy_match, is_case_sensitive},
     Result,
 };
-use chrono::{TimeZone, Utc};
-use rusqlite::{named_params, params, Connection, ToSql};
-use std::{path::PathBuf, time::Duration};
+use chrono::{NaiveDate, TimeZone, Utc};
+use rusqlite::{named_params, params, Connection, ToSql, TransactionBehavior};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
 const SQLITE_APPLICATION_ID: i32 = 1151497937;
 
+/// Schema migrations in the order they are applied.
+///
+/// The `user_version` of a database is the number of migrations applied to it, so new
+/// migrations must only ever be appended to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema (databases created before versioning have `user_version` 0 but
+    // already contain it, hence `if not exists`)
+    "
+    create table if not exists history (
+        id integer primary key autoincrement,
+        command_line text not null,
+        start_timestamp integer,
+        session_id integer,
+        hostname text,
+        cwd text,
+        duration_ms integer,
+        exit_status integer,
+        more_info text
+    ) strict;
+    create index if not exists idx_history_time on history(start_timestamp);
+    create index if not exists idx_history_cwd on history(cwd); -- suboptimal for many hosts
+    create index if not exists idx_history_exit_status on history(exit_status);
+    create index if not exists idx_history_cmd on history(command_line);
+    ",
+    // 2: the session index used to be declared as a second `idx_history_cmd` and was never created
+    "
+    create index if not exists idx_history_session on history(session_id);
+    ",
+    // 3: tags of the entries, in the order they were given
+    "
+    create table history_tags (
+        history_id integer not null references history(id) on delete cascade,
+        tag text not null,
+        primary key (history_id, tag)
+    ) strict;
+    create index idx_history_tags_tag on history_tags(tag);
+    ",
+    // 4: ordering by duration
+    "
+    create index idx_history_duration on history(duration_ms);
+    ",
+];
+
+/// Selects the tags of an entry of the `history` table as JSON array
+const TAGS_COLUMN: &str = "(SELECT json_group_array(tag) FROM \
+    (SELECT tag FROM history_tags WHERE history_id = history.id ORDER BY rowid)) AS tags";
+
+/// Condition excluding the pinned entries, whose tag is bound to `:pinned`
+const NOT_PINNED: &str = "id NOT IN (SELECT history_id FROM history_tags WHERE tag = :pinned)";
+
+/// Full text index over the command lines, see [`SqliteBackedHistory::with_full_text_search()`].
+///
+/// Triggers keep it in sync with every insert, update and delete of the `history` table.
+const FULL_TEXT_SEARCH_SCHEMA: &str = "
+    create virtual table history_fts using fts5(
+        command_line,
+        content = 'history',
+        content_rowid = 'id',
+        tokenize = 'trigram case_sensitive 1'
+    );
+    create trigger history_fts_insert after insert on history begin
+        insert into history_fts (rowid, command_line) values (new.id, new.command_line);
+    end;
+    create trigger history_fts_delete after delete on history begin
+        insert into history_fts (history_fts, rowid, command_line)
+            values ('delete', old.id, old.command_line);
+    end;
+    create trigger history_fts_update after update of command_line on history begin
+        insert into history_fts (history_fts, rowid, command_line)
+            values ('delete', old.id, old.command_line);
+        insert into history_fts (rowid, command_line) values (new.id, new.command_line);
+    end;
+    insert into history_fts (history_fts) values ('rebuild');
+";
+
+/// SQLite JSON path of nested object keys, e.g. `$."git"."branch"`
+fn json_path(keys: &[String]) -> String {
+    keys.iter().fold("$".to_string(), |path, key| {
+        format!("{path}.\"{}\"", key.replace('"', "\"\""))
+    })
+}
+
+fn has_full_text_index(db: &Connection) -> Result<bool> {
+    db.query_row(
+        "select count(*) > 0 from sqlite_master where type = 'table' and name = 'history_fts'",
+        params![],
+        |r| r.get(0),
+    )
+    .map_err(map_sqlite_err)
+}
+
 /// A history that stores the values to an SQLite database.
 /// In addition to storing the command, the history can store an additional arbitrary HistoryEntryContext,
 /// to add information such as a timestamp, running directory, result...
@@ -21,10 +122,14 @@ pub struct SqliteBackedHistory {
     db: rusqlite::Connection,
     session: Option<HistorySessionId>,
     session_timestamp: Option<chrono::DateTime<Utc>>,
+    // whether the database contains the full text index
+    full_text_search: bool,
+    shared: bool,
 }
 
 fn deserialize_history_item(row: &rusqlite::Row) -> rusqlite::Result<HistoryItem> {
     let x: Option<String> = row.get("more_info")?;
+    let tags: String = row.get("tags")?;
     Ok(HistoryItem {
         id: Some(HistoryItemId::new(row.get("id")?)),
         start_timestamp: row.get::<&str, Option<i64>>("start_timestamp")?.map(|e| {
@@ -43,6 +148,13 @@ fn deserialize_history_item(row: &rusqlite::Row) -> rusqlite::Result<HistoryItem
             .get::<&str, Option<i64>>("duration_ms")?
             .map(|e| Duration::from_millis(e as u64)),
         exit_status: row.get("exit_status")?,
+        tags: serde_json::from_str(&tags).map_err(|e| {
+            rusqlite::Error::InvalidColumnType(
+                0,
+                format!("could not deserialize tags: {e}"),
+                rusqlite::types::Type::Text,
+            )
+        })?,
         more_info: x
             .map(|x| {
                 serde_json::from_str(&x).map_err(|e| {
@@ -59,9 +171,10 @@ fn deserialize_history_item(row: &rusqlite::Row) -> rusqlite::Result<HistoryItem
 }
 
 impl History for SqliteBackedHistory {
+    /// Saves the entry and replaces its tags in a single transaction
     fn save(&mut self, mut entry: HistoryItem) -> Result<HistoryItem> {
-        let ret: i64 = self
-            .db
+        let tx = self.db.transaction().map_err(map_sqlite_err)?;
+        let ret: i64 = tx
             .prepare(
                 "insert into history
                                (id,  start_timestamp,  command_line,  session_id,  hostname,  cwd,  duration_ms,  exit_status,  more_info)
@@ -93,6 +206,19 @@ impl History for SqliteBackedHistory {
                 |row| row.get(0),
             )
             .map_err(map_sqlite_err)?;
+        tx.execute(
+            "delete from history_tags where history_id = ?",
+            params![ret],
+        )
+        .map_err(map_sqlite_err)?;
+        for tag in &entry.tags {
+            tx.execute(
+                "insert or ignore into history_tags (history_id, tag) values (?, ?)",
+                params![ret, tag],
+            )
+            .map_err(map_sqlite_err)?;
+        }
+        tx.commit().map_err(map_sqlite_err)?;
         entry.id = Some(HistoryItemId::new(ret));
         Ok(entry)
     }
@@ -100,7 +226,9 @@ impl History for SqliteBackedHistory {
     fn load(&self, id: HistoryItemId) -> Result<HistoryItem> {
         let entry = self
             .db
-            .prepare("select * from history where id = :id")
+            .prepare(&format!(
+                "select *, {TAGS_COLUMN} from history where id = :id"
+            ))
             .map_err(map_sqlite_err)?
             .query_row(named_params! { ":id": id.0 }, deserialize_history_item)
             .map_err(map_sqlite_err)?;
@@ -120,16 +248,27 @@ impl History for SqliteBackedHistory {
     }
 
     fn search(&self, query: SearchQuery) -> Result<Vec<HistoryItem>> {
-        let (query, params) = self.construct_query(&query, "*");
+        let (sql, params) = self.construct_query(&query, &format!("*, {TAGS_COLUMN}"));
         let params_borrow: Vec<(&str, &dyn ToSql)> = params.iter().map(|e| (e.0, &*e.1)).collect();
         let results: Vec<HistoryItem> = self
             .db
-            .prepare(&query)
+            .prepare(&sql)
             .unwrap()
             .query_map(&params_borrow[..], deserialize_history_item)
             .map_err(map_sqlite_err)?
             .collect::<rusqlite::Result<Vec<HistoryItem>>>()
             .map_err(map_sqlite_err)?;
+        if let Some(CommandLineSearch::Fuzzy(pattern)) = &query.filter.command_line {
+            let limit = query.limit.map_or(usize::MAX, |l| l as usize);
+            let matches = results.into_iter().filter_map(|item| {
+                fuzzy_match(pattern, &item.command_line).map(|m| (m.score, item))
+            });
+            if query.order != SearchOrder::Chronological {
+                // already ordered by the database
+                return Ok(matches.take(limit).map(|(_, item)| item).collect());
+            }
+            return Ok(rank_fuzzy_matches(matches.collect(), limit));
+        }
         Ok(results)
     }
 
@@ -179,6 +318,199 @@ impl History for SqliteBackedHistory {
     fn session(&self) -> Option<HistorySessionId> {
         self.session
     }
+
+    fn session_timestamp(&self) -> Option<chrono::DateTime<Utc>> {
+        // sessions sharing their history see all items
+        self.session_timestamp.filter(|_| !self.shared)
+    }
+
+    /// Applies the policy with a few statements in a single transaction
+    fn apply_retention(&mut self, policy: &RetentionPolicy) -> Result<RetentionReport> {
+        let now = Utc::now();
+        let mut report = RetentionReport::default();
+        let tx = self
+            .db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(map_sqlite_err)?;
+        if let Some(max_age) = policy.max_age_of_failed {
+            report.failed = tx
+                .execute(
+                    &format!(
+                        "DELETE FROM history \
+                         WHERE exit_status != 0 AND start_timestamp < :cutoff AND {NOT_PINNED}"
+                    ),
+                    named_params! {
+                        ":cutoff": (now - max_age).timestamp_millis(),
+                        ":pinned": HistoryItem::PINNED_TAG,
+                    },
+                )
+                .map_err(map_sqlite_err)?;
+        }
+        if let Some(max_age) = policy.max_age {
+            report.expired = tx
+                .execute(
+                    &format!(
+                        "DELETE FROM history WHERE start_timestamp < :cutoff AND {NOT_PINNED}"
+                    ),
+                    named_params! {
+                        ":cutoff": (now - max_age).timestamp_millis(),
+                        ":pinned": HistoryItem::PINNED_TAG,
+                    },
+                )
+                .map_err(map_sqlite_err)?;
+        }
+        let duplicates = match policy.duplicates {
+            DuplicateRetention::KeepAll => None,
+            DuplicateRetention::DropConsecutive => Some(
+                "SELECT id, command_line FROM (
+                    SELECT id, command_line, lead(command_line) OVER (ORDER BY id) AS next
+                    FROM history
+                ) WHERE command_line = next",
+            ),
+            DuplicateRetention::DropAll => Some(
+                "SELECT id, command_line FROM history
+                WHERE id NOT IN (SELECT max(id) FROM history GROUP BY command_line)",
+            ),
+        };
+        if let Some(duplicates) = duplicates {
+            let mut statement = tx
+                .prepare(&format!(
+                    "SELECT command_line, count(*) FROM ({duplicates}) \
+                     WHERE {NOT_PINNED} GROUP BY command_line"
+                ))
+                .map_err(map_sqlite_err)?;
+            report.merged_duplicates = statement
+                .query_map(
+                    named_params! { ":pinned": HistoryItem::PINNED_TAG },
+                    |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)),
+                )
+                .map_err(map_sqlite_err)?
+                .collect::<rusqlite::Result<_>>()
+                .map_err(map_sqlite_err)?;
+            drop(statement);
+            report.duplicates = tx
+                .execute(
+                    &format!(
+                        "DELETE FROM history \
+                         WHERE id IN (SELECT id FROM ({duplicates})) AND {NOT_PINNED}"
+                    ),
+                    named_params! { ":pinned": HistoryItem::PINNED_TAG },
+                )
+                .map_err(map_sqlite_err)?;
+        }
+        if let Some(max_entries) = policy.max_entries {
+            report.over_limit = tx
+                .execute(
+                    &format!(
+                        "DELETE FROM history WHERE {NOT_PINNED} AND id NOT IN \
+                         (SELECT id FROM history WHERE {NOT_PINNED} \
+                          ORDER BY id DESC LIMIT :max_entries)"
+                    ),
+                    named_params! {
+                        ":max_entries": max_entries as i64,
+                        ":pinned": HistoryItem::PINNED_TAG,
+                    },
+                )
+                .map_err(map_sqlite_err)?;
+        }
+        tx.commit().map_err(map_sqlite_err)?;
+        Ok(report)
+    }
+
+    /// Aggregates in the database, the first tokens from the counts of the distinct command lines
+    fn stats(&self, limit: usize) -> Result<HistoryStats> {
+        let limit = limit.min(i64::MAX as usize) as i64;
+        let local_time = |format: &str| {
+            format!("strftime('{format}', start_timestamp / 1000, 'unixepoch', 'localtime')")
+        };
+        let total: i64 = self
+            .db
+            .query_row("SELECT count(*) FROM history", params![], |r| r.get(0))
+            .map_err(map_sqlite_err)?;
+
+        let top_commands = self.query_stats(
+            "SELECT command_line, count(*), count(exit_status),
+                count(CASE WHEN exit_status != 0 THEN 1 END), avg(duration_ms)
+            FROM history
+            GROUP BY command_line
+            ORDER BY count(*) DESC, command_line
+            LIMIT :limit",
+            named_params! { ":limit": limit },
+            |row| {
+                let average_ms: Option<f64> = row.get(4)?;
+                Ok(CommandStats {
+                    command_line: row.get(0)?,
+                    count: row.get::<_, i64>(1)? as usize,
+                    with_exit_status: row.get::<_, i64>(2)? as usize,
+                    failed: row.get::<_, i64>(3)? as usize,
+                    average_duration: average_ms
+                        .map(|ms| Duration::from_micros((ms * 1000.0).round() as u64)),
+                })
+            },
+        )?;
+
+        let mut first_tokens = HashMap::new();
+        let command_counts = self.query_stats(
+            "SELECT command_line, count(*) FROM history GROUP BY command_line",
+            params![],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)),
+        )?;
+        for (command_line, count) in command_counts {
+            if let Some(token) = first_token(&command_line) {
+                *first_tokens.entry(token.to_string()).or_default() += count;
+            }
+        }
+
+        let commands_per_cwd = self.query_stats(
+            "SELECT cwd, count(*) FROM history
+            WHERE cwd IS NOT NULL
+            GROUP BY cwd
+            ORDER BY count(*) DESC, cwd
+            LIMIT :limit",
+            named_params! { ":limit": limit },
+            |row| Ok((row.get(0)?, row.get::<_, i64>(1)? as usize)),
+        )?;
+
+        let mut activity_per_hour = [0; 24];
+        let hours = self.query_stats(
+            &format!(
+                "SELECT CAST({} AS INTEGER) AS hour, count(*) FROM history
+                WHERE start_timestamp IS NOT NULL
+                GROUP BY hour",
+                local_time("%H")
+            ),
+            params![],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)? as usize)),
+        )?;
+        for (hour, count) in hours {
+            activity_per_hour[hour as usize % 24] = count;
+        }
+
+        let activity_per_day = self
+            .query_stats(
+                &format!(
+                    "SELECT {} AS day, count(*) FROM history
+                    WHERE start_timestamp IS NOT NULL
+                    GROUP BY day
+                    ORDER BY day",
+                    local_time("%Y-%m-%d")
+                ),
+                params![],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)),
+            )?
+            .into_iter()
+            .filter_map(|(day, count)| Some((day.parse::<NaiveDate>().ok()?, count)))
+            .collect();
+
+        Ok(HistoryStats {
+            total: total as usize,
+            top_commands,
+            top_first_tokens: top_counts(first_tokens, limit as usize),
+            commands_per_cwd,
+            activity_per_hour,
+            activity_per_day,
+        })
+    }
 }
 fn map_sqlite_err(err: rusqlite::Error) -> ReedlineError {
     // TODO: better error mapping
@@ -187,6 +519,86 @@ fn map_sqlite_err(err: rusqlite::Error) -> ReedlineError {
     )))
 }
 
+/// `LIKE` pattern matching the ASCII characters of `pattern` as a subsequence (case-insensitive)
+///
+/// `LIKE` only ignores the case of ASCII characters, so other characters are left to
+/// [`fuzzy_match`], which lowercases all of them.
+fn fuzzy_like_pattern(pattern: &str) -> String {
+    let mut like = String::from("%");
+    for c in pattern.chars().filter(char::is_ascii) {
+        if matches!(c, '%' | '_' | '\\') {
+            like.push('\\');
+        }
+        like.push(c);
+        like.push('%');
+    }
+    like
+}
+
+/// `GLOB` pattern matching the characters of `pattern` as a subsequence (case-sensitive)
+fn fuzzy_glob_pattern(pattern: &str) -> String {
+    let mut glob = String::from("*");
+    for c in pattern.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                glob.push('[');
+                glob.push(c);
+                glob.push(']');
+            }
+            _ => glob.push(c),
+        }
+        glob.push('*');
+    }
+    glob
+}
+
+/// `CASE` expression for the frecency weight of an entry, compared to the `:now` parameter
+fn frecency_weight_sql() -> String {
+    let mut sql = "CASE WHEN start_timestamp IS NULL THEN 1".to_string();
+    for (max_age, weight) in FRECENCY_WEIGHTS {
+        sql.push_str(&format!(
+            " WHEN :now - start_timestamp < {max_age} THEN {weight}"
+        ));
+    }
+    sql.push_str(" ELSE 1 END");
+    sql
+}
+
+fn schema_version(db: &Connection) -> Result<usize> {
+    let version: i32 = db
+        .query_row(
+            "SELECT user_version FROM pragma_user_version",
+            params![],
+            |r| r.get(0),
+        )
+        .map_err(map_sqlite_err)?;
+    Ok(version as usize)
+}
+
+/// Brings the schema up to date, one migration per transaction
+fn migrate(db: &mut Connection) -> Result<()> {
+    let db_version = schema_version(db)?;
+    if db_version > MIGRATIONS.len() {
+        return Err(ReedlineError(ReedlineErrorVariants::HistoryDatabaseError(
+            format!("Unknown database version {db_version}"),
+        )));
+    }
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(db_version) {
+        let tx = db
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(map_sqlite_err)?;
+        // re-check inside the transaction in case another session migrated concurrently
+        if schema_version(&tx)? > version {
+            continue;
+        }
+        tx.execute_batch(migration).map_err(map_sqlite_err)?;
+        tx.pragma_update(None, "user_version", (version + 1) as i32)
+            .map_err(map_sqlite_err)?;
+        tx.commit().map_err(map_sqlite_err)?;
+    }
+    Ok(())
+}
+
 type BoxedNamedParams<'a> = Vec<(&'static str, Box<dyn ToSql + 'a>)>;
 
 impl SqliteBackedHistory {
@@ -218,7 +630,7 @@ impl SqliteBackedHistory {
     }
     /// initialize a new database / migrate an existing one
     fn from_connection(
-        db: Connection,
+        mut db: Connection,
         session: Option<HistorySessionId>,
         session_timestamp: Option<chrono::DateTime<Utc>>,
     ) -> Result<Self> {
@@ -233,47 +645,85 @@ impl SqliteBackedHistory {
             .map_err(map_sqlite_err)?;
         db.pragma_update(None, "application_id", SQLITE_APPLICATION_ID)
             .map_err(map_sqlite_err)?;
-        let db_version: i32 = db
-            .query_row(
-                "SELECT user_version FROM pragma_user_version",
-                params![],
-                |r| r.get(0),
-            )
-            .map_err(map_sqlite_err)?;
-        if db_version != 0 {
-            return Err(ReedlineError(ReedlineErrorVariants::HistoryDatabaseError(
-                format!("Unknown database version {db_version}"),
-            )));
-        }
-        db.execute_batch(
-            "
-        create table if not exists history (
-            id integer primary key autoincrement,
-            command_line text not null,
-            start_timestamp integer,
-            session_id integer,
-            hostname text,
-            cwd text,
-            duration_ms integer,
-            exit_status integer,
-            more_info text
-        ) strict;
-        create index if not exists idx_history_time on history(start_timestamp);
-        create index if not exists idx_history_cwd on history(cwd); -- suboptimal for many hosts
-        create index if not exists idx_history_exit_status on history(exit_status);
-        create index if not exists idx_history_cmd on history(command_line);
-        create index if not exists idx_history_cmd on history(session_id);
-        -- todo: better indexes
-        ",
-        )
-        .map_err(map_sqlite_err)?;
+        migrate(&mut db)?;
+        let full_text_search = has_full_text_index(&db)?;
         Ok(SqliteBackedHistory {
             db,
             session,
             session_timestamp,
+            full_text_search,
+            shared: false,
         })
     }
 
+    /// A builder that adds a full text index to the database, if it doesn't have one yet.
+    ///
+    /// The index speeds up [`CommandLineSearch::Substring`] and [`CommandLineSearch::Prefix`]
+    /// queries of at least three characters, which otherwise scan the whole history.
+    /// It costs some disk space and is kept up to date by the database itself, so it stays
+    /// correct even when other sessions write to the same database.
+    ///
+    /// Requires SQLite with the FTS5 extension (>= 3.34 for its trigram tokenizer), which is
+    /// part of the bundled SQLite of the `sqlite` feature. Once added, the database can't be
+    /// written to by an SQLite without FTS5 anymore.
+    pub fn with_full_text_search(mut self) -> Result<Self> {
+        if !self.full_text_search {
+            let tx = self
+                .db
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(map_sqlite_err)?;
+            let exists = has_full_text_index(&tx)?;
+            if !exists {
+                tx.execute_batch(FULL_TEXT_SEARCH_SCHEMA)
+                    .map_err(map_sqlite_err)?;
+            }
+            tx.commit().map_err(map_sqlite_err)?;
+            self.full_text_search = true;
+        }
+        Ok(self)
+    }
+
+    /// A builder that enables the shared mode, similar to zsh's `share_history`
+    ///
+    /// Searches filtered by session then also find the entries other sessions saved since this
+    /// session started, instead of only those saved before. The entries of this session are
+    /// ordered after all others, so that they are still found first when browsing the history.
+    /// This doesn't apply to [`SearchOrder::Frecency`].
+    #[must_use]
+    pub fn with_shared_history(mut self, enable: bool) -> Self {
+        self.shared = enable;
+        self
+    }
+
+    /// Whether searches use the full text index, see [`SqliteBackedHistory::with_full_text_search()`]
+    pub fn has_full_text_search(&self) -> bool {
+        self.full_text_search
+    }
+
+    /// FTS5 phrase query for a substring, if the full text index can answer it.
+    ///
+    /// The trigram tokenizer can't match strings shorter than three characters.
+    fn full_text_query(&self, substring: &str) -> Option<String> {
+        (self.full_text_search && substring.chars().count() >= 3)
+            .then(|| format!("\"{}\"", substring.replace('"', "\"\"")))
+    }
+
+    /// Runs a query of the statistics, mapping every row
+    fn query_stats<T>(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+        map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut statement = self.db.prepare(sql).map_err(map_sqlite_err)?;
+        let rows = statement
+            .query_map(params, map)
+            .map_err(map_sqlite_err)?
+            .collect::<rusqlite::Result<Vec<T>>>()
+            .map_err(map_sqlite_err)?;
+        Ok(rows)
+    }
+
     fn construct_query<'a>(
         &self,
         query: &'a SearchQuery,
@@ -286,44 +736,68 @@ impl SqliteBackedHistory {
         };
         let mut wheres = Vec::new();
         let mut params: BoxedNamedParams = Vec::new();
+        // In the shared mode, rows are ordered by `(session_id IS :session_id, id)`
+        let own_session_last = match query.filter.session {
+            Some(session_id) if self.shared && query.order == SearchOrder::Chronological => {
+                params.push((":session_id", Box::new(session_id)));
+                true
+            }
+            _ => false,
+        };
         if let Some(start) = query.start_time {
             wheres.push(if is_asc {
-                "timestamp_start > :start_time"
+                "start_timestamp > :start_time"
             } else {
-                "timestamp_start < :start_time"
+                "start_timestamp < :start_time"
             });
             params.push((":start_time", Box::new(start.timestamp_millis())));
         }
         if let Some(end) = query.end_time {
             wheres.push(if is_asc {
-                ":end_time >= timestamp_start"
+                ":end_time >= start_timestamp"
             } else {
-                ":end_time <= timestamp_start"
+                ":end_time <= start_timestamp"
             });
             params.push((":end_time", Box::new(end.timestamp_millis())));
         }
         if let Some(start) = query.start_id {
-            wheres.push(if is_asc {
-                "id > :start_id"
-            } else {
-                "id < :start_id"
+            wheres.push(match (is_asc, own_session_last) {
+                (true, false) => "id > :start_id",
+                (false, false) => "id < :start_id",
+                (true, true) => {
+                    "(session_id IS :session_id, id) > \
+                     (SELECT session_id IS :session_id, id FROM history WHERE id = :start_id)"
+                }
+                (false, true) => {
+                    "(session_id IS :session_id, id) < \
+                     (SELECT session_id IS :session_id, id FROM history WHERE id = :start_id)"
+                }
             });
             params.push((":start_id", Box::new(start.0)));
         }
         if let Some(end) = query.end_id {
-            wheres.push(if is_asc {
-                ":end_id >= id"
-            } else {
-                ":end_id <= id"
+            wheres.push(match (is_asc, own_session_last) {
+                (true, false) => ":end_id >= id",
+                (false, false) => ":end_id <= id",
+                (true, true) => {
+                    "(session_id IS :session_id, id) <= \
+                     (SELECT session_id IS :session_id, id FROM history WHERE id = :end_id)"
+                }
+                (false, true) => {
+                    "(session_id IS :session_id, id) >= \
+                     (SELECT session_id IS :session_id, id FROM history WHERE id = :end_id)"
+                }
             });
             params.push((":end_id", Box::new(end.0)));
         }
+        // fuzzy results are ranked after the query, so the limit is applied there
+        let is_fuzzy = matches!(query.filter.command_line, Some(CommandLineSearch::Fuzzy(_)));
         let limit = match query.limit {
-            Some(l) => {
+            Some(l) if !is_fuzzy => {
                 params.push((":limit", Box::new(l)));
                 "limit :limit"
             }
-            None => "",
+            _ => "",
         };
         if let Some(command_line) = &query.filter.command_line {
             match command_line {
@@ -332,12 +806,32 @@ impl SqliteBackedHistory {
                     params.push((":command_line", Box::new(e)));
                 }
                 CommandLineSearch::Prefix(prefix) => {
+                    if let Some(fts_query) = self.full_text_query(prefix) {
+                        wheres.push("id in (select rowid from history_fts where history_fts match :fts_query)");
+                        params.push((":fts_query", Box::new(fts_query)));
+                    }
                     wheres.push("instr(command_line, :command_line) == 1");
                     params.push((":command_line", Box::new(prefix)));
                 }
                 CommandLineSearch::Substring(cont) => {
-                    wheres.push("instr(command_line, :command_line) >= 1");
-                    params.push((":command_line", Box::new(cont)));
+                    if let Some(fts_query) = self.full_text_query(cont) {
+                        // the trigram phrase query already is an exact, case-sensitive substring match
+                        wheres.push("id in (select rowid from history_fts where history_fts match :fts_query)");
+                        params.push((":fts_query", Box::new(fts_query)));
+                    } else {
+                        wheres.push("instr(command_line, :command_line) >= 1");
+                        params.push((":command_line", Box::new(cont)));
+                    }
+                }
+                CommandLineSearch::Fuzzy(pattern) => {
+                    // prefilter for the subsequence, the ranking happens in `search`
+                    if is_case_sensitive(pattern) {
+                        wheres.push("command_line glob :command_line");
+                        params.push((":command_line", Box::new(fuzzy_glob_pattern(pattern))));
+                    } else {
+                        wheres.push("command_line like :command_line escape '\\'");
+                        params.push((":command_line", Box::new(fuzzy_like_pattern(pattern))));
+                    }
                 }
             };
         }
@@ -366,8 +860,57 @@ impl SqliteBackedHistory {
                 wheres.push("exit_status != 0");
             }
         }
-        if let (Some(session_id), Some(session_timestamp)) =
-            (query.filter.session, self.session_timestamp)
+        if let Some(min_duration) = query.filter.min_duration {
+            wheres.push("duration_ms >= :min_duration");
+            params.push((":min_duration", Box::new(min_duration.as_millis() as i64)));
+        }
+        if let Some(max_duration) = query.filter.max_duration {
+            wheres.push("duration_ms <= :max_duration");
+            params.push((":max_duration", Box::new(max_duration.as_millis() as i64)));
+        }
+        if !query.filter.tags.is_empty() {
+            let tags: BTreeSet<&String> = query.filter.tags.iter().collect();
+            wheres.push(
+                "(SELECT count(*) FROM history_tags \
+                  WHERE history_id = history.id AND tag IN (SELECT value FROM json_each(:tags))) \
+                 = json_array_length(:tags)",
+            );
+            params.push((
+                ":tags",
+                Box::new(serde_json::to_string(&tags).expect("tags serialize to JSON")),
+            ));
+        }
+        if !query.filter.more_info.is_empty() {
+            // `[path, kind, value]` of every filter, none of which may fail
+            wheres.push(
+                "NOT EXISTS (SELECT 1 FROM json_each(:more_info) AS f \
+                  WHERE json_type(more_info, json_extract(f.value, '$[0]')) IS NOT 'text' \
+                  OR CASE json_extract(f.value, '$[1]') \
+                      WHEN 'equals' THEN json_extract(more_info, json_extract(f.value, '$[0]')) \
+                                         != json_extract(f.value, '$[2]') \
+                      ELSE instr(json_extract(more_info, json_extract(f.value, '$[0]')), \
+                                 json_extract(f.value, '$[2]')) != 1 \
+                  END)",
+            );
+            let filters: Vec<_> = query
+                .filter
+                .more_info
+                .iter()
+                .map(|filter| {
+                    let (kind, value) = match &filter.matches {
+                        MoreInfoMatch::Equals(value) => ("equals", value),
+                        MoreInfoMatch::Prefix(prefix) => ("prefix", prefix),
+                    };
+                    (json_path(&filter.path), kind, value)
+                })
+                .collect();
+            params.push((
+                ":more_info",
+                Box::new(serde_json::to_string(&filters).expect("filters serialize to JSON")),
+            ));
+        }
+        if let (Some(session_id), Some(session_timestamp), false) =
+            (query.filter.session, self.session_timestamp, self.shared)
         {
             // Filter so that we get rows:
             // - that have the same session_id, or
@@ -383,13 +926,547 @@ impl SqliteBackedHistory {
         if wheres.is_empty() {
             wheres = "true".to_string();
         }
-        let query = format!(
-            "SELECT {select_expression} \
-             FROM history \
-             WHERE ({wheres}) \
-             ORDER BY id {asc} \
-             {limit}"
-        );
+        let query = match query.order {
+            SearchOrder::Chronological if own_session_last => format!(
+                "SELECT {select_expression} \
+                 FROM history \
+                 WHERE ({wheres}) \
+                 ORDER BY session_id IS :session_id {asc}, id {asc} \
+                 {limit}"
+            ),
+            SearchOrder::Chronological => format!(
+                "SELECT {select_expression} \
+                 FROM history \
+                 WHERE ({wheres}) \
+                 ORDER BY id {asc} \
+                 {limit}"
+            ),
+            SearchOrder::Duration => format!(
+                "SELECT {select_expression} \
+                 FROM history \
+                 WHERE ({wheres}) \
+                 ORDER BY duration_ms IS NULL, duration_ms DESC, id {asc} \
+                 {limit}"
+            ),
+            SearchOrder::ExitStatus => format!(
+                "SELECT {select_expression} \
+                 FROM history \
+                 WHERE ({wheres}) \
+                 ORDER BY exit_status IS NULL, exit_status = 0, id {asc} \
+                 {limit}"
+            ),
+            SearchOrder::Frecency => {
+                params.push((":now", Box::new(Utc::now().timestamp_millis())));
+                let weight = frecency_weight_sql();
+                format!(
+                    "WITH ranked AS ( \
+                         SELECT max(id) AS id, sum({weight}) AS frecency \
+                         FROM history \
+                         WHERE ({wheres}) \
+                         GROUP BY command_line \
+                     ) \
+                     SELECT {select_expression} \
+                     FROM history JOIN ranked USING (id) \
+                     ORDER BY ranked.frecency DESC, id DESC \
+                     {limit}"
+                )
+            }
+        };
         (query, params)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MoreInfoFilter;
+
+    fn has_index(db: &Connection, name: &str) -> bool {
+        db.query_row(
+            "select count(*) from sqlite_master where type = 'index' and name = ?",
+            params![name],
+            |r| r.get::<_, i64>(0),
+        )
+        .unwrap()
+            == 1
+    }
+
+    #[test]
+    fn new_database_is_fully_migrated() -> Result<()> {
+        let history = SqliteBackedHistory::in_memory()?;
+        assert_eq!(schema_version(&history.db)?, MIGRATIONS.len());
+        assert!(has_index(&history.db, "idx_history_session"));
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_unversioned_database() -> Result<()> {
+        let db = Connection::open_in_memory().map_err(map_sqlite_err)?;
+        // schema as created before migrations were introduced
+        db.execute_batch(
+            "
+            create table history (
+                id integer primary key autoincrement,
+                command_line text not null,
+                start_timestamp integer,
+                session_id integer,
+                hostname text,
+                cwd text,
+                duration_ms integer,
+                exit_status integer,
+                more_info text
+            ) strict;
+            create index idx_history_time on history(start_timestamp);
+            create index idx_history_cwd on history(cwd);
+            create index idx_history_exit_status on history(exit_status);
+            create index idx_history_cmd on history(command_line);
+            insert into history (command_line) values ('ls');
+            ",
+        )
+        .map_err(map_sqlite_err)?;
+
+        let history = SqliteBackedHistory::from_connection(db, None, None)?;
+        assert_eq!(schema_version(&history.db)?, MIGRATIONS.len());
+        assert!(has_index(&history.db, "idx_history_session"));
+        assert_eq!(history.load(HistoryItemId::new(1))?.command_line, "ls");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_newer_database() -> Result<()> {
+        let db = Connection::open_in_memory().map_err(map_sqlite_err)?;
+        db.pragma_update(None, "user_version", MIGRATIONS.len() as i32 + 1)
+            .map_err(map_sqlite_err)?;
+        assert!(SqliteBackedHistory::from_connection(db, None, None).is_err());
+        Ok(())
+    }
+
+    fn substring_matches(history: &SqliteBackedHistory, substring: &str) -> Result<Vec<String>> {
+        let query = SearchQuery::all_that_contain_rev(substring.to_string());
+        Ok(history
+            .search(query)?
+            .into_iter()
+            .map(|item| item.command_line)
+            .collect())
+    }
+
+    #[test]
+    fn full_text_index_matches_plain_search() -> Result<()> {
+        let command_lines = [
+            "git push",
+            "git pull",
+            "echo \"quoted\"",
+            "Cargo build",
+            "ls",
+        ];
+        let mut plain = SqliteBackedHistory::in_memory()?;
+        let mut fts = SqliteBackedHistory::in_memory()?;
+        for command_line in command_lines {
+            plain.save(HistoryItem::from_command_line(command_line))?;
+            fts.save(HistoryItem::from_command_line(command_line))?;
+        }
+        // the index is built for the entries that already exist
+        let fts = fts.with_full_text_search()?;
+        assert!(fts.has_full_text_search());
+        assert!(!plain.has_full_text_search());
+
+        for substring in ["git", "it p", "pu", "\"quoted\"", "cargo", "Cargo", "s"] {
+            assert_eq!(
+                substring_matches(&fts, substring)?,
+                substring_matches(&plain, substring)?,
+                "substring {substring:?}"
+            );
+        }
+
+        let prefix = SearchQuery::last_with_prefix("git p".to_string(), None);
+        let found = fts.search(prefix)?;
+        assert_eq!(found[0].command_line, "git pull");
+        Ok(())
+    }
+
+    #[test]
+    fn full_text_index_follows_changes() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?.with_full_text_search()?;
+        let first = history.save(HistoryItem::from_command_line("cargo test"))?;
+        let second = history.save(HistoryItem::from_command_line("cargo build"))?;
+        assert_eq!(
+            substring_matches(&history, "cargo")?,
+            vec!["cargo build", "cargo test"]
+        );
+
+        history.update(first.id.unwrap(), &|mut item| {
+            item.command_line = "cargo check".to_string();
+            item
+        })?;
+        assert!(substring_matches(&history, "test")?.is_empty());
+        assert_eq!(substring_matches(&history, "check")?, vec!["cargo check"]);
+
+        history.delete(second.id.unwrap())?;
+        assert_eq!(substring_matches(&history, "cargo")?, vec!["cargo check"]);
+        Ok(())
+    }
+
+    #[test]
+    fn full_text_index_is_detected_on_reopen() -> Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let mut history =
+            SqliteBackedHistory::with_file(path.clone(), None, None)?.with_full_text_search()?;
+        history.save(HistoryItem::from_command_line("cargo test"))?;
+        drop(history);
+
+        let reopened = SqliteBackedHistory::with_file(path, None, None)?;
+        assert!(reopened.has_full_text_search());
+        assert_eq!(substring_matches(&reopened, "test")?, vec!["cargo test"]);
+        Ok(())
+    }
+
+    #[test]
+    fn frecency_weighs_recent_entries_more() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        let now = Utc::now();
+        for (command_line, age) in [
+            ("make", chrono::Duration::days(60)),
+            ("make", chrono::Duration::days(45)),
+            ("make", chrono::Duration::days(40)),
+            ("cargo build", chrono::Duration::minutes(5)),
+            ("ls", chrono::Duration::days(2)),
+            ("ls", chrono::Duration::days(3)),
+        ] {
+            history.save(HistoryItem {
+                start_timestamp: Some(now - age),
+                ..HistoryItem::from_command_line(command_line)
+            })?;
+        }
+        let query = SearchQuery::everything(SearchDirection::Backward, None)
+            .with_order(SearchOrder::Frecency);
+        assert_eq!(history.count(query)?, 3);
+
+        let query = SearchQuery::everything(SearchDirection::Backward, None)
+            .with_order(SearchOrder::Frecency);
+        let ranked: Vec<_> = history
+            .search(query)?
+            .into_iter()
+            .map(|item| (item.id.unwrap().0, item.command_line))
+            .collect();
+        assert_eq!(
+            ranked,
+            vec![
+                (4, "cargo build".to_string()),
+                (6, "ls".to_string()),
+                (3, "make".to_string())
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shared_mode_finds_entries_of_other_sessions_after_own() -> Result<()> {
+        use crate::{history::HistoryCursor, HistoryNavigationQuery, LineBuffer};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sqlite3");
+        let open = |session: i64, shared: bool| {
+            SqliteBackedHistory::with_file(
+                path.clone(),
+                Some(HistorySessionId::new(session)),
+                Some(Utc::now()),
+            )
+            .map(|history| history.with_shared_history(shared))
+        };
+        let save = |history: &mut SqliteBackedHistory, command_line: &str| {
+            history.save(HistoryItem {
+                session_id: history.session(),
+                start_timestamp: Some(Utc::now()),
+                ..HistoryItem::from_command_line(command_line)
+            })
+        };
+        let mut history_a = open(1, true)?;
+        let mut history_b = open(2, true)?;
+        let unshared_a = open(1, false)?;
+        save(&mut history_a, "A1")?;
+        save(&mut history_b, "B1")?;
+        save(&mut history_a, "A2")?;
+        save(&mut history_b, "B2")?;
+
+        let browse_back = |history: &SqliteBackedHistory| -> Result<Vec<String>> {
+            let mut cursor = HistoryCursor::new(
+                HistoryNavigationQuery::Normal(LineBuffer::default()),
+                history.session(),
+            );
+            let mut seen = vec![];
+            cursor.back(history)?;
+            while let Some(entry) = cursor.string_at_cursor() {
+                if seen.last() == Some(&entry) {
+                    break;
+                }
+                seen.push(entry);
+                cursor.back(history)?;
+            }
+            Ok(seen)
+        };
+        assert_eq!(browse_back(&history_a)?, vec!["A2", "A1", "B2", "B1"]);
+        assert_eq!(browse_back(&history_b)?, vec!["B2", "B1", "A2", "A1"]);
+        // without the shared mode, entries of sessions started later are hidden
+        assert_eq!(browse_back(&unshared_a)?, vec!["A2", "A1"]);
+        Ok(())
+    }
+
+    #[test]
+    fn retention_matches_the_in_memory_rules() -> Result<()> {
+        use crate::history::retention::plan_retention;
+        use chrono::Duration;
+
+        let entries = [
+            ("make", 40, 2),
+            ("ls", 20, 0),
+            ("cd src", 10, 0),
+            ("make", 9, 1),
+            ("make", 8, 0),
+            ("ls", 3, 0),
+            ("git push", 1, 1),
+        ];
+        for (duplicates, pinned) in [
+            (DuplicateRetention::DropConsecutive, &[][..]),
+            (DuplicateRetention::DropAll, &[][..]),
+            (DuplicateRetention::DropConsecutive, &[0, 3][..]),
+            (DuplicateRetention::DropAll, &[0, 3][..]),
+        ] {
+            let policy = RetentionPolicy::default()
+                .with_max_age_of_failed(Duration::days(5))
+                .with_max_age(Duration::days(30))
+                .with_duplicates(duplicates)
+                .with_max_entries(3);
+            let mut history = SqliteBackedHistory::in_memory()?;
+            for (i, (command_line, days_ago, exit_status)) in entries.into_iter().enumerate() {
+                history.save(HistoryItem {
+                    start_timestamp: Some(Utc::now() - Duration::days(days_ago)),
+                    exit_status: Some(exit_status),
+                    tags: pinned
+                        .contains(&i)
+                        .then(|| HistoryItem::PINNED_TAG.to_string())
+                        .into_iter()
+                        .collect(),
+                    ..HistoryItem::from_command_line(command_line)
+                })?;
+            }
+            let all = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+            let (removed, expected) = plan_retention(&all, &policy, Utc::now());
+
+            assert_eq!(history.apply_retention(&policy)?, expected);
+            let left = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+            assert_eq!(left.len(), all.len() - removed.len());
+            assert!(left.iter().all(|item| !removed.contains(&item.id.unwrap())));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn orders_by_duration_and_exit_status() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        for (command_line, duration_ms, exit_status) in [
+            ("cargo build", Some(40_000), Some(0)),
+            ("cargo test", Some(95_000), Some(101)),
+            ("ls", Some(3), Some(0)),
+            ("git pull", None, None),
+            ("cargo build", Some(40_000), Some(101)),
+            ("cat missing", Some(2), Some(1)),
+        ] {
+            history.save(HistoryItem {
+                duration: duration_ms.map(Duration::from_millis),
+                exit_status,
+                ..HistoryItem::from_command_line(command_line)
+            })?;
+        }
+        let everything = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+
+        let search = |query: SearchQuery| -> Result<Vec<i64>> {
+            let in_memory: Vec<_> = query
+                .run_in_memory(everything.iter(), None)
+                .into_iter()
+                .map(|item| item.id.unwrap().0)
+                .collect();
+            let ids: Vec<_> = history
+                .search(query)?
+                .into_iter()
+                .map(|item| item.id.unwrap().0)
+                .collect();
+            assert_eq!(ids, in_memory);
+            Ok(ids)
+        };
+        let query = |direction| SearchQuery::everything(direction, None);
+        assert_eq!(
+            search(query(SearchDirection::Backward).with_order(SearchOrder::Duration))?,
+            vec![2, 5, 1, 3, 6, 4]
+        );
+        assert_eq!(
+            search(query(SearchDirection::Forward).with_order(SearchOrder::ExitStatus))?,
+            vec![2, 5, 6, 1, 3, 4]
+        );
+
+        let mut slow = query(SearchDirection::Backward).with_order(SearchOrder::Duration);
+        slow.filter.min_duration = Some(Duration::from_secs(30));
+        slow.filter.max_duration = Some(Duration::from_secs(60));
+        assert_eq!(search(slow)?, vec![5, 1]);
+
+        let mut broken = query(SearchDirection::Backward).with_order(SearchOrder::ExitStatus);
+        broken.filter.command_line = Some(CommandLineSearch::Fuzzy("cargo".to_string()));
+        broken.limit = Some(2);
+        assert_eq!(search(broken)?, vec![5, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn filters_by_more_info_fields() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        // more_info written by another application
+        for (command_line, more_info) in [
+            ("git push", r#"{"git":{"branch":"main"}}"#),
+            ("git rebase", r#"{"git":{"branch":"feature/search"}}"#),
+            ("cargo test", r#"{"git":{"branch":7}}"#),
+            ("ls", r#"{"my.key":"x"}"#),
+        ] {
+            let id = history
+                .save(HistoryItem::from_command_line(command_line))?
+                .id
+                .unwrap();
+            history
+                .db
+                .execute(
+                    "UPDATE history SET more_info = ? WHERE id = ?",
+                    params![more_info, id.0],
+                )
+                .map_err(map_sqlite_err)?;
+        }
+        history.save(HistoryItem::from_command_line("exit"))?;
+
+        let matching = |filters: Vec<MoreInfoFilter>| -> Result<Vec<String>> {
+            let mut query = SearchQuery::everything(SearchDirection::Forward, None);
+            query.filter.more_info = filters;
+            Ok(history
+                .search(query)?
+                .into_iter()
+                .map(|item| item.command_line)
+                .collect())
+        };
+        assert_eq!(
+            matching(vec![MoreInfoFilter::equals("git.branch", "main")])?,
+            vec!["git push"]
+        );
+        assert_eq!(
+            matching(vec![MoreInfoFilter::prefix("git.branch", "feature/")])?,
+            vec!["git rebase"]
+        );
+        assert_eq!(
+            matching(vec![MoreInfoFilter::prefix("git.branch", "")])?,
+            vec!["git push", "git rebase"]
+        );
+        assert_eq!(
+            matching(vec![
+                MoreInfoFilter::prefix("git.branch", "ma"),
+                MoreInfoFilter::equals("git.branch", "feature/search"),
+            ])?,
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            matching(vec![MoreInfoFilter {
+                path: vec!["my.key".to_string()],
+                matches: MoreInfoMatch::Equals("x".to_string()),
+            }])?,
+            vec!["ls"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stores_and_filters_tags() -> Result<()> {
+        let mut history = SqliteBackedHistory::in_memory()?;
+        let deploy = history.save(HistoryItem {
+            tags: vec!["deploy".to_string(), HistoryItem::PINNED_TAG.to_string()],
+            ..HistoryItem::from_command_line("kubectl apply -f prod.yaml")
+        })?;
+        history.save(HistoryItem {
+            tags: vec!["deploy".to_string()],
+            ..HistoryItem::from_command_line("kubectl apply -f staging.yaml")
+        })?;
+        history.save(HistoryItem::from_command_line("ls"))?;
+
+        let id = deploy.id.unwrap();
+        assert_eq!(history.load(id)?.tags, deploy.tags);
+        let tagged = |history: &SqliteBackedHistory, tags: &[&str]| -> Result<Vec<String>> {
+            let mut query = SearchQuery::everything(SearchDirection::Forward, None);
+            query.filter.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            Ok(history
+                .search(query)?
+                .into_iter()
+                .map(|item| item.command_line)
+                .collect())
+        };
+        assert_eq!(
+            tagged(&history, &["deploy"])?,
+            vec![
+                "kubectl apply -f prod.yaml",
+                "kubectl apply -f staging.yaml"
+            ]
+        );
+        assert_eq!(
+            tagged(&history, &["deploy", "pinned", "deploy"])?,
+            vec!["kubectl apply -f prod.yaml"]
+        );
+
+        history.update(id, &|mut item| {
+            item.tags.retain(|tag| tag != "deploy");
+            item
+        })?;
+        assert_eq!(tagged(&history, &["deploy"])?.len(), 1);
+        assert!(history.load(id)?.is_pinned());
+        history.delete(id)?;
+        let left: i64 = history
+            .db
+            .query_row("SELECT count(*) FROM history_tags", params![], |r| r.get(0))
+            .map_err(map_sqlite_err)?;
+        assert_eq!(left, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn stats_match_the_in_memory_aggregation() -> Result<()> {
+        use chrono::Duration as TimeDelta;
+
+        let mut history = SqliteBackedHistory::in_memory()?;
+        let entries = [
+            ("cargo build", Some("/src"), 50, Some(1), Some(3000)),
+            ("cargo build", Some("/src"), 49, Some(0), Some(1000)),
+            ("cargo  test --all", Some("/src"), 30, Some(0), None),
+            ("ls", Some("/home"), 26, None, Some(3)),
+            ("ls", None, 2, Some(0), Some(4)),
+            ("git status", Some("/src"), 1, Some(0), Some(20)),
+        ];
+        for (command_line, cwd, hours_ago, exit_status, duration_ms) in entries {
+            history.save(HistoryItem {
+                start_timestamp: Some(Utc::now() - TimeDelta::hours(hours_ago)),
+                cwd: cwd.map(str::to_string),
+                exit_status,
+                duration: duration_ms.map(Duration::from_millis),
+                ..HistoryItem::from_command_line(command_line)
+            })?;
+        }
+        history.save(HistoryItem::from_command_line("ls -l"))?;
+
+        let items = history.search(SearchQuery::everything(SearchDirection::Forward, None))?;
+        for limit in [2, 10] {
+            let stats = history.stats(limit)?;
+            assert_eq!(stats, HistoryStats::from_items(&items, limit));
+        }
+        let stats = history.stats(1)?;
+        assert_eq!(stats.total, 7);
+        assert_eq!(stats.top_first_tokens, vec![("cargo".to_string(), 3)]);
+        assert_eq!(stats.top_commands[0].command_line, "cargo build");
+        assert_eq!(stats.top_commands[0].failure_rate(), Some(0.5));
+        assert_eq!(
+            stats.top_commands[0].average_duration,
+            Some(Duration::from_secs(2))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+use super::HistoryItem;
+use chrono::{Local, NaiveDate, Timelike};
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+/// Aggregated numbers about a single command line, see [`HistoryStats::top_commands`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandStats {
+    /// The command line
+    pub command_line: String,
+    /// How often it was run
+    pub count: usize,
+    /// How often it was run with a recorded exit status
+    pub with_exit_status: usize,
+    /// How often it failed, with a non-zero exit status
+    pub failed: usize,
+    /// Average duration of the runs with a recorded duration
+    pub average_duration: Option<Duration>,
+}
+
+impl CommandStats {
+    /// Share of the runs with a recorded exit status that failed, between 0 and 1
+    pub fn failure_rate(&self) -> Option<f64> {
+        (self.with_exit_status > 0).then(|| self.failed as f64 / self.with_exit_status as f64)
+    }
+}
+
+/// Statistics about the entries of a [`History`](super::History), see [`History::stats()`](super::History::stats)
+///
+/// Rankings are ordered by their count, most frequent first, and alphabetically for equal counts.
+/// Times are in the local time zone, entries without the needed info are left out of the
+/// respective numbers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryStats {
+    /// Number of entries
+    pub total: usize,
+    /// The most frequent command lines
+    pub top_commands: Vec<CommandStats>,
+    /// The most frequent first words of the command lines, usually the program run
+    pub top_first_tokens: Vec<(String, usize)>,
+    /// The directories most commands were run in
+    pub commands_per_cwd: Vec<(String, usize)>,
+    /// Number of commands started in each hour of the day
+    pub activity_per_hour: [usize; 24],
+    /// Number of commands started on each day, in chronological order
+    pub activity_per_day: Vec<(NaiveDate, usize)>,
+}
+
+/// The `limit` keys with the highest counts, ties broken by key
+pub(crate) fn top_counts<K: Ord + Hash>(
+    counts: HashMap<K, usize>,
+    limit: usize,
+) -> Vec<(K, usize)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a_key, a_count), (b_key, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_key.cmp(b_key))
+    });
+    counts.truncate(limit);
+    counts
+}
+
+/// The first whitespace separated word of a command line
+pub(crate) fn first_token(command_line: &str) -> Option<&str> {
+    command_line.split_whitespace().next()
+}
+
+impl HistoryStats {
+    /// Computes the statistics in memory, as done for histories without a more efficient way
+    pub(crate) fn from_items(items: &[HistoryItem], limit: usize) -> Self {
+        #[derive(Default)]
+        struct Runs {
+            count: usize,
+            with_exit_status: usize,
+            failed: usize,
+            durations: Vec<Duration>,
+        }
+
+        let mut runs: HashMap<&str, Runs> = HashMap::new();
+        let mut cwds = HashMap::new();
+        let mut activity_per_hour = [0; 24];
+        let mut days = HashMap::new();
+        for item in items {
+            let command_runs = runs.entry(&item.command_line).or_default();
+            command_runs.count += 1;
+            if let Some(exit_status) = item.exit_status {
+                command_runs.with_exit_status += 1;
+                if exit_status != 0 {
+                    command_runs.failed += 1;
+                }
+            }
+            command_runs.durations.extend(item.duration);
+            if let Some(cwd) = &item.cwd {
+                *cwds.entry(cwd.clone()).or_default() += 1;
+            }
+            if let Some(start) = item.start_timestamp {
+                let start = start.with_timezone(&Local);
+                activity_per_hour[start.hour() as usize] += 1;
+                *days.entry(start.date_naive()).or_default() += 1;
+            }
+        }
+
+        let mut first_tokens = HashMap::new();
+        for (command_line, command_runs) in &runs {
+            if let Some(token) = first_token(command_line) {
+                *first_tokens.entry(token.to_string()).or_default() += command_runs.count;
+            }
+        }
+        let counts = runs.iter().map(|(k, v)| (*k, v.count)).collect();
+        let top_commands = top_counts(counts, limit)
+            .into_iter()
+            .map(|(command_line, _)| {
+                let command_runs = &runs[command_line];
+                let average_duration = (!command_runs.durations.is_empty()).then(|| {
+                    let total: Duration = command_runs.durations.iter().sum();
+                    total / command_runs.durations.len() as u32
+                });
+                CommandStats {
+                    command_line: command_line.to_string(),
+                    count: command_runs.count,
+                    with_exit_status: command_runs.with_exit_status,
+                    failed: command_runs.failed,
+                    average_duration,
+                }
+            })
+            .collect();
+        let mut activity_per_day: Vec<_> = days.into_iter().collect();
+        activity_per_day.sort_unstable();
+
+        HistoryStats {
+            total: items.len(),
+            top_commands,
+            top_first_tokens: top_counts(first_tokens, limit),
+            commands_per_cwd: top_counts(cwds, limit),
+            activity_per_hour,
+            activity_per_day,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileBackedHistory, History, Result};
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    fn item(
+        command_line: &str,
+        cwd: &str,
+        start: Option<(u32, u32)>,
+        exit_status: Option<i64>,
+        duration_ms: Option<u64>,
+    ) -> HistoryItem {
+        HistoryItem {
+            start_timestamp: start.map(|(day, hour)| {
+                Local
+                    .with_ymd_and_hms(2024, 3, day, hour, 15, 0)
+                    .unwrap()
+                    .with_timezone(&Utc)
+            }),
+            cwd: Some(cwd.to_string()),
+            exit_status,
+            duration: duration_ms.map(Duration::from_millis),
+            ..HistoryItem::from_command_line(command_line)
+        }
+    }
+
+    fn example_items() -> Vec<HistoryItem> {
+        vec![
+            item("cargo build", "/src", Some((1, 9)), Some(1), Some(3000)),
+            item("cargo build", "/src", Some((1, 9)), Some(0), Some(1000)),
+            item("cargo test", "/src", Some((1, 10)), Some(0), None),
+            item("ls", "/home", Some((2, 9)), None, None),
+            item("git status", "/src", Some((2, 22)), Some(0), Some(20)),
+            item("ls", "/home", None, Some(0), Some(5)),
+        ]
+    }
+
+    #[test]
+    fn aggregates_items() {
+        let stats = HistoryStats::from_items(&example_items(), 2);
+
+        assert_eq!(stats.total, 6);
+        assert_eq!(
+            stats.top_commands,
+            vec![
+                CommandStats {
+                    command_line: "cargo build".to_string(),
+                    count: 2,
+                    with_exit_status: 2,
+                    failed: 1,
+                    average_duration: Some(Duration::from_millis(2000)),
+                },
+                CommandStats {
+                    command_line: "ls".to_string(),
+                    count: 2,
+                    with_exit_status: 1,
+                    failed: 0,
+                    average_duration: Some(Duration::from_millis(5)),
+                },
+            ]
+        );
+        assert_eq!(stats.top_commands[0].failure_rate(), Some(0.5));
+        assert_eq!(
+            stats.top_first_tokens,
+            vec![("cargo".to_string(), 3), ("ls".to_string(), 2)]
+        );
+        assert_eq!(
+            stats.commands_per_cwd,
+            vec![("/src".to_string(), 4), ("/home".to_string(), 2)]
+        );
+        let mut per_hour = [0; 24];
+        per_hour[9] = 3;
+        per_hour[10] = 1;
+        per_hour[22] = 1;
+        assert_eq!(stats.activity_per_hour, per_hour);
+        assert_eq!(
+            stats.activity_per_day,
+            vec![
+                (NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(), 3),
+                (NaiveDate::from_ymd_opt(2024, 3, 2).unwrap(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_commands_of_plain_histories() -> Result<()> {
+        let mut history = FileBackedHistory::default();
+        for command_line in ["ls -l", "cd", "ls", "ls -l"] {
+            history.save(HistoryItem::from_command_line(command_line))?;
+        }
+        let stats = history.stats(10)?;
+        assert_eq!(stats.total, 4);
+        assert_eq!(stats.top_commands[0].command_line, "ls -l");
+        assert_eq!(stats.top_commands[0].failure_rate(), None);
+        assert_eq!(
+            stats.top_first_tokens,
+            vec![("ls".to_string(), 3), ("cd".to_string(), 1)]
+        );
+        assert_eq!(stats.activity_per_day, vec![]);
+        Ok(())
+    }
+}
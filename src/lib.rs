@@ -187,9 +187,10 @@
 //! ## Crate features
 //!
 //! - `clipboard`: Enable support to use the `SystemClipboard`. Enabling this feature will return a `SystemClipboard` instead of a local clipboard when calling `get_default_clipboard()`.
-//! - `bashisms`: Enable support for special text sequences that recall components from the history. e.g. `!!` and `!$`. For use in shells like `bash` or [`nushell`](https://nushell.sh).
+//! - `bashisms`: Enable support for special text sequences that recall components from the history. e.g. `!!`, `!$`, `!!:s/foo/bar/` and `^old^new^`. For use in shells like `bash` or [`nushell`](https://nushell.sh).
 //! - `sqlite`: Provides the `SqliteBackedHistory` to store richer information in the history. Statically links the required sqlite version.
 //! - `sqlite-dynlib`: Alternative to the feature `sqlite`. Will not statically link. Requires `sqlite >= 3.38` to link dynamically!
+//! - `jsonl`: Provides the `JsonlBackedHistory` to store the same rich information as `SqliteBackedHistory` in a plain JSON lines file, without depending on SQLite.
 //! - `external_printer`: **Experimental:** Thread-safe `ExternalPrinter` handle to print lines from concurrently running threads.
 //!
 //! ## Are we prompt yet? (Development status)
@@ -251,10 +252,15 @@ mod history;
 #[cfg(any(feature = "sqlite", feature = "sqlite-dynlib"))]
 pub use history::SqliteBackedHistory;
 pub use history::{
-    CommandLineSearch, FileBackedHistory, History, HistoryItem, HistoryItemExtraInfo,
-    HistoryItemId, HistoryNavigationQuery, HistorySessionId, IgnoreAllExtraInfo, SearchDirection,
-    SearchFilter, SearchQuery, HISTORY_SIZE,
+    export_history, import_history, BackgroundHistory, CommandLineSearch, CommandStats,
+    DefaultHistoryFilter, DuplicateRetention, FileBackedHistory, History, HistoryFileFormat,
+    HistoryFilter, HistoryItem, HistoryItemExtraInfo, HistoryItemId, HistoryNavigationQuery,
+    HistorySessionId, HistoryStats, IgnoreAllExtraInfo, LayeredHistory, MoreInfoFilter,
+    MoreInfoMatch, NewItemCheck, RetentionPolicy, RetentionReport, SearchDirection, SearchFilter,
+    SearchOrder, SearchQuery, HISTORY_SIZE,
 };
+#[cfg(feature = "jsonl")]
+pub use history::{JsonlBackedHistory, JSONL_HISTORY_SIZE};
 
 mod prompt;
 pub use prompt::{
@@ -274,7 +280,11 @@ mod highlighter;
 pub use highlighter::{AbbrExpandContext, ExampleHighlighter, Highlighter, SimpleMatchHighlighter};
 
 mod completion;
-pub use completion::{Completer, DefaultCompleter, Span, Suggestion};
+pub use completion::{
+    BackgroundCompleter, Completer, CompletionContext, DefaultCompleter, FallbackCompleter,
+    FilePathCompleter, MappedCompleter, MatchingStrategy, MergedCompleter, RoutedCompleter, Span,
+    Suggestion,
+};
 
 mod hinter;
 pub use hinter::CwdAwareHinter;
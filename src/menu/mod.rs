@@ -5,8 +5,11 @@ mod list_menu;
 pub mod menu_functions;
 
 use crate::core_editor::Editor;
-use crate::History;
-use crate::{completion::history::HistoryCompleter, painting::Painter, Completer, Suggestion};
+use crate::{
+    completion::{history::HistoryCompleter, ContextCompleter},
+    painting::Painter,
+    Completer, CompletionContext, Suggestion,
+};
 pub use columnar_menu::ColumnarMenu;
 pub use columnar_menu::TraversalDirection;
 pub use description_menu::DescriptionMenu;
@@ -355,7 +358,17 @@ pub enum ReedlineMenu {
     /// Menu that uses Reedline's completer to update its values
     EngineCompleter(Box<dyn Menu>),
     /// Menu that uses the history as its completer
+    ///
+    /// Lists the entries containing the typed text. Words like `cwd:~/src`, `host:build01`,
+    /// `exit:0`, `session:current`, `tag:deploy`, `after:2d` or `before:2026-01-01` narrow
+    /// the search to the entries run in or below a directory, on a host, succeeding (or failing
+    /// with any other status), of the current session, with a tag, or started after or before
+    /// a date or time span ago (`s`, `m`, `h`, `d` or `w`).
     HistoryMenu(Box<dyn Menu>),
+    /// Menu that uses the history as its completer, listing the pinned entries first
+    ///
+    /// Entries are pinned with the [`HistoryItem::PINNED_TAG`](crate::HistoryItem::PINNED_TAG).
+    PinnedHistoryMenu(Box<dyn Menu>),
     /// Menu that has its own Completer
     WithCompleter {
         /// Base menu
@@ -370,6 +383,7 @@ impl ReedlineMenu {
         match self {
             Self::EngineCompleter(menu)
             | Self::HistoryMenu(menu)
+            | Self::PinnedHistoryMenu(menu)
             | Self::WithCompleter { menu, .. } => menu.as_ref(),
         }
     }
@@ -378,75 +392,77 @@ impl ReedlineMenu {
         match self {
             Self::EngineCompleter(menu)
             | Self::HistoryMenu(menu)
+            | Self::PinnedHistoryMenu(menu)
             | Self::WithCompleter { menu, .. } => menu.as_mut(),
         }
     }
 
-    pub(crate) fn can_partially_complete(
-        &mut self,
-        values_updated: bool,
-        editor: &mut Editor,
-        completer: &mut dyn Completer,
-        history: &dyn History,
-    ) -> bool {
+    /// The completer of the menu, receiving the `context` with the name of the menu
+    fn with_completer<'a, T>(
+        &'a mut self,
+        completer: &'a mut dyn Completer,
+        context: &CompletionContext<'a>,
+        f: impl FnOnce(&mut dyn Menu, &mut dyn Completer) -> T,
+    ) -> T {
+        let pinned_first = matches!(self, Self::PinnedHistoryMenu(_));
+        let name = self.name().to_string();
+        let context = CompletionContext {
+            menu_name: Some(&name),
+            ..context.clone()
+        };
         match self {
-            Self::EngineCompleter(menu) => {
-                menu.can_partially_complete(values_updated, editor, completer)
-            }
-            Self::HistoryMenu(menu) => {
-                let mut history_completer = HistoryCompleter::new(history);
-                menu.can_partially_complete(values_updated, editor, &mut history_completer)
+            Self::EngineCompleter(menu) => f(
+                menu.as_mut(),
+                &mut ContextCompleter::new(completer, context),
+            ),
+            Self::HistoryMenu(menu) | Self::PinnedHistoryMenu(menu) => {
+                let mut history_completer =
+                    HistoryCompleter::new(context.history).with_pinned_first(pinned_first);
+                f(menu.as_mut(), &mut history_completer)
             }
             Self::WithCompleter {
                 menu,
                 completer: own_completer,
-            } => menu.can_partially_complete(values_updated, editor, own_completer.as_mut()),
+            } => f(
+                menu.as_mut(),
+                &mut ContextCompleter::new(own_completer.as_mut(), context),
+            ),
         }
     }
 
+    pub(crate) fn can_partially_complete(
+        &mut self,
+        values_updated: bool,
+        editor: &mut Editor,
+        completer: &mut dyn Completer,
+        context: &CompletionContext,
+    ) -> bool {
+        self.with_completer(completer, context, |menu, completer| {
+            menu.can_partially_complete(values_updated, editor, completer)
+        })
+    }
+
     pub(crate) fn update_values(
         &mut self,
         editor: &mut Editor,
         completer: &mut dyn Completer,
-        history: &dyn History,
+        context: &CompletionContext,
     ) {
-        match self {
-            Self::EngineCompleter(menu) => menu.update_values(editor, completer),
-            Self::HistoryMenu(menu) => {
-                let mut history_completer = HistoryCompleter::new(history);
-                menu.update_values(editor, &mut history_completer);
-            }
-            Self::WithCompleter {
-                menu,
-                completer: own_completer,
-            } => {
-                menu.update_values(editor, own_completer.as_mut());
-            }
-        }
+        self.with_completer(completer, context, |menu, completer| {
+            menu.update_values(editor, completer);
+        });
     }
 
     pub(crate) fn update_working_details(
         &mut self,
         editor: &mut Editor,
         completer: &mut dyn Completer,
-        history: &dyn History,
+        context: &CompletionContext,
         painter: &Painter,
     ) {
-        match self {
-            Self::EngineCompleter(menu) => {
-                menu.update_working_details(editor, completer, painter);
-            }
-            Self::HistoryMenu(menu) => {
-                let mut history_completer = HistoryCompleter::new(history);
-                menu.update_working_details(editor, &mut history_completer, painter);
-            }
-            Self::WithCompleter {
-                menu,
-                completer: own_completer,
-            } => {
-                menu.update_working_details(editor, own_completer.as_mut(), painter);
-            }
-        }
+        self.with_completer(completer, context, |menu, completer| {
+            menu.update_working_details(editor, completer, painter);
+        });
     }
 }
 
@@ -482,7 +498,9 @@ impl Menu for ReedlineMenu {
         completer: &mut dyn Completer,
     ) -> bool {
         match self {
-            Self::EngineCompleter(menu) | Self::HistoryMenu(menu) => {
+            Self::EngineCompleter(menu)
+            | Self::HistoryMenu(menu)
+            | Self::PinnedHistoryMenu(menu) => {
                 menu.can_partially_complete(values_updated, editor, completer)
             }
             Self::WithCompleter {
@@ -494,7 +512,9 @@ impl Menu for ReedlineMenu {
 
     fn update_values(&mut self, editor: &mut Editor, completer: &mut dyn Completer) {
         match self {
-            Self::EngineCompleter(menu) | Self::HistoryMenu(menu) => {
+            Self::EngineCompleter(menu)
+            | Self::HistoryMenu(menu)
+            | Self::PinnedHistoryMenu(menu) => {
                 menu.update_values(editor, completer);
             }
             Self::WithCompleter {
@@ -513,7 +533,9 @@ impl Menu for ReedlineMenu {
         painter: &Painter,
     ) {
         match self {
-            Self::EngineCompleter(menu) | Self::HistoryMenu(menu) => {
+            Self::EngineCompleter(menu)
+            | Self::HistoryMenu(menu)
+            | Self::PinnedHistoryMenu(menu) => {
                 menu.update_working_details(editor, completer, painter);
             }
             Self::WithCompleter {
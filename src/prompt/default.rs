@@ -1,4 +1,7 @@
-use crate::{Prompt, PromptEditMode, PromptHistorySearch, PromptHistorySearchStatus, PromptViMode};
+use crate::{
+    utils::path::home_dir, Prompt, PromptEditMode, PromptHistorySearch, PromptHistorySearchStatus,
+    PromptViMode,
+};
 
 use {
     chrono::Local,
@@ -120,11 +123,7 @@ impl DefaultPrompt {
 
 fn get_working_dir() -> Result<String, std::io::Error> {
     let cwd = env::current_dir()?;
-    // `USERPROFILE` on Windows, `HOME` elsewhere. Avoids `env::home_dir()`,
-    // which is buggy on Windows before 1.85 (above our 1.63 MSRV).
-    let home = env::var_os("USERPROFILE")
-        .or_else(|| env::var_os("HOME"))
-        .map(std::path::PathBuf::from);
+    let home = home_dir();
     Ok(format_working_dir(&cwd, home.as_deref()))
 }
 
@@ -0,0 +1,167 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_CONSECUTIVE: i64 = 12;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_FIRST_GRAPHEME: i64 = 8;
+const PENALTY_GAP: i64 = 1;
+
+/// Result of a successful fuzzy match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FuzzyMatch {
+    /// Higher is better
+    pub score: i64,
+    /// Indices of the graphemes (NOT bytes or chars) of the haystack that matched the pattern
+    pub indices: Vec<usize>,
+}
+
+/// Case sensitivity follows the "smart case" convention: the match is case-insensitive
+/// unless the pattern contains an uppercase character.
+pub(crate) fn is_case_sensitive(pattern: &str) -> bool {
+    pattern.chars().any(char::is_uppercase)
+}
+
+fn is_boundary(previous: Option<&str>) -> bool {
+    match previous {
+        None => true,
+        Some(prev) => prev
+            .chars()
+            .all(|c| c.is_whitespace() || matches!(c, '-' | '_' | '/' | '.' | ':' | '=' | ',')),
+    }
+}
+
+/// Match `pattern` as a subsequence of `haystack` and score the best alignment.
+///
+/// Matches at the start of words and runs of consecutive matches score higher,
+/// gaps between matched graphemes are slightly penalized.
+/// An empty pattern matches everything with a score of zero.
+pub(crate) fn fuzzy_match(pattern: &str, haystack: &str) -> Option<FuzzyMatch> {
+    let case_sensitive = is_case_sensitive(pattern);
+    let normalize = |s: &str| {
+        if case_sensitive {
+            s.to_string()
+        } else {
+            s.to_lowercase()
+        }
+    };
+    let pattern: Vec<String> = pattern.graphemes(true).map(normalize).collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            indices: vec![],
+        });
+    }
+    let raw: Vec<&str> = haystack.graphemes(true).collect();
+    let haystack: Vec<String> = raw.iter().map(|g| normalize(g)).collect();
+
+    // Cheap rejection before scoring all alignments
+    let mut remaining = pattern.iter();
+    let mut wanted = remaining.next();
+    for grapheme in &haystack {
+        if wanted == Some(grapheme) {
+            wanted = remaining.next();
+        }
+    }
+    if wanted.is_some() {
+        return None;
+    }
+
+    let bonus = |j: usize| {
+        let mut bonus = 0;
+        if j == 0 {
+            bonus += BONUS_FIRST_GRAPHEME;
+        }
+        if is_boundary(j.checked_sub(1).map(|p| raw[p])) {
+            bonus += BONUS_BOUNDARY;
+        }
+        bonus
+    };
+
+    // scores[i][j]: best score with pattern[i] matched at haystack[j]
+    let n = haystack.len();
+    let mut scores: Vec<Vec<Option<i64>>> = vec![vec![None; n]; pattern.len()];
+    let mut from: Vec<Vec<usize>> = vec![vec![0; n]; pattern.len()];
+    for (j, grapheme) in haystack.iter().enumerate() {
+        if *grapheme == pattern[0] {
+            scores[0][j] = Some(SCORE_MATCH + bonus(j) - PENALTY_GAP * j as i64);
+        }
+    }
+    for i in 1..pattern.len() {
+        // Running best of `scores[i - 1][k] + k * PENALTY_GAP` for all k < j - 1
+        let mut best_gapped: Option<(i64, usize)> = None;
+        for j in i..n {
+            if j >= 2 {
+                if let Some(prev) = scores[i - 1][j - 2] {
+                    let candidate = prev + (j as i64 - 2) * PENALTY_GAP;
+                    if best_gapped.map_or(true, |(best, _)| candidate > best) {
+                        best_gapped = Some((candidate, j - 2));
+                    }
+                }
+            }
+            if haystack[j] != pattern[i] {
+                continue;
+            }
+            let gapped =
+                best_gapped.map(|(best, k)| (best - (j as i64 - 1) * PENALTY_GAP + bonus(j), k));
+            let consecutive = scores[i - 1][j - 1].map(|prev| (prev + BONUS_CONSECUTIVE, j - 1));
+            let best = match (gapped, consecutive) {
+                (Some(g), Some(c)) => Some(if c.0 >= g.0 { c } else { g }),
+                (g, c) => g.or(c),
+            };
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score + SCORE_MATCH);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let last = pattern.len() - 1;
+    let (mut j, score) = scores[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, s)| s.map(|s| (j, s)))
+        .max_by_key(|(j, s)| (*s, std::cmp::Reverse(*j)))?;
+    let mut indices = vec![0; pattern.len()];
+    for i in (0..pattern.len()).rev() {
+        indices[i] = j;
+        j = from[i][j];
+    }
+    Some(FuzzyMatch { score, indices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("gpfl", "git push --force-with-lease", Some(vec![0, 4, 11, 22]))]
+    #[case("abc", "a b c", Some(vec![0, 2, 4]))]
+    #[case("", "anything", Some(vec![]))]
+    #[case("xyz", "git push", None)]
+    #[case("GP", "git push", None)]
+    #[case("gp", "GIT PUSH", Some(vec![0, 4]))]
+    #[case("😄b", "a😄b", Some(vec![1, 2]))]
+    fn matched_indices(
+        #[case] pattern: &str,
+        #[case] haystack: &str,
+        #[case] expected: Option<Vec<usize>>,
+    ) {
+        assert_eq!(fuzzy_match(pattern, haystack).map(|m| m.indices), expected);
+    }
+
+    #[test]
+    fn consecutive_matches_score_higher() {
+        let consecutive = fuzzy_match("push", "git push").unwrap();
+        let scattered = fuzzy_match("push", "pull --rebase --autostash").unwrap();
+        assert!(consecutive.score > scattered.score);
+    }
+
+    #[test]
+    fn word_starts_score_higher() {
+        let boundary = fuzzy_match("gp", "git push").unwrap();
+        let inner = fuzzy_match("gp", "ggggpppp").unwrap();
+        assert!(boundary.score > inner.score);
+    }
+}
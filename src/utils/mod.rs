@@ -1,3 +1,5 @@
+pub(crate) mod fuzzy;
+pub(crate) mod path;
 mod query;
 pub(crate) mod text_manipulation;
 
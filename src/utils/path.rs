@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+/// The home directory, from `USERPROFILE` on Windows and `HOME` elsewhere
+///
+/// Avoids `env::home_dir()`, which is buggy on Windows before 1.85 (above our 1.63 MSRV).
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("USERPROFILE")
+        .or_else(|| std::env::var_os("HOME"))
+        .map(PathBuf::from)
+}
+
+/// Replaces a leading `~` of the path by the home directory
+pub(crate) fn expand_tilde(path: &str) -> String {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => rest,
+        _ => return path.to_string(),
+    };
+    match home_dir() {
+        Some(home) => format!("{}{rest}", home.display()),
+        None => path.to_string(),
+    }
+}
+
+/// Replaces the `$NAME` and `${NAME}` environment variables in the path,
+/// leaving unset variables as they are
+pub(crate) fn expand_env_vars(path: &str) -> String {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after.find(|c| !is_name_char(c)).unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() && name.chars().all(is_name_char) => {
+                expanded.push_str(&value);
+            }
+            _ => expanded.push_str(&rest[dollar..=dollar + len]),
+        }
+        rest = &rest[dollar + 1 + len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn expands_set_environment_variables() {
+        std::env::set_var("REEDLINE_TEST_EXPAND_DIR", "/srv/data");
+        assert_eq!(
+            expand_env_vars("$REEDLINE_TEST_EXPAND_DIR/logs/${REEDLINE_TEST_EXPAND_DIR}"),
+            "/srv/data/logs//srv/data"
+        );
+        assert_eq!(
+            expand_env_vars("$REEDLINE_TEST_UNSET_DIR/a$/${b"),
+            "$REEDLINE_TEST_UNSET_DIR/a$/${b"
+        );
+    }
+}